@@ -0,0 +1,16 @@
+function (name=grow, xs, n) {
+    if (n < 1) {
+        return xs
+    }
+    return grow(xs.concat(xs), n - 1)
+}
+function (name=probe, n) {
+    if (n < 1) {
+        return 0
+    }
+    ys = xs
+    table = {items: ys, name: "probe"}
+    return len(table.items) + len(ys) + probe(n - 1)
+}
+xs = grow(["item", 1, 2.5, true, [1, 2]], 10)
+result = probe(200)
@@ -0,0 +1,20 @@
+function (name=churn, n) {
+    if (n < 1) {
+        return 0
+    }
+    xs = [n, "node"]
+    xs.push(xs)
+    m = {id: n, items: xs}
+    m.insert("self", m)
+    return len(xs) + len(m) + churn(n - 1)
+}
+function (name=rounds, n) {
+    if (n < 1) {
+        return gc()
+    }
+    total = churn(100)
+    return rounds(n - 1)
+}
+before = rounds(5).objects
+after = rounds(50).objects
+grown = after - before
@@ -0,0 +1,9 @@
+function (name=sum, n) {
+    if (n < 1) {
+        return 0
+    }
+    return n + sum(n - 1)
+}
+xs = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20]
+table = {a: xs, b: xs, c: xs, d: xs, e: xs, f: xs, g: xs, h: xs}
+result = sum(200) + sum(200) + sum(200) + sum(200)
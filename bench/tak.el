@@ -0,0 +1,7 @@
+function (name=tak, x, y, z) {
+    if (y < x) {
+        return tak(tak(x - 1, y, z), tak(y - 1, z, x), tak(z - 1, x, y))
+    }
+    return z
+}
+result = tak(14, 8, 2)
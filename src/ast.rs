@@ -1,44 +1,155 @@
 // src/ast.rs
 
-use crate::lexer::Token;
-use std::collections::HashMap;
+use crate::ordered_map::OrderedMap;
+use crate::resolver::{Binding, Layout};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
 
 #[derive(Debug, Clone)]
 pub enum ASTNode {
-    Program(Vec<ASTNode>),
     Package(String),
     Import(String, Option<String>),
     Msg(String),
-    // リテラルは式としても使うので、ASTNode::Literal と Expr::Literal のどちらかを使います。
-    Literal(Value),
-    // 変数代入：変数名 と 右辺の式（Box<Expr>）
-    Variable(String, Box<Expr>),
+    // 変数代入：変数名、任意の型注釈（x: num = ... の "num"）、右辺の式（Box<Expr>）、
+    // 書き込むスロット（解析した時点では Dynamic で、木構造の評価器が実行する前に resolver::annotate で決める）
+    Variable(String, Option<String>, Box<Expr>, Binding),
     // 二項演算子（文としては使わず、式で扱うことを推奨）
     // BinaryOpは Expr::BinaryOp を使うのでここでは省略
-    // 条件文：条件（ASTNode として）、then 部分、else 部分（どちらも Vec<ASTNode>）
-    If(Box<ASTNode>, Vec<ASTNode>, Vec<ASTNode>),
-    // 関数定義：関数名、引数リスト、関数本体（文のリスト）
-    Function(String, Vec<String>, Vec<ASTNode>),
+    // 条件文：条件式、then 部分、else 部分（どちらも Vec<ASTNode>）
+    If(Box<Expr>, Vec<ASTNode>, Vec<ASTNode>),
+    // 関数定義：関数名 と 定義内容（引数、戻り値の型注釈、本体）。
+    // 定義は実行時に登録して呼び出しのたびに使うので、複製せずに共有する
+    Function(String, Rc<FunctionDef>),
     // 関数呼び出し：関数名、引数リスト（各引数は Expr とする）
     FunctionCall(String, Vec<Expr>),
+    // メソッド呼び出し：対象の式、メソッド名、引数リスト（x.f(args) や file.edit(...)）
+    MethodCall(Box<Expr>, String, Vec<Expr>),
+    // 資源を開くブロック：資源を開く式（file.open(...)）と 本体。本体を抜けると資源を閉じる
+    With(Box<Expr>, Vec<ASTNode>),
+    // 戻り値：return の後の式（値がない場合は Value::None のリテラル）
+    Return(Box<Expr>),
     Exit,
 }
 
+// 関数定義の内容：型注釈は省略可能（省略時は実行時まで型を検査しない）
+#[derive(Debug, Clone)]
+pub struct FunctionDef {
+    // 引数：引数名 と 任意の型注釈
+    pub params: Vec<(String, Option<String>)>,
+    // 戻り値の型注釈（-> num の "num"）
+    pub return_type: Option<String>,
+    pub body: Vec<ASTNode>,
+    // ローカル変数の配置（resolver::annotate で決める。木構造の評価器が呼び出しのフレームに使う）
+    pub locals: Rc<Layout>,
+}
+
+// テキスト・配列・マップの中身は参照カウントで共有する。値の複製（変数の読み出しなど）は
+// 参照カウントを増やすだけで、書き換えるときに共有されていれば複製する（Rc::make_mut）
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Number(i64),
-    Text(String),
+    Float(f64),
+    Text(Rc<str>),
     Boolean(bool),
-    Array(Vec<Value>),
-    Map(HashMap<String, Value>),
+    Array(Rc<Vec<Value>>),
+    // マップ：キーの挿入順を保つ
+    Map(Rc<OrderedMap<String, Value>>),
+    // 関数への参照（関数名）。map や filter などに関数を渡すときに使う
+    Function(Rc<str>),
     None,
 }
 
+impl Value {
+    /// テキストの値
+    pub fn text(text: impl Into<Rc<str>>) -> Value {
+        Value::Text(text.into())
+    }
+
+    /// 配列の値
+    pub fn array(items: Vec<Value>) -> Value {
+        Value::Array(Rc::new(items))
+    }
+
+    /// マップの値
+    pub fn map(map: OrderedMap<String, Value>) -> Value {
+        Value::Map(Rc::new(map))
+    }
+
+    /// 2 つの値が同じ配列・マップの中身を共有しているか
+    pub fn shares(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Array(a), Value::Array(b)) => Rc::ptr_eq(a, b),
+            (Value::Map(a), Value::Map(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    /// 型パターンや type() で使う型名の一覧
+    pub const TYPE_NAMES: [&'static str; 8] =
+        ["num", "float", "text", "bool", "array", "map", "function", "none"];
+
+    /// 値の型名を返す（:num: の "num" など）
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "num",
+            Value::Float(_) => "float",
+            Value::Text(_) => "text",
+            Value::Boolean(_) => "bool",
+            Value::Array(_) => "array",
+            Value::Map(_) => "map",
+            Value::Function(_) => "function",
+            Value::None => "none",
+        }
+    }
+}
+
+/// == と一貫したハッシュ（マップは順序に依存しない。0.0 と -0.0 は同じ値として扱う）
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Value::Number(n) => n.hash(state),
+            Value::Float(x) => (if *x == 0.0 { 0.0f64 } else { *x }).to_bits().hash(state),
+            Value::Text(s) => s.hash(state),
+            Value::Boolean(b) => b.hash(state),
+            Value::Array(items) => items.hash(state),
+            Value::Map(map) => {
+                // 要素ごとのハッシュを足し合わせて、挿入順の違いがハッシュに出ないようにする
+                let combined = map.iter().fold(0u64, |sum, (key, value)| {
+                    let mut entry = DefaultHasher::new();
+                    key.hash(&mut entry);
+                    value.hash(&mut entry);
+                    sum.wrapping_add(entry.finish())
+                });
+                map.len().hash(state);
+                combined.hash(state);
+            }
+            Value::Function(name) => name.hash(state),
+            Value::None => {}
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Expr {
     Literal(Value),
-    Variable(String),
+    // 変数の参照：変数名 と 読むスロット（代入と同じく resolver::annotate で決める）
+    Variable(String, Binding),
     BinaryOp(Box<Expr>, String, Box<Expr>),
-    Input(String),
+    // 単項演算子：演算子（"not" など） と オペランド
+    UnaryOp(String, Box<Expr>),
+    // 入力：プロンプトの式 と 任意の型指定（:num: などの型名）
+    Input(Box<Expr>, Option<String>),
     FunctionCall(String, Vec<Expr>),
+    // メンバーアクセス：対象の式 と メンバー名（マップのキーやモジュールの変数）
+    Member(Box<Expr>, String),
+    // メソッド呼び出し：対象の式、メソッド名、引数リスト
+    MethodCall(Box<Expr>, String, Vec<Expr>),
+    // 型パターン：:num: や :text: など（== / is の右辺で型を検査する）
+    TypePattern(String),
+    // 配列リテラル：[1, 2, 3]
+    Array(Vec<Expr>),
+    // マップリテラル：{name: "elium", version: 1}（キー と 値の式）
+    Map(Vec<(String, Expr)>),
 }
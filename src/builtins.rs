@@ -0,0 +1,162 @@
+// src/builtins.rs
+
+use crate::ast::Value;
+use crate::interpreter::Interpreter;
+use crate::utils::error::{Error, Result};
+use std::collections::HashMap;
+
+/// ネイティブ関数：インタプリタと評価済みの引数を受け取り、値を返す
+pub type NativeFn = fn(&mut Interpreter, Vec<Value>) -> Result<Value>;
+
+/// 値を書き換えるメソッド：インタプリタ、書き換える値（xs.push(x) の xs）、評価済みの引数を受け取る
+pub type MutatorFn = fn(&mut Interpreter, &mut Value, Vec<Value>) -> Result<Value>;
+
+/// 組み込み関数をネイティブ関数表に登録する
+pub fn register(natives: &mut HashMap<String, NativeFn>) {
+    natives.insert("num".into(), builtin_num);
+    natives.insert("float".into(), builtin_float);
+    natives.insert("bool".into(), builtin_bool);
+    natives.insert("text".into(), builtin_text);
+    natives.insert("type".into(), builtin_type);
+    natives.insert("len".into(), builtin_len);
+    natives.insert("str".into(), builtin_str);
+    natives.insert("repr".into(), builtin_repr);
+    natives.insert("gc".into(), builtin_gc);
+}
+
+/// 引数の個数を検査し、1 つだけの引数を取り出す
+fn single_arg(name: &str, mut args: Vec<Value>) -> Result<Value> {
+    if args.len() != 1 {
+        return Err(Error::Runtime(format!(
+            "Function {} expected 1 arguments, but got {}",
+            name,
+            args.len()
+        )));
+    }
+    Ok(args.remove(0))
+}
+
+fn builtin_num(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    to_num(single_arg("num", args)?)
+}
+
+fn builtin_float(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    to_float(single_arg("float", args)?)
+}
+
+fn builtin_bool(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    to_bool(single_arg("bool", args)?)
+}
+
+fn builtin_text(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    Ok(to_text(single_arg("text", args)?))
+}
+
+/// str(x): 表示用のテキスト（テキストはそのまま）。text(x) と同じ
+fn builtin_str(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    Ok(to_text(single_arg("str", args)?))
+}
+
+/// repr(x): リテラルの形のテキスト（テキストは引用符で囲む）
+fn builtin_repr(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    Ok(Value::text(single_arg("repr", args)?.repr()))
+}
+
+/// gc(): 使われなくなった共有名を取り除き、変数から辿れる中身の統計をマップで返す
+/// （objects, arrays, maps, texts, bytes, names）
+fn builtin_gc(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    if !args.is_empty() {
+        return Err(Error::Runtime(format!("Function gc expected 0 arguments, but got {}", args.len())));
+    }
+    interpreter.collect_garbage();
+    Ok(interpreter.heap_stats().to_value())
+}
+
+fn builtin_type(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    Ok(Value::text(single_arg("type", args)?.type_name().to_string()))
+}
+
+fn builtin_len(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    match single_arg("len", args)? {
+        Value::Text(s) => Ok(Value::Number(s.chars().count() as i64)),
+        Value::Array(arr) => Ok(Value::Number(arr.len() as i64)),
+        Value::Map(map) => Ok(Value::Number(map.len() as i64)),
+        other => Err(Error::Type(format!("len expects text, array or map, but got {}", other.type_name()))),
+    }
+}
+
+/// 型名（:num: などの中身）に従って値を変換する
+pub fn convert(value: Value, type_name: &str) -> Result<Value> {
+    match type_name {
+        "num" => to_num(value),
+        "float" => to_float(value),
+        "bool" => to_bool(value),
+        "text" => Ok(to_text(value)),
+        _ => Err(Error::Type(format!("Unknown type: :{}:", type_name))),
+    }
+}
+
+fn conversion_error(value: &Value, type_name: &str) -> Error {
+    match value {
+        Value::Text(s) => Error::Type(format!("Cannot convert {:?} to {}", s, type_name)),
+        other => Error::Type(format!("Cannot convert {} to {}", other, type_name)),
+    }
+}
+
+/// 整数へ変換する（小数は 0 方向へ切り捨て）
+pub fn to_num(value: Value) -> Result<Value> {
+    match value {
+        Value::Number(n) => Ok(Value::Number(n)),
+        Value::Float(f) => {
+            if f.is_finite() && f >= i64::MIN as f64 && f <= i64::MAX as f64 {
+                Ok(Value::Number(f.trunc() as i64))
+            } else {
+                Err(conversion_error(&Value::Float(f), "num"))
+            }
+        }
+        Value::Boolean(b) => Ok(Value::Number(b as i64)),
+        Value::Text(ref s) => s
+            .trim()
+            .parse::<i64>()
+            .map(Value::Number)
+            .map_err(|_| conversion_error(&value, "num")),
+        other => Err(conversion_error(&other, "num")),
+    }
+}
+
+/// 浮動小数点数へ変換する
+pub fn to_float(value: Value) -> Result<Value> {
+    match value {
+        Value::Number(n) => Ok(Value::Float(n as f64)),
+        Value::Float(f) => Ok(Value::Float(f)),
+        Value::Boolean(b) => Ok(Value::Float(if b { 1.0 } else { 0.0 })),
+        Value::Text(ref s) => s
+            .trim()
+            .parse::<f64>()
+            .map(Value::Float)
+            .map_err(|_| conversion_error(&value, "float")),
+        other => Err(conversion_error(&other, "float")),
+    }
+}
+
+/// 真偽値へ変換する（テキストは "true" / "false" のみ受け付ける）
+pub fn to_bool(value: Value) -> Result<Value> {
+    match value {
+        Value::Boolean(b) => Ok(Value::Boolean(b)),
+        Value::Number(n) => Ok(Value::Boolean(n != 0)),
+        Value::Text(ref s) => match s.trim() {
+            "true" => Ok(Value::Boolean(true)),
+            "false" => Ok(Value::Boolean(false)),
+            _ => Err(conversion_error(&value, "bool")),
+        },
+        other => Err(conversion_error(&other, "bool")),
+    }
+}
+
+/// テキストへ変換する（どの値でも変換できる）
+pub fn to_text(value: Value) -> Value {
+    match value {
+        Value::Text(s) => Value::Text(s),
+        other => Value::text(other.to_string()),
+    }
+}
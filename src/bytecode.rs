@@ -0,0 +1,935 @@
+// src/bytecode.rs
+
+use crate::ast::Value;
+use crate::compiler::{BinaryOp, Chunk, FunctionProto, Op, Store};
+use crate::intern::intern;
+use crate::ordered_map::OrderedMap;
+use crate::resolver::Layout;
+use crate::stdlib::{Access, Capabilities};
+use crate::utils::error::{Error, Result};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// コンパイル済みファイルの拡張子
+pub const BYTECODE_EXTENSION: &str = "eliumc";
+
+// ファイルの先頭の識別子と形式のバージョン（命令や値の表し方を変えたらバージョンを上げる）
+const MAGIC: &[u8; 4] = b"ELMC";
+const FORMAT_VERSION: u16 = 3;
+
+// 作成したコンパイラ。形式が同じでも、コンパイラや最適化が生成する命令列を変えたら COMPILER_REVISION を
+// 上げる（キャッシュの照合はソースのハッシュだけなので、古いコンパイラが作ったものを使わないように）
+const COMPILER_VERSION: &str = env!("CARGO_PKG_VERSION");
+const COMPILER_REVISION: u16 = 1;
+
+// 自動キャッシュを置くディレクトリ（スクリプトと同じディレクトリの中に作る）
+const CACHE_DIR: &str = "__eliumcache__";
+
+// 読み込むときの値や関数の入れ子の深さの上限
+const MAX_DEPTH: usize = 512;
+
+/// ソースのハッシュ（FNV-1a）。キャッシュが元のソースから作られたものかの照合に使う
+pub fn source_hash(source: &str) -> u64 {
+    source.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// コンパイルしたプログラムをファイルの形式にする（source_hash は元のソースのハッシュ）
+pub fn encode(program: &FunctionProto, source_hash: u64) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    out.extend_from_slice(&COMPILER_REVISION.to_le_bytes());
+    write_str(&mut out, COMPILER_VERSION);
+    out.extend_from_slice(&source_hash.to_le_bytes());
+    write_function(&mut out, program);
+    out
+}
+
+/// ファイルの形式からプログラムと元のソースのハッシュを読み込む。
+/// 形式やバージョンが違うもの、別のコンパイラが作ったもの、壊れたもの、実行すると VM が破綻する命令列はエラーにする
+pub fn decode(bytes: &[u8]) -> Result<(FunctionProto, u64)> {
+    let mut reader = Reader { bytes, pos: 0 };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(invalid("not an Elium bytecode file"));
+    }
+    let version = reader.u16()?;
+    if version != FORMAT_VERSION {
+        return Err(invalid(&format!(
+            "unsupported format version {} (expected {})",
+            version, FORMAT_VERSION
+        )));
+    }
+    let revision = reader.u16()?;
+    let compiler = reader.string()?;
+    if revision != COMPILER_REVISION || compiler != COMPILER_VERSION {
+        return Err(invalid(&format!(
+            "compiled by eliumw {} (revision {}), recompile with eliumw {} (revision {})",
+            compiler, revision, COMPILER_VERSION, COMPILER_REVISION
+        )));
+    }
+    let hash = reader.u64()?;
+    let program = reader.function(0, 0)?;
+    if reader.pos != bytes.len() {
+        return Err(invalid("unexpected trailing bytes"));
+    }
+    if !program.locals.is_empty() {
+        return Err(invalid("top-level program cannot have local variables"));
+    }
+    Ok((program, hash))
+}
+
+fn invalid(message: &str) -> Error {
+    Error::Runtime(format!("Invalid bytecode: {}", message))
+}
+
+/// スクリプトのキャッシュファイルの場所（<ディレクトリ>/__eliumcache__/<名前>.eliumc）
+pub fn cache_path(script: &Path) -> PathBuf {
+    let dir = script.parent().unwrap_or(Path::new(""));
+    let name = script.file_stem().unwrap_or(script.as_os_str());
+    dir.join(CACHE_DIR).join(name).with_extension(BYTECODE_EXTENSION)
+}
+
+/// キャッシュを使ってよいか。ファイルの読み書きを制限しているときは、
+/// キャッシュの場所の読み込みと書き込みの両方が許可されている場合だけ使う
+pub fn cache_allowed(capabilities: &Capabilities, script: &Path) -> bool {
+    let path = cache_path(script);
+    [Access::Read, Access::Write].into_iter().all(|access| capabilities.allows_path(access, &path))
+}
+
+/// キャッシュがあり、今のソースから作られたものならコンパイル済みのプログラムを返す
+pub fn load_cache(script: &Path, source: &str) -> Option<FunctionProto> {
+    let bytes = std::fs::read(cache_path(script)).ok()?;
+    match decode(&bytes) {
+        Ok((program, hash)) if hash == source_hash(source) => Some(program),
+        _ => None,
+    }
+}
+
+/// コンパイルしたプログラムをキャッシュに書き込む（書き込めなくても実行は続ける）
+pub fn store_cache(script: &Path, source: &str, program: &FunctionProto) {
+    let path = cache_path(script);
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    let _ = std::fs::write(path, encode(program, source_hash(source)));
+}
+
+// ---- 書き出し ----
+
+fn write_u32(out: &mut Vec<u8>, n: u32) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
+
+fn write_len(out: &mut Vec<u8>, len: usize) {
+    write_u32(out, len as u32);
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    write_len(out, s.len());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn write_option_str(out: &mut Vec<u8>, s: Option<&str>) {
+    match s {
+        Some(s) => {
+            out.push(1);
+            write_str(out, s);
+        }
+        None => out.push(0),
+    }
+}
+
+fn write_option_u32(out: &mut Vec<u8>, n: Option<u32>) {
+    match n {
+        Some(n) => {
+            out.push(1);
+            write_u32(out, n);
+        }
+        None => out.push(0),
+    }
+}
+
+fn write_function(out: &mut Vec<u8>, function: &FunctionProto) {
+    write_str(out, &function.name);
+    write_len(out, function.params.len());
+    for (param, type_name) in &function.params {
+        write_str(out, param);
+        write_option_str(out, type_name.as_deref());
+    }
+    write_option_str(out, function.return_type.as_deref());
+    write_len(out, function.locals.len());
+    for local in function.locals.iter() {
+        write_str(out, local);
+    }
+    write_len(out, function.globals.len());
+    for global in &function.globals {
+        write_str(out, global);
+    }
+    let chunk = &function.chunk;
+    write_len(out, chunk.constants.len());
+    for constant in &chunk.constants {
+        write_value(out, constant);
+    }
+    write_len(out, chunk.functions.len());
+    for inner in &chunk.functions {
+        write_function(out, inner);
+    }
+    write_len(out, chunk.code.len());
+    for op in &chunk.code {
+        write_op(out, op);
+    }
+}
+
+fn write_value(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::None => out.push(0),
+        Value::Number(n) => {
+            out.push(1);
+            out.extend_from_slice(&n.to_le_bytes());
+        }
+        Value::Float(x) => {
+            out.push(2);
+            out.extend_from_slice(&x.to_bits().to_le_bytes());
+        }
+        Value::Text(s) => {
+            out.push(3);
+            write_str(out, s);
+        }
+        Value::Boolean(b) => out.push(if *b { 5 } else { 4 }),
+        Value::Array(items) => {
+            out.push(6);
+            write_len(out, items.len());
+            for item in items.iter() {
+                write_value(out, item);
+            }
+        }
+        Value::Map(map) => {
+            out.push(7);
+            write_len(out, map.len());
+            for (key, item) in map.iter() {
+                write_str(out, key);
+                write_value(out, item);
+            }
+        }
+        Value::Function(name) => {
+            out.push(8);
+            write_str(out, name);
+        }
+    }
+}
+
+fn binary_code(op: BinaryOp) -> u8 {
+    match op {
+        BinaryOp::Add => 0,
+        BinaryOp::Sub => 1,
+        BinaryOp::Mul => 2,
+        BinaryOp::Div => 3,
+        BinaryOp::Eq => 4,
+        BinaryOp::Ne => 5,
+        BinaryOp::Is => 6,
+        BinaryOp::Gt => 7,
+        BinaryOp::Lt => 8,
+    }
+}
+
+fn write_op(out: &mut Vec<u8>, op: &Op) {
+    match *op {
+        Op::Constant(index) => {
+            out.push(0);
+            write_u32(out, index);
+        }
+        Op::LoadLocal(slot) => {
+            out.push(1);
+            write_u32(out, slot);
+        }
+        Op::StoreLocal(slot) => {
+            out.push(2);
+            write_u32(out, slot);
+        }
+        Op::LoadName(name) => {
+            out.push(3);
+            write_u32(out, name);
+        }
+        Op::StoreGlobal(slot) => {
+            out.push(4);
+            write_u32(out, slot);
+        }
+        Op::CheckType { type_name, context } => {
+            out.push(5);
+            write_u32(out, type_name);
+            write_u32(out, context);
+        }
+        Op::Binary(op) => {
+            out.push(6);
+            out.push(binary_code(op));
+        }
+        Op::IsType { type_name, negate } => {
+            out.push(7);
+            write_u32(out, type_name);
+            out.push(negate as u8);
+        }
+        Op::Not => out.push(8),
+        Op::ShortCircuit { or, target } => {
+            out.push(9);
+            out.push(or as u8);
+            write_u32(out, target);
+        }
+        Op::TestBool { or } => {
+            out.push(10);
+            out.push(or as u8);
+        }
+        Op::Jump(target) => {
+            out.push(11);
+            write_u32(out, target);
+        }
+        Op::JumpUnlessTrue(target) => {
+            out.push(12);
+            write_u32(out, target);
+        }
+        Op::Pop => out.push(13),
+        Op::Input { type_name } => {
+            out.push(14);
+            write_option_u32(out, type_name);
+        }
+        Op::Call { name, argc } => {
+            out.push(15);
+            write_u32(out, name);
+            write_u32(out, argc);
+        }
+        Op::BeginInvoke { path } => {
+            out.push(16);
+            write_u32(out, path);
+        }
+        Op::InvokePath { method, argc, target } => {
+            out.push(17);
+            write_u32(out, method);
+            write_u32(out, argc);
+            match target {
+                None => out.push(0),
+                Some(Store::Local(slot)) => {
+                    out.push(1);
+                    write_u32(out, slot);
+                }
+                Some(Store::Global(slot)) => {
+                    out.push(2);
+                    write_u32(out, slot);
+                }
+            }
+        }
+        Op::Invoke { method, argc } => {
+            out.push(18);
+            write_u32(out, method);
+            write_u32(out, argc);
+        }
+        Op::LoadPath(path) => {
+            out.push(19);
+            write_u32(out, path);
+        }
+        Op::GetMember(member) => {
+            out.push(20);
+            write_u32(out, member);
+        }
+        Op::MakeArray(count) => {
+            out.push(21);
+            write_u32(out, count);
+        }
+        Op::MakeMap(keys) => {
+            out.push(22);
+            write_u32(out, keys);
+        }
+        Op::Msg(message) => {
+            out.push(23);
+            write_u32(out, message);
+        }
+        Op::Package(name) => {
+            out.push(24);
+            write_u32(out, name);
+        }
+        Op::Import { package, target } => {
+            out.push(25);
+            write_u32(out, package);
+            write_option_u32(out, target);
+        }
+        Op::DefineFunction(index) => {
+            out.push(26);
+            write_u32(out, index);
+        }
+        Op::OpenWith => out.push(27),
+        Op::CloseWith => out.push(28),
+        Op::Return => out.push(29),
+        Op::Exit => out.push(30),
+        Op::LoadGlobal(slot) => {
+            out.push(31);
+            write_u32(out, slot);
+        }
+    }
+}
+
+// ---- 読み込み ----
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(len).filter(|&end| end <= self.bytes.len());
+        let end = end.ok_or_else(|| invalid("unexpected end of file"))?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn bool(&mut self) -> Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            other => Err(invalid(&format!("invalid flag {}", other))),
+        }
+    }
+
+    // 要素の数（各要素は 1 バイト以上なので、残りのバイト数より多ければ壊れている）
+    fn len(&mut self) -> Result<usize> {
+        let len = self.u32()? as usize;
+        if len > self.bytes.len() - self.pos {
+            return Err(invalid("length exceeds file size"));
+        }
+        Ok(len)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.len()?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| invalid("invalid UTF-8 in string"))
+    }
+
+    fn option_string(&mut self) -> Result<Option<String>> {
+        Ok(if self.bool()? { Some(self.string()?) } else { None })
+    }
+
+    fn option_u32(&mut self) -> Result<Option<u32>> {
+        Ok(if self.bool()? { Some(self.u32()?) } else { None })
+    }
+
+    // globals は外側のプログラムのトップレベルの変数の数（入れ子の関数の検証に使う）
+    fn function(&mut self, depth: usize, globals: usize) -> Result<FunctionProto> {
+        if depth > MAX_DEPTH {
+            return Err(invalid("functions nested too deeply"));
+        }
+        let name = self.string()?;
+        let mut params = Vec::with_capacity(self.len()?);
+        for _ in 0..params.capacity() {
+            params.push((self.string()?, self.option_string()?));
+        }
+        let return_type = self.option_string()?;
+        let mut locals = Vec::with_capacity(self.len()?);
+        for _ in 0..locals.capacity() {
+            locals.push(self.string()?);
+        }
+        let mut own_globals = Vec::with_capacity(self.len()?);
+        for _ in 0..own_globals.capacity() {
+            own_globals.push(self.string()?);
+        }
+        if depth > 0 && !own_globals.is_empty() {
+            return Err(invalid("only the top-level program can have global variables"));
+        }
+        let globals = if depth == 0 { own_globals.len() } else { globals };
+        let mut chunk = Chunk::default();
+        for _ in 0..self.len()? {
+            chunk.constants.push(self.value(0)?);
+        }
+        for _ in 0..self.len()? {
+            chunk.functions.push(Rc::new(self.function(depth + 1, globals)?));
+        }
+        for _ in 0..self.len()? {
+            chunk.code.push(self.op()?);
+        }
+        let function = FunctionProto { name, params, return_type, locals: Rc::new(Layout::new(locals)), globals: own_globals, chunk };
+        verify(&function, globals)?;
+        Ok(function)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value> {
+        if depth > MAX_DEPTH {
+            return Err(invalid("constant nested too deeply"));
+        }
+        Ok(match self.u8()? {
+            0 => Value::None,
+            1 => Value::Number(i64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+            2 => Value::Float(f64::from_bits(self.u64()?)),
+            3 => Value::Text(intern(&self.string()?)),
+            4 => Value::Boolean(false),
+            5 => Value::Boolean(true),
+            6 => {
+                let mut items = Vec::with_capacity(self.len()?);
+                for _ in 0..items.capacity() {
+                    items.push(self.value(depth + 1)?);
+                }
+                Value::array(items)
+            }
+            7 => {
+                let len = self.len()?;
+                let mut map = OrderedMap::with_capacity(len);
+                for _ in 0..len {
+                    let key = self.string()?;
+                    map.insert(key, self.value(depth + 1)?);
+                }
+                Value::map(map)
+            }
+            8 => Value::Function(intern(&self.string()?)),
+            other => return Err(invalid(&format!("unknown value tag {}", other))),
+        })
+    }
+
+    fn op(&mut self) -> Result<Op> {
+        Ok(match self.u8()? {
+            0 => Op::Constant(self.u32()?),
+            1 => Op::LoadLocal(self.u32()?),
+            2 => Op::StoreLocal(self.u32()?),
+            3 => Op::LoadName(self.u32()?),
+            4 => Op::StoreGlobal(self.u32()?),
+            5 => Op::CheckType { type_name: self.u32()?, context: self.u32()? },
+            6 => Op::Binary(match self.u8()? {
+                0 => BinaryOp::Add,
+                1 => BinaryOp::Sub,
+                2 => BinaryOp::Mul,
+                3 => BinaryOp::Div,
+                4 => BinaryOp::Eq,
+                5 => BinaryOp::Ne,
+                6 => BinaryOp::Is,
+                7 => BinaryOp::Gt,
+                8 => BinaryOp::Lt,
+                other => return Err(invalid(&format!("unknown binary operator {}", other))),
+            }),
+            7 => Op::IsType { type_name: self.u32()?, negate: self.bool()? },
+            8 => Op::Not,
+            9 => Op::ShortCircuit { or: self.bool()?, target: self.u32()? },
+            10 => Op::TestBool { or: self.bool()? },
+            11 => Op::Jump(self.u32()?),
+            12 => Op::JumpUnlessTrue(self.u32()?),
+            13 => Op::Pop,
+            14 => Op::Input { type_name: self.option_u32()? },
+            15 => Op::Call { name: self.u32()?, argc: self.u32()? },
+            16 => Op::BeginInvoke { path: self.u32()? },
+            17 => {
+                let method = self.u32()?;
+                let argc = self.u32()?;
+                let target = match self.u8()? {
+                    0 => None,
+                    1 => Some(Store::Local(self.u32()?)),
+                    2 => Some(Store::Global(self.u32()?)),
+                    other => return Err(invalid(&format!("unknown store target {}", other))),
+                };
+                Op::InvokePath { method, argc, target }
+            }
+            18 => Op::Invoke { method: self.u32()?, argc: self.u32()? },
+            19 => Op::LoadPath(self.u32()?),
+            20 => Op::GetMember(self.u32()?),
+            21 => Op::MakeArray(self.u32()?),
+            22 => Op::MakeMap(self.u32()?),
+            23 => Op::Msg(self.u32()?),
+            24 => Op::Package(self.u32()?),
+            25 => Op::Import { package: self.u32()?, target: self.option_u32()? },
+            26 => Op::DefineFunction(self.u32()?),
+            27 => Op::OpenWith,
+            28 => Op::CloseWith,
+            29 => Op::Return,
+            30 => Op::Exit,
+            31 => Op::LoadGlobal(self.u32()?),
+            other => return Err(invalid(&format!("unknown opcode {}", other))),
+        })
+    }
+}
+
+// ---- 検証 ----
+
+// 命令を実行する直前の状態：値のスタック、file.open のブロック、BeginInvoke の入れ子の深さ
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Depths {
+    stack: usize,
+    withs: usize,
+    invocations: usize,
+}
+
+// 関数の命令列を検証する。定数・スロット・関数の番号が範囲内で、名前の定数がテキストであり、
+// どの経路でもスタックの深さが一致して末尾を越えて実行しないことを確かめる（globals はトップレベルの変数の数）
+fn verify(function: &FunctionProto, globals: usize) -> Result<()> {
+    let chunk = &function.chunk;
+    let context = |message: String| invalid(&format!("in function {}: {}", function.name, message));
+    if function.params.len() > function.locals.len()
+        || function.params.iter().zip(function.locals.iter()).any(|((param, _), local)| param != local)
+    {
+        return Err(context("parameters do not match local variables".into()));
+    }
+    let constant = |index: u32| {
+        chunk.constants.get(index as usize).ok_or_else(|| context(format!("constant {} out of range", index)))
+    };
+    let name = |index: u32| match constant(index)? {
+        Value::Text(_) => Ok(()),
+        _ => Err(context(format!("constant {} is not a name", index))),
+    };
+    let path = |index: u32, min: usize| match constant(index)? {
+        Value::Array(items) if items.len() >= min && items.iter().all(|item| matches!(item, Value::Text(_))) => {
+            Ok(items.len())
+        }
+        _ => Err(context(format!("constant {} is not a list of names", index))),
+    };
+    let slot = |slot: u32| {
+        if (slot as usize) < function.locals.len() {
+            Ok(())
+        } else {
+            Err(context(format!("local slot {} out of range", slot)))
+        }
+    };
+    let global = |slot: u32| {
+        if (slot as usize) < globals {
+            Ok(())
+        } else {
+            Err(context(format!("global slot {} out of range", slot)))
+        }
+    };
+    let target = |target: u32| {
+        if (target as usize) < chunk.code.len() {
+            Ok(target as usize)
+        } else {
+            Err(context(format!("jump target {} out of range", target)))
+        }
+    };
+
+    let mut states: Vec<Option<Depths>> = vec![None; chunk.code.len()];
+    let mut pending = vec![(0, Depths { stack: 0, withs: 0, invocations: 0 })];
+    if chunk.code.is_empty() {
+        return Err(context("empty code".into()));
+    }
+    while let Some((pc, depths)) = pending.pop() {
+        match states[pc] {
+            Some(seen) if seen == depths => continue,
+            Some(_) => return Err(context(format!("inconsistent stack at instruction {}", pc))),
+            None => states[pc] = Some(depths),
+        }
+        let op = chunk.code[pc];
+        let Depths { stack, withs, invocations } = depths;
+        // (取り出す値の数, 積む値の数)
+        let (pops, pushes) = match op {
+            Op::Constant(index) => {
+                constant(index)?;
+                (0, 1)
+            }
+            Op::LoadLocal(index) => {
+                slot(index)?;
+                (0, 1)
+            }
+            Op::StoreLocal(index) => {
+                slot(index)?;
+                (1, 0)
+            }
+            Op::LoadName(index) => {
+                name(index)?;
+                (0, 1)
+            }
+            Op::LoadGlobal(index) => {
+                global(index)?;
+                (0, 1)
+            }
+            Op::StoreGlobal(index) => {
+                global(index)?;
+                (1, 0)
+            }
+            Op::CheckType { type_name, context } => {
+                name(type_name)?;
+                name(context)?;
+                (1, 1)
+            }
+            Op::Binary(_) => (2, 1),
+            Op::IsType { type_name, .. } => {
+                name(type_name)?;
+                (1, 1)
+            }
+            Op::Not | Op::TestBool { .. } => (1, 1),
+            Op::ShortCircuit { target: to, .. } => {
+                if stack < 1 {
+                    return Err(context(format!("stack underflow at instruction {}", pc)));
+                }
+                pending.push((target(to)?, depths));
+                (1, 0)
+            }
+            Op::Jump(_) => (0, 0),
+            Op::JumpUnlessTrue(_) | Op::Pop => (1, 0),
+            Op::Input { type_name } => {
+                if let Some(type_name) = type_name {
+                    name(type_name)?;
+                }
+                (1, 1)
+            }
+            Op::Call { name: index, argc } => {
+                name(index)?;
+                (argc as usize, 1)
+            }
+            Op::BeginInvoke { path: index } => {
+                path(index, 1)?;
+                (0, 1)
+            }
+            Op::InvokePath { method, argc, target: store } => {
+                name(method)?;
+                match store {
+                    Some(Store::Local(index)) => slot(index)?,
+                    Some(Store::Global(index)) => global(index)?,
+                    None => {}
+                }
+                if invocations == 0 {
+                    return Err(context(format!("InvokePath without BeginInvoke at instruction {}", pc)));
+                }
+                (argc as usize + 1, 1)
+            }
+            Op::Invoke { method, argc } => {
+                name(method)?;
+                (argc as usize + 1, 1)
+            }
+            Op::LoadPath(index) => {
+                path(index, 1)?;
+                (0, 1)
+            }
+            Op::GetMember(index) => {
+                name(index)?;
+                (1, 1)
+            }
+            Op::MakeArray(count) => (count as usize, 1),
+            Op::MakeMap(index) => (path(index, 0)?, 1),
+            Op::Msg(index) | Op::Package(index) => {
+                name(index)?;
+                (0, 0)
+            }
+            Op::Import { package, target } => {
+                name(package)?;
+                if let Some(target) = target {
+                    name(target)?;
+                }
+                (0, 0)
+            }
+            Op::DefineFunction(index) => {
+                if index as usize >= chunk.functions.len() {
+                    return Err(context(format!("function {} out of range", index)));
+                }
+                (0, 0)
+            }
+            Op::OpenWith | Op::CloseWith | Op::Exit => (0, 0),
+            Op::Return => (1, 0),
+        };
+        if stack < pops {
+            return Err(context(format!("stack underflow at instruction {}", pc)));
+        }
+        let mut next = Depths { stack: stack - pops + pushes, withs, invocations };
+        match op {
+            Op::BeginInvoke { .. } => next.invocations += 1,
+            Op::InvokePath { .. } => next.invocations -= 1,
+            Op::OpenWith => next.withs += 1,
+            Op::CloseWith if withs == 0 => {
+                return Err(context(format!("CloseWith without OpenWith at instruction {}", pc)));
+            }
+            Op::CloseWith => next.withs -= 1,
+            _ => {}
+        }
+        // 次に実行しうる命令（return / exit 以外で末尾を越えてはならない）
+        let following = || {
+            if pc + 1 < chunk.code.len() {
+                Ok(pc + 1)
+            } else {
+                Err(context("code runs past the end".into()))
+            }
+        };
+        match op {
+            Op::Return | Op::Exit => {}
+            Op::Jump(to) => pending.push((target(to)?, next)),
+            Op::JumpUnlessTrue(to) => {
+                pending.push((target(to)?, next));
+                pending.push((following()?, next));
+            }
+            _ => pending.push((following()?, next)),
+        }
+    }
+    Ok(())
+}
+
+// ---- 逆アセンブル ----
+
+/// コンパイルしたプログラムを読める形の命令の一覧にする（eliumw disasm）
+pub fn disassemble(program: &FunctionProto) -> String {
+    let mut out = String::new();
+    write_disassembly(&mut out, program, &program.globals);
+    out
+}
+
+// globals はトップレベルの変数の名前（入れ子の関数にも渡す）
+fn write_disassembly(out: &mut String, function: &FunctionProto, globals: &[String]) {
+    let chunk = &function.chunk;
+    let params: Vec<String> = function.params.iter().map(|(param, type_name)| match type_name {
+        Some(type_name) => format!("{}: {}", param, type_name),
+        None => param.clone(),
+    }).collect();
+    let _ = write!(out, "function {}({})", function.name, params.join(", "));
+    if let Some(return_type) = &function.return_type {
+        let _ = write!(out, " -> {}", return_type);
+    }
+    let _ = writeln!(out);
+    if !function.locals.is_empty() {
+        let _ = writeln!(out, "  locals: {}", function.locals.join(", "));
+    }
+    if !function.globals.is_empty() {
+        let _ = writeln!(out, "  globals: {}", function.globals.join(", "));
+    }
+    let constant = |index: u32| chunk.constants.get(index as usize).map_or("?".to_string(), Value::repr);
+    let local = |slot: u32| function.locals.get(slot as usize).cloned().unwrap_or_else(|| "?".to_string());
+    let global = |slot: u32| globals.get(slot as usize).cloned().unwrap_or_else(|| "?".to_string());
+    for (pc, op) in chunk.code.iter().enumerate() {
+        let note = match *op {
+            Op::Constant(index)
+            | Op::LoadName(index)
+            | Op::BeginInvoke { path: index }
+            | Op::LoadPath(index)
+            | Op::GetMember(index)
+            | Op::MakeMap(index)
+            | Op::Msg(index)
+            | Op::Package(index)
+            | Op::IsType { type_name: index, .. }
+            | Op::Call { name: index, .. }
+            | Op::Invoke { method: index, .. }
+            | Op::Import { package: index, .. } => constant(index),
+            Op::InvokePath { method, target, .. } => match target {
+                Some(Store::Local(slot)) => format!("{} -> {}", constant(method), local(slot)),
+                Some(Store::Global(slot)) => format!("{} -> {}", constant(method), global(slot)),
+                None => constant(method),
+            },
+            Op::CheckType { type_name, context } => format!("{} ({})", constant(type_name), constant(context)),
+            Op::LoadLocal(slot) | Op::StoreLocal(slot) => local(slot),
+            Op::LoadGlobal(slot) | Op::StoreGlobal(slot) => global(slot),
+            Op::DefineFunction(index) => {
+                chunk.functions.get(index as usize).map_or("?".to_string(), |f| f.name.clone())
+            }
+            _ => String::new(),
+        };
+        let op = format!("{:?}", op);
+        if note.is_empty() {
+            let _ = writeln!(out, "  {:04}  {}", pc, op);
+        } else {
+            let _ = writeln!(out, "  {:04}  {:<48} ; {}", pc, op, note);
+        }
+    }
+    for inner in &chunk.functions {
+        let _ = writeln!(out);
+        write_disassembly(out, inner, globals);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::Limits;
+    use crate::{compiler, optimizer, parser, resolver};
+
+    const SOURCE: &str = r#"import math
+function (name=fact, n: num) -> num {
+    if (n < 2) {
+        return 1
+    }
+    return n * fact(n - 1)
+}
+function (name=describe, xs) {
+    m = {count: len(xs), first: xs.get(0)}
+    m.insert("sqrt", math.sqrt(2.0))
+    return m
+}
+x = fact(10)
+xs = [1, "two", 3.5, true, []]
+xs.push(fact)
+d = describe(xs)
+if (x > 100 and not false) {
+    msg "big"
+}
+"#;
+
+    fn compile(source: &str) -> FunctionProto {
+        let stmts = parser::parse_source(source).unwrap();
+        resolver::check(&stmts).unwrap();
+        compiler::compile(&optimizer::optimize(stmts, false)).unwrap()
+    }
+
+    fn header_len() -> usize {
+        MAGIC.len() + 2 + 2 + 4 + COMPILER_VERSION.len() + 8
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        let program = compile(SOURCE);
+        let bytes = encode(&program, source_hash(SOURCE));
+        let (decoded, hash) = decode(&bytes).unwrap();
+        assert_eq!(hash, source_hash(SOURCE));
+        assert_eq!(disassemble(&decoded), disassemble(&program));
+        // 書き出し直すと同じバイト列になる
+        assert_eq!(encode(&decoded, hash), bytes);
+    }
+
+    #[test]
+    fn other_compilers_are_rejected() {
+        let bytes = encode(&compile(SOURCE), source_hash(SOURCE));
+        let rejects = |patch: &dyn Fn(&mut Vec<u8>), expected: &str| {
+            let mut patched = bytes.clone();
+            patch(&mut patched);
+            match decode(&patched) {
+                Err(Error::Runtime(message)) => assert!(message.contains(expected), "{}", message),
+                other => panic!("expected an error containing {:?}, got {:?}", expected, other.map(|_| ())),
+            }
+        };
+        rejects(&|bytes| bytes[0] = b'X', "not an Elium bytecode file");
+        rejects(&|bytes| bytes[4] = bytes[4].wrapping_add(1), "unsupported format version");
+        rejects(&|bytes| bytes[6] = bytes[6].wrapping_add(1), "recompile");
+        // 同じ長さの別のバージョン
+        rejects(&|bytes| bytes[12] = if bytes[12] == b'9' { b'8' } else { b'9' }, "recompile");
+    }
+
+    #[test]
+    fn corrupted_input_is_rejected() {
+        let bytes = encode(&compile(SOURCE), source_hash(SOURCE));
+        for len in 0..bytes.len() {
+            assert!(decode(&bytes[..len]).is_err(), "truncated to {} bytes", len);
+        }
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(decode(&trailing).is_err());
+        // 命令列のどのバイトが壊れても、パニックせずにエラーか検証を通ったプログラムを返す。
+        // 読み込めたものは実行しても VM が破綻しない
+        let mut accepted = 0;
+        for i in header_len()..bytes.len() {
+            for flip in [0x01, 0x80, 0xff] {
+                let mut corrupted = bytes.clone();
+                corrupted[i] ^= flip;
+                if let Ok((program, _)) = decode(&corrupted) {
+                    accepted += 1;
+                    let mut interpreter = crate::interpreter::Interpreter::new();
+                    interpreter.set_limits(Limits { steps: Some(10_000), ..Limits::default() });
+                    let _ = interpreter.run_program(&program);
+                }
+            }
+        }
+        assert!(accepted < (bytes.len() - header_len()) * 3, "no corruption was detected");
+    }
+}
@@ -0,0 +1,447 @@
+// src/compiler.rs
+
+use crate::ast::{ASTNode, Expr, FunctionDef, Value};
+use crate::intern::intern;
+use crate::interpreter::expr_path;
+use crate::resolver::{function_locals, Binding, Layout, Resolver};
+use crate::utils::error::{Error, Result};
+use std::rc::Rc;
+
+/// 二項演算子（and / or と型パターンとの比較は専用の命令にする）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Eq,
+    Ne,
+    Is,
+    Gt,
+    Lt,
+}
+
+impl BinaryOp {
+    pub fn parse(op: &str) -> Option<BinaryOp> {
+        match op {
+            "+" => Some(BinaryOp::Add),
+            "-" => Some(BinaryOp::Sub),
+            "*" => Some(BinaryOp::Mul),
+            "/" => Some(BinaryOp::Div),
+            "==" => Some(BinaryOp::Eq),
+            "!=" => Some(BinaryOp::Ne),
+            "is" => Some(BinaryOp::Is),
+            ">" => Some(BinaryOp::Gt),
+            "<" => Some(BinaryOp::Lt),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Is => "is",
+            BinaryOp::Gt => ">",
+            BinaryOp::Lt => "<",
+        }
+    }
+}
+
+/// 値を書き戻す先（関数のローカル変数のスロット、またはトップレベルの変数のスロット）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Store {
+    Local(u32),
+    Global(u32),
+}
+
+/// バイトコードの命令。名前やリテラルは定数表の位置、ジャンプ先は命令の位置で表す
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    // 定数を積む
+    Constant(u32),
+    // ローカル変数のスロットを積む（まだ代入されていなければ名前で探す）
+    LoadLocal(u32),
+    StoreLocal(u32),
+    // トップレベルの変数のスロットを積む／代入する
+    LoadGlobal(u32),
+    StoreGlobal(u32),
+    // 名前（定数）の変数を積む（呼び出し元のローカル変数でありうる名前は実行時に探す）
+    LoadName(u32),
+    // スタックの先頭の値が型注釈に合うかを検査する（context は "variable x" などのエラー表示）
+    CheckType { type_name: u32, context: u32 },
+    Binary(BinaryOp),
+    // 値が型パターン :type_name: に合うか（negate なら != の結果）
+    IsType { type_name: u32, negate: bool },
+    Not,
+    // and / or の左辺：結果が決まれば真偽値を積んで target へ飛ぶ
+    ShortCircuit { or: bool, target: u32 },
+    // and / or の右辺が真偽値であることを検査する
+    TestBool { or: bool },
+    Jump(u32),
+    // 先頭の値が true でなければ飛ぶ
+    JumpUnlessTrue(u32),
+    Pop,
+    // input(prompt) / input(prompt, :type:)
+    Input { type_name: Option<u32> },
+    Call { name: u32, argc: u32 },
+    // a.b.f(args) の a.b（名前の配列の定数）を解決する。名前空間でなければ値を、名前空間なら none を積む
+    BeginInvoke { path: u32 },
+    // BeginInvoke で解決した対象のメソッドを呼ぶ。書き換えた値は target に書き戻す
+    InvokePath { method: u32, argc: u32, target: Option<Store> },
+    // 式の値のメソッドを呼ぶ（受け取る値は引数の下に積まれている）
+    Invoke { method: u32, argc: u32 },
+    // a.b.c（名前の配列の定数）の値を積む
+    LoadPath(u32),
+    // 先頭のマップのメンバー
+    GetMember(u32),
+    MakeArray(u32),
+    // キー（テキストの配列の定数）と積まれた値からマップを作る
+    MakeMap(u32),
+    Msg(u32),
+    Package(u32),
+    Import { package: u32, target: Option<u32> },
+    // Chunk::functions の関数を定義する
+    DefineFunction(u32),
+    // file.open のブロックの始まりと終わり（ブロック内で開いたファイルを閉じる）
+    OpenWith,
+    CloseWith,
+    Return,
+    Exit,
+}
+
+/// 命令列と、命令から参照する定数・関数
+#[derive(Debug, Clone, Default)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    pub constants: Vec<Value>,
+    pub functions: Vec<Rc<FunctionProto>>,
+}
+
+/// コンパイル済みの関数（トップレベルのプログラムも名前 "<main>" の関数にする）
+#[derive(Debug, Clone)]
+pub struct FunctionProto {
+    pub name: String,
+    // 引数：引数名 と 任意の型注釈（ローカル変数の先頭のスロットに対応する）
+    pub params: Vec<(String, Option<String>)>,
+    pub return_type: Option<String>,
+    // ローカル変数の名前（スロットの順）
+    pub locals: Rc<Layout>,
+    // トップレベルの変数の名前（スロットの順。"<main>" だけが持ち、入れ子の関数はこれを参照する）
+    pub globals: Vec<String>,
+    pub chunk: Chunk,
+}
+
+/// プログラム（トップレベルの文のリスト）をコンパイルする。変数の参照は Resolver で
+/// ローカル変数・トップレベルの変数のスロットに解決し、決まらない名前だけを名前で扱う
+pub fn compile(stmts: &[ASTNode]) -> Result<FunctionProto> {
+    let resolver = Resolver::new(stmts);
+    let mut compiler = Compiler { chunk: Chunk::default(), locals: None, resolver: &resolver };
+    compiler.block(stmts)?;
+    compiler.finish();
+    Ok(FunctionProto {
+        name: "<main>".to_string(),
+        params: Vec::new(),
+        return_type: None,
+        locals: Rc::default(),
+        globals: resolver.globals().to_vec(),
+        chunk: compiler.chunk,
+    })
+}
+
+// 関数をコンパイルする。引数と本体で代入する変数をローカル変数のスロットにする
+fn compile_function(name: &str, def: &FunctionDef, resolver: &Resolver) -> Result<FunctionProto> {
+    let mut compiler = Compiler { chunk: Chunk::default(), locals: Some(function_locals(def)), resolver };
+    compiler.block(&def.body)?;
+    compiler.finish();
+    Ok(FunctionProto {
+        name: name.to_string(),
+        params: def.params.clone(),
+        return_type: def.return_type.clone(),
+        locals: Rc::new(compiler.locals.unwrap_or_default()),
+        globals: Vec::new(),
+        chunk: compiler.chunk,
+    })
+}
+
+struct Compiler<'a> {
+    chunk: Chunk,
+    // 関数のローカル変数（トップレベルでは None）
+    locals: Option<Layout>,
+    resolver: &'a Resolver,
+}
+
+impl Compiler<'_> {
+    fn emit(&mut self, op: Op) -> usize {
+        self.chunk.code.push(op);
+        self.chunk.code.len() - 1
+    }
+
+    // 次に生成する命令の位置
+    fn here(&self) -> u32 {
+        self.chunk.code.len() as u32
+    }
+
+    // ジャンプ命令の飛び先を現在の位置にする
+    fn patch(&mut self, at: usize) {
+        let target = self.here();
+        match &mut self.chunk.code[at] {
+            Op::Jump(to) | Op::JumpUnlessTrue(to) | Op::ShortCircuit { target: to, .. } => *to = target,
+            other => unreachable!("not a jump: {:?}", other),
+        }
+    }
+
+    // 定数を定数表に加える（同じテキストの定数は共有する）
+    fn constant(&mut self, value: Value) -> u32 {
+        let existing = match &value {
+            Value::Text(text) => self.chunk.constants.iter().position(|c| matches!(c, Value::Text(t) if t == text)),
+            _ => None,
+        };
+        let index = existing.unwrap_or_else(|| {
+            self.chunk.constants.push(value);
+            self.chunk.constants.len() - 1
+        });
+        index as u32
+    }
+
+    fn name(&mut self, name: &str) -> u32 {
+        self.constant(Value::Text(intern(name)))
+    }
+
+    fn path(&mut self, path: &[&str]) -> u32 {
+        self.constant(Value::array(path.iter().map(|name| Value::Text(intern(name))).collect()))
+    }
+
+    fn bind(&self, name: &str) -> Binding {
+        self.resolver.bind(name, self.locals.as_ref())
+    }
+
+    // 変数への書き込み先。関数の中で書き込む変数はローカル変数、トップレベルで書き込む変数は
+    // トップレベルの変数なので、必ずどちらかのスロットに決まる
+    fn store_target(&self, name: &str) -> Store {
+        match self.bind(name) {
+            Binding::Local(slot) => Store::Local(slot),
+            Binding::Global(slot) => Store::Global(slot),
+            Binding::Dynamic => unreachable!("assigned variable {} has no slot", name),
+        }
+    }
+
+    // 本体の末尾：return がなければ none を返す
+    fn finish(&mut self) {
+        let none = self.constant(Value::None);
+        self.emit(Op::Constant(none));
+        self.emit(Op::Return);
+    }
+
+    fn block(&mut self, stmts: &[ASTNode]) -> Result<()> {
+        for stmt in stmts {
+            self.statement(stmt)?;
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, stmt: &ASTNode) -> Result<()> {
+        match stmt {
+            ASTNode::Variable(name, type_name, expr, _) => {
+                self.expression(expr)?;
+                if let Some(type_name) = type_name {
+                    let type_name = self.name(type_name);
+                    let context = self.name(&format!("variable {}", name));
+                    self.emit(Op::CheckType { type_name, context });
+                }
+                match self.store_target(name) {
+                    Store::Local(slot) => self.emit(Op::StoreLocal(slot)),
+                    Store::Global(slot) => self.emit(Op::StoreGlobal(slot)),
+                };
+            }
+            ASTNode::Msg(message) => {
+                let message = self.name(message);
+                self.emit(Op::Msg(message));
+            }
+            ASTNode::Package(name) => {
+                let name = self.name(name);
+                self.emit(Op::Package(name));
+            }
+            ASTNode::Import(package, target) => {
+                let package = self.name(package);
+                let target = target.as_deref().map(|target| self.name(target));
+                self.emit(Op::Import { package, target });
+            }
+            ASTNode::If(condition, then_body, else_body) => {
+                self.expression(condition)?;
+                let to_else = self.emit(Op::JumpUnlessTrue(0));
+                self.block(then_body)?;
+                let to_end = self.emit(Op::Jump(0));
+                self.patch(to_else);
+                self.block(else_body)?;
+                self.patch(to_end);
+            }
+            ASTNode::Function(name, def) => {
+                let function = compile_function(name, def, self.resolver)?;
+                self.chunk.functions.push(Rc::new(function));
+                self.emit(Op::DefineFunction(self.chunk.functions.len() as u32 - 1));
+            }
+            ASTNode::FunctionCall(name, args) => {
+                self.call(name, args)?;
+                self.emit(Op::Pop);
+            }
+            ASTNode::MethodCall(object, method, args) => {
+                self.method_call(object, method, args)?;
+                self.emit(Op::Pop);
+            }
+            ASTNode::With(open, body) => {
+                self.emit(Op::OpenWith);
+                self.expression(open)?;
+                self.emit(Op::Pop);
+                self.block(body)?;
+                self.emit(Op::CloseWith);
+            }
+            ASTNode::Return(expr) => {
+                self.expression(expr)?;
+                self.emit(Op::Return);
+            }
+            ASTNode::Exit => {
+                self.emit(Op::Exit);
+            }
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, name: &str, args: &[Expr]) -> Result<()> {
+        for arg in args {
+            self.expression(arg)?;
+        }
+        let name = self.name(name);
+        self.emit(Op::Call { name, argc: args.len() as u32 });
+        Ok(())
+    }
+
+    fn method_call(&mut self, object: &Expr, method: &str, args: &[Expr]) -> Result<()> {
+        let argc = args.len() as u32;
+        match expr_path(object) {
+            // a.b.f(args)：a.b が名前空間かどうかは実行時に決まる（同じ名前の変数を優先する）
+            Some(path) => {
+                let target = match object {
+                    Expr::Variable(name, _) => Some(self.store_target(name)),
+                    _ => None,
+                };
+                let path = self.path(&path);
+                self.emit(Op::BeginInvoke { path });
+                for arg in args {
+                    self.expression(arg)?;
+                }
+                let method = self.name(method);
+                self.emit(Op::InvokePath { method, argc, target });
+            }
+            None => {
+                self.expression(object)?;
+                for arg in args {
+                    self.expression(arg)?;
+                }
+                let method = self.name(method);
+                self.emit(Op::Invoke { method, argc });
+            }
+        }
+        Ok(())
+    }
+
+    fn expression(&mut self, expr: &Expr) -> Result<()> {
+        match expr {
+            Expr::Literal(value) => {
+                let value = self.constant(value.clone());
+                self.emit(Op::Constant(value));
+            }
+            Expr::Variable(name, _) => {
+                let op = match self.bind(name) {
+                    Binding::Local(slot) => Op::LoadLocal(slot),
+                    Binding::Global(slot) => Op::LoadGlobal(slot),
+                    Binding::Dynamic => Op::LoadName(self.name(name)),
+                };
+                self.emit(op);
+            }
+            Expr::BinaryOp(left, op, right) => self.binary(left, op, right)?,
+            Expr::Input(prompt, type_name) => {
+                self.expression(prompt)?;
+                let type_name = type_name.as_deref().map(|type_name| self.name(type_name));
+                self.emit(Op::Input { type_name });
+            }
+            Expr::UnaryOp(op, operand) => {
+                if op != "not" {
+                    return Err(Error::Runtime(format!("Unknown unary operator: {}", op)));
+                }
+                self.expression(operand)?;
+                self.emit(Op::Not);
+            }
+            Expr::Member(object, member) => match expr_path(object) {
+                Some(mut path) => {
+                    path.push(member);
+                    let path = self.path(&path);
+                    self.emit(Op::LoadPath(path));
+                }
+                None => {
+                    self.expression(object)?;
+                    let member = self.name(member);
+                    self.emit(Op::GetMember(member));
+                }
+            },
+            Expr::MethodCall(object, method, args) => self.method_call(object, method, args)?,
+            Expr::TypePattern(type_name) => {
+                return Err(Error::Runtime(format!(
+                    "Type pattern :{}: can only be used with ==, != or is",
+                    type_name
+                )));
+            }
+            Expr::FunctionCall(name, args) => self.call(name, args)?,
+            Expr::Array(items) => {
+                for item in items {
+                    self.expression(item)?;
+                }
+                self.emit(Op::MakeArray(items.len() as u32));
+            }
+            Expr::Map(entries) => {
+                for (_, value) in entries {
+                    self.expression(value)?;
+                }
+                let keys: Vec<&str> = entries.iter().map(|(key, _)| key.as_str()).collect();
+                let keys = self.path(&keys);
+                self.emit(Op::MakeMap(keys));
+            }
+        }
+        Ok(())
+    }
+
+    fn binary(&mut self, left: &Expr, op: &str, right: &Expr) -> Result<()> {
+        // and / or は短絡評価する（右辺は必要なときだけ評価）
+        if op == "and" || op == "or" {
+            let or = op == "or";
+            self.expression(left)?;
+            let jump = self.emit(Op::ShortCircuit { or, target: 0 });
+            self.expression(right)?;
+            self.emit(Op::TestBool { or });
+            self.patch(jump);
+            return Ok(());
+        }
+        // 型パターンとの比較は値の型を検査する
+        if op == "==" || op == "is" || op == "!=" {
+            match (left, right) {
+                (value, Expr::TypePattern(type_name)) | (Expr::TypePattern(type_name), value) => {
+                    self.expression(value)?;
+                    let type_name = self.name(type_name);
+                    self.emit(Op::IsType { type_name, negate: op == "!=" });
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+        let binary = BinaryOp::parse(op)
+            .ok_or_else(|| Error::Runtime(format!("Unknown binary operator: {}", op)))?;
+        self.expression(left)?;
+        self.expression(right)?;
+        self.emit(Op::Binary(binary));
+        Ok(())
+    }
+}
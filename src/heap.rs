@@ -0,0 +1,156 @@
+// src/heap.rs
+
+use crate::ast::Value;
+use crate::intern;
+use crate::interpreter::Interpreter;
+use crate::ordered_map::OrderedMap;
+use std::collections::HashSet;
+use std::fmt;
+use std::mem::size_of;
+use std::rc::Rc;
+
+// 値の中身（テキスト・配列・マップ）は参照カウントで共有し、書き換えるときに共有されていれば複製する。
+// 書き換えた値が自分自身を含むことはない（xs.push(xs) は書き換える前の xs を複製して加える）ので、
+// 中身の参照は循環せず、どこからも参照されなくなった中身は参照カウントですぐに解放される。
+// そのため追跡型の回収は要らず、ここでは変数から辿れる中身の統計と、共有名の表の掃除だけを行う。
+// 関数の値は名前（Value::Function）だけを持ち、定義した場所の変数を捕まえる仕組み（クロージャ）はないので、
+// 関数を配列やマップに入れても循環しない。読み込んだモジュールのインタプリタは読み込み元の名前空間が持ち、
+// モジュールローダーのキャッシュは弱く参照する（ローダーはモジュールとも共有するので）。
+// 循環しないことは tests/heap.rs で、確保中のバイト数を数えるアロケータを使って確かめる
+
+/// ヒープの統計。変数から辿れる中身を数える（共有している中身は 1 回だけ数える）
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HeapStats {
+    pub arrays: usize,
+    pub maps: usize,
+    pub texts: usize,
+    // 中身のおおよその大きさ（バイト）
+    pub bytes: usize,
+    // 共有している名前（intern）の数
+    pub names: usize,
+}
+
+impl HeapStats {
+    /// 中身の数
+    pub fn objects(&self) -> usize {
+        self.arrays + self.maps + self.texts
+    }
+
+    /// Elium のマップにする（gc() の戻り値）。共有名の数は実行方式によって違うので含めない
+    pub fn to_value(self) -> Value {
+        let mut map = OrderedMap::with_capacity(5);
+        for (key, count) in [
+            ("objects", self.objects()),
+            ("arrays", self.arrays),
+            ("maps", self.maps),
+            ("texts", self.texts),
+            ("bytes", self.bytes),
+        ] {
+            map.insert(key.to_string(), Value::Number(count as i64));
+        }
+        Value::map(map)
+    }
+}
+
+impl fmt::Display for HeapStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} objects ({} arrays, {} maps, {} texts), {} bytes, {} names",
+            self.objects(),
+            self.arrays,
+            self.maps,
+            self.texts,
+            self.bytes,
+            self.names
+        )
+    }
+}
+
+/// roots から辿れる中身を数える（深い入れ子でもスタックを使い切らないように、辿る値を積んで順に調べる）
+pub fn measure<'a>(roots: impl IntoIterator<Item = &'a Value>) -> HeapStats {
+    let mut stats = HeapStats { names: intern::count(), ..HeapStats::default() };
+    let mut seen: HashSet<*const ()> = HashSet::new();
+    let mut pending: Vec<&Value> = roots.into_iter().collect();
+    while let Some(value) = pending.pop() {
+        let Some(pointer) = payload_pointer(value) else {
+            continue;
+        };
+        if !seen.insert(pointer) {
+            continue;
+        }
+        match value {
+            Value::Text(_) => stats.texts += 1,
+            Value::Array(items) => {
+                stats.arrays += 1;
+                pending.extend(items.iter());
+            }
+            Value::Map(map) => {
+                stats.maps += 1;
+                pending.extend(map.iter().map(|(_, item)| item));
+            }
+            _ => {}
+        }
+        stats.bytes += payload_bytes(value);
+    }
+    stats
+}
+
+/// 値の中身の場所（テキスト・配列・マップでなければ None）
+pub fn payload_pointer(value: &Value) -> Option<*const ()> {
+    match value {
+        Value::Text(text) => Some(Rc::as_ptr(text) as *const ()),
+        Value::Array(items) => Some(Rc::as_ptr(items) as *const ()),
+        Value::Map(map) => Some(Rc::as_ptr(map) as *const ()),
+        _ => None,
+    }
+}
+
+/// 値の中身そのもののおおよその大きさ（配列・マップの要素の中身は含めない）
+pub fn payload_bytes(value: &Value) -> usize {
+    match value {
+        Value::Text(text) => text.len(),
+        Value::Array(items) => items.capacity() * size_of::<Value>(),
+        Value::Map(map) => map.iter().map(|(key, _)| size_of::<(String, Value)>() + key.len()).sum(),
+        _ => 0,
+    }
+}
+
+/// 作ったばかりの値の大きさ。ほかと共有していない中身だけを数える
+/// （変数などと共有している中身は、それを作ったときに数えている）
+pub fn fresh_bytes(value: &Value) -> usize {
+    let mut bytes = 0;
+    let mut pending = vec![value];
+    while let Some(value) = pending.pop() {
+        let unique = match value {
+            Value::Text(text) => Rc::strong_count(text) == 1,
+            Value::Array(items) => Rc::strong_count(items) == 1,
+            Value::Map(map) => Rc::strong_count(map) == 1,
+            _ => false,
+        };
+        if !unique {
+            continue;
+        }
+        bytes += payload_bytes(value);
+        match value {
+            Value::Array(items) => pending.extend(items.iter()),
+            Value::Map(map) => pending.extend(map.iter().map(|(_, item)| item)),
+            _ => {}
+        }
+    }
+    bytes
+}
+
+impl Interpreter {
+    /// 変数（トップレベルの変数、実行中の関数のローカル変数）から辿れる中身の統計
+    pub fn heap_stats(&self) -> HeapStats {
+        let locals = self.frames.iter().flat_map(|frame| frame.locals.iter().flatten());
+        measure(self.scope.values().chain(locals))
+    }
+
+    /// 使われなくなった共有名を取り除き、取り除いた数を返す（gc()）。
+    /// 中身は参照カウントで解放済みなので、ほかに回収するものはない
+    pub fn collect_garbage(&mut self) -> usize {
+        intern::collect()
+    }
+}
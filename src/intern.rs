@@ -0,0 +1,58 @@
+// src/intern.rs
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+thread_local! {
+    // 共有している名前（同じ名前は 1 つの Rc<str> を使う）
+    static NAMES: RefCell<HashSet<Rc<str>>> = RefCell::new(HashSet::new());
+}
+
+/// 共有している名前の数
+pub fn count() -> usize {
+    NAMES.with(|names| names.borrow().len())
+}
+
+/// 表のほかに使われていない名前を取り除き、取り除いた数を返す
+pub fn collect() -> usize {
+    NAMES.with(|names| {
+        let mut names = names.borrow_mut();
+        let before = names.len();
+        names.retain(|name| Rc::strong_count(name) > 1);
+        before - names.len()
+    })
+}
+
+/// 名前（変数名・関数名・定数のテキスト）を共有する。同じ名前には同じ Rc<str> を返すので、
+/// 複製は参照カウントを増やすだけで済む
+pub fn intern(name: &str) -> Rc<str> {
+    NAMES.with(|names| {
+        let mut names = names.borrow_mut();
+        if let Some(existing) = names.get(name) {
+            return existing.clone();
+        }
+        let name: Rc<str> = Rc::from(name);
+        names.insert(name.clone());
+        name
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_shared_until_unused() {
+        let first = intern("interned_name");
+        let again = intern(&String::from("interned_name"));
+        assert!(Rc::ptr_eq(&first, &again));
+        assert!(!Rc::ptr_eq(&first, &intern("another_name")));
+        // 使われている名前は残し、表だけが持つ名前は取り除く
+        let before = count();
+        assert_eq!(collect(), 1);
+        assert_eq!(count(), before - 1);
+        drop((first, again));
+        assert_eq!(collect(), 1);
+    }
+}
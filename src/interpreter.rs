@@ -1,159 +1,1023 @@
 // src/interpreter.rs
 
-use crate::ast::{ASTNode, Expr, Value};
+use crate::ast::{ASTNode, Expr, FunctionDef, Value};
+use crate::builtins::{self, MutatorFn, NativeFn};
+use crate::compiler::{self, FunctionProto};
+use crate::intern::intern;
+use crate::lexer;
+use crate::limits::Meter;
+use crate::module::{self, ModuleLoader};
+use crate::ordered_map::OrderedMap;
+use crate::resolver::{self, Binding};
 use crate::scope::Scope;
+use crate::stdlib::{self, Capabilities, Capability};
+use crate::stdlib::file::OpenFile;
+use crate::stdlib::random::Random;
+use crate::typecheck;
 use crate::utils::error::{Error, Result};
+use crate::vm::Frame;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
 
+/// 値の表示（str）。テキストはそのまま、配列やマップは Elium のリテラルの形で表示する
 impl std::fmt::Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Value::Number(n) => write!(f, "{}", n),
             Value::Text(s) => write!(f, "{}", s),
-            Value::Boolean(b) => write!(f, "{}", b),
-            Value::Array(arr) => write!(f, "{:?}", arr),
-            Value::Map(map) => write!(f, "{:?}", map),
-            Value::None => write!(f, "None"),
+            other => write_value(f, other, 0),
         }
     }
 }
 
+impl Value {
+    /// 値をリテラルの形で表した文字列（repr）。テキストも引用符で囲む
+    pub fn repr(&self) -> String {
+        struct Repr<'a>(&'a Value);
+        impl std::fmt::Display for Repr<'_> {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write_value(f, self.0, 0)
+            }
+        }
+        Repr(self).to_string()
+    }
+}
+
+// 表示するネストの深さの上限（これより深い配列・マップは [...] / {...} と省略する）
+const MAX_DISPLAY_DEPTH: usize = 64;
+
+// 値をリテラルの形で書き出す。マップは挿入順に並べる
+fn write_value(f: &mut std::fmt::Formatter<'_>, value: &Value, depth: usize) -> std::fmt::Result {
+    match value {
+        Value::Number(n) => write!(f, "{}", n),
+        Value::Float(x) => write!(f, "{:?}", x),
+        Value::Text(s) => write_quoted(f, s),
+        Value::Boolean(b) => write!(f, "{}", b),
+        Value::Array(_) if depth >= MAX_DISPLAY_DEPTH => write!(f, "[...]"),
+        Value::Array(items) => {
+            write!(f, "[")?;
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write_value(f, item, depth + 1)?;
+            }
+            write!(f, "]")
+        }
+        Value::Map(_) if depth >= MAX_DISPLAY_DEPTH => write!(f, "{{...}}"),
+        Value::Map(map) => {
+            write!(f, "{{")?;
+            for (i, (key, item)) in map.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                // 識別子として書けるキーはそのまま、それ以外は引用符で囲む
+                if is_plain_key(key) {
+                    write!(f, "{}", key)?;
+                } else {
+                    write_quoted(f, key)?;
+                }
+                write!(f, ": ")?;
+                write_value(f, item, depth + 1)?;
+            }
+            write!(f, "}}")
+        }
+        Value::Function(name) => write!(f, "<function {}>", name),
+        Value::None => write!(f, "none"),
+    }
+}
+
+// テキストを引用符で囲み、字句解析器と同じエスケープで書き出す
+fn write_quoted(f: &mut std::fmt::Formatter<'_>, s: &str) -> std::fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\t' => write!(f, "\\t")?,
+            '\r' => write!(f, "\\r")?,
+            '\0' => write!(f, "\\0")?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+// マップリテラルのキーとして引用符なしで書けるか（識別子で、キーワードでない）
+fn is_plain_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+        && lexer::keyword(key).is_none()
+}
+
+/// import した名前空間
+#[derive(Debug, Clone)]
+pub enum Namespace {
+    // ファイルから読み込んだモジュール（モジュールを実行したインタプリタ）
+    Module(Rc<RefCell<Interpreter>>),
+    // 組み込みモジュール（関数は "os.args" のような名前で natives に登録されている）
+    Native(&'static str),
+}
+
+/// 実行方式。Vm はバイトコードにコンパイルして実行し、Tree は AST をそのまま辿る（参照実装）
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Engine {
+    Tree,
+    #[default]
+    Vm,
+}
+
 #[derive(Debug, Clone)]
 pub struct Interpreter {
-    // 変数や関数定義のためのスコープ
+    // トップレベルの変数のスコープ（関数のローカル変数は frames に置く）
     pub scope: Scope,
-    // 関数定義：関数名 → (引数リスト, 関数本体の文リスト)
-    pub functions: HashMap<String, (Vec<String>, Vec<ASTNode>)>,
+    // 関数定義：関数名 → 定義内容（引数、戻り値の型注釈、本体）。構文木の定義を共有する
+    pub functions: HashMap<String, Rc<FunctionDef>>,
+    // コンパイル済みの関数：関数名 → 関数（Vm で実行したときに登録される）
+    pub compiled: HashMap<String, Rc<FunctionProto>>,
+    // 組み込み関数：関数名 → ネイティブ関数
+    pub natives: HashMap<String, NativeFn>,
+    // 値を書き換えるメソッド："array.push" などの名前 → ネイティブ関数
+    pub mutators: HashMap<String, MutatorFn>,
+    // モジュールローダー（読み込んだモジュールとも共有する）
+    pub loader: Rc<RefCell<ModuleLoader>>,
+    // import した名前空間：名前 → モジュール
+    pub namespaces: HashMap<String, Namespace>,
+    // package 宣言で宣言されたパッケージ名
+    pub package: Option<String>,
+    // モジュールとして読み込まれたときに期待されるパッケージ名
+    pub expected_package: Option<String>,
+    // 組み込み関数の使用許可（埋め込み先で許可しない資源を外せる）
+    pub capabilities: Capabilities,
+    // スクリプトに渡されたコマンドライン引数（os.args() で参照する）
+    pub args: Vec<String>,
+    // file.open のブロックで開いているファイル（内側のブロックが末尾）
+    pub open_files: Vec<OpenFile>,
+    // random モジュールの乱数生成器（読み込んだモジュールとも共有する）
+    pub random: Rc<RefCell<Random>>,
+    // 実行方式（読み込んだモジュールにも引き継ぐ）
+    pub engine: Engine,
+    // 実行中の関数の呼び出し（内側の呼び出しが末尾）
+    pub frames: Vec<Frame>,
+    // 実行の上限と使用量（読み込んだモジュールとも共有する）
+    pub meter: Rc<Meter>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
     /// 新しいインタプリタを作成
     pub fn new() -> Self {
+        Self::with_loader(Rc::new(RefCell::new(ModuleLoader::new())))
+    }
+
+    /// モジュールローダーを共有するインタプリタを作成
+    pub fn with_loader(loader: Rc<RefCell<ModuleLoader>>) -> Self {
+        let mut natives = HashMap::new();
+        let mut mutators = HashMap::new();
+        builtins::register(&mut natives);
+        stdlib::register(&mut natives, &mut mutators);
         Self {
-            scope: Scope::new(None),
+            scope: Scope::new(),
             functions: HashMap::new(),
+            compiled: HashMap::new(),
+            natives,
+            mutators,
+            loader,
+            namespaces: HashMap::new(),
+            package: None,
+            expected_package: None,
+            capabilities: Capabilities::default(),
+            args: Vec::new(),
+            open_files: Vec::new(),
+            random: Rc::new(RefCell::new(Random::from_entropy())),
+            engine: Engine::default(),
+            frames: Vec::new(),
+            meter: Rc::new(Meter::default()),
         }
     }
 
+    /// 乱数のシードを設定する。同じシードなら random モジュールは毎回同じ値を返す
+    pub fn seed(&mut self, seed: u64) {
+        *self.random.borrow_mut() = Random::new(seed);
+    }
+
     /// AST（文のリスト）を実行する
     pub fn interpret(&mut self, stmts: Vec<ASTNode>) -> Result<()> {
-        for stmt in stmts {
-            self.execute_statement(&stmt)?;
+        // トップレベルの return はプログラムの終了として扱う
+        match self.engine {
+            Engine::Tree => {
+                // 変数の参照をスロットに解決してから実行する（トップレベルの変数はこのスコープのスロットにする）
+                let mut stmts = stmts;
+                resolver::annotate(&mut stmts, &mut |name| self.scope.slot(name) as u32);
+                self.execute_block(&stmts)?;
+            }
+            Engine::Vm => {
+                let program = compiler::compile(&stmts)?;
+                self.run_program(&program)?;
+            }
         }
         Ok(())
     }
 
+    /// 文のリストを実行する。return が実行されたら Some(戻り値) を返す
+    fn execute_block(&mut self, stmts: &[ASTNode]) -> Result<Option<Value>> {
+        for stmt in stmts {
+            if let Some(value) = self.execute_statement(stmt)? {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
     /// 各文を実行する
-    fn execute_statement(&mut self, stmt: &ASTNode) -> Result<()> {
+    fn execute_statement(&mut self, stmt: &ASTNode) -> Result<Option<Value>> {
+        self.meter.step()?;
         match stmt {
-            ASTNode::Variable(name, expr_box) => {
-                // expr_box は Box<Expr> なので、*expr_box で解包
-                let value = self.evaluate_expression(&*expr_box)?;
-                self.scope.set(name.clone(), value);
+            ASTNode::Variable(name, type_name, expr_box, binding) => {
+                let value = self.evaluate_expression(expr_box)?;
+                if let Some(type_name) = type_name {
+                    check_type(&value, type_name, &format!("variable {}", name))?;
+                }
+                // 代入する変数は、関数の中ならローカル変数、トップレベルならトップレベルの変数に決まっている
+                let stored = self.bound_slot(*binding).unwrap_or_else(|| unreachable!("assigned variable {} has no slot", name));
+                *stored = Some(value);
             }
             ASTNode::Msg(message) => {
                 println!("{}", message);
             }
-            ASTNode::If(condition_node, then_body, else_body) => {
-                // 条件部分は、ASTNode::Literal または ASTNode::Variable として想定
-                let condition_expr = match &**condition_node {
-                    ASTNode::Literal(val) => Expr::Literal(val.clone()),
-                    ASTNode::Variable(name, _) => Expr::Variable(name.clone()),
-                    _ => return Err(Error::Runtime("Unsupported condition expression".into())),
-                };
-                if let Value::Boolean(true) = self.evaluate_expression(&condition_expr)? {
-                    self.interpret(then_body.clone())?;
+            ASTNode::Package(name) => self.declare_package(name)?,
+            ASTNode::Import(package, target) => self.import(package, target.as_deref())?,
+            ASTNode::If(condition, then_body, else_body) => {
+                if let Value::Boolean(true) = self.evaluate_expression(condition)? {
+                    return self.execute_block(then_body);
                 } else {
-                    self.interpret(else_body.clone())?;
+                    return self.execute_block(else_body);
                 }
             }
-            ASTNode::Function(name, params, body) => {
-                self.functions.insert(name.clone(), (params.clone(), body.clone()));
+            ASTNode::Function(name, def) => {
+                self.functions.insert(name.clone(), def.clone());
             }
             ASTNode::FunctionCall(name, args) => {
-                // 関数呼び出し
-                let (params, body) = self.functions.get(name)
-                    .cloned()
-                    .ok_or_else(|| Error::Runtime(format!("Function {} not found", name)))?;
-                if params.len() != args.len() {
-                    return Err(Error::Runtime(format!(
-                        "Function {} expected {} arguments, but got {}",
-                        name,
-                        params.len(),
-                        args.len()
-                    )));
-                }
-                // ローカルスコープを作成して引数を評価
-                let mut local_scope = Scope::new(Some(self.scope.clone()));
-                for (param, arg) in params.iter().zip(args.iter()) {
-                    let value = self.evaluate_expression(arg)?;
-                    local_scope.set(param.clone(), value);
-                }
-                let previous_scope = self.scope.clone();
-                self.scope = local_scope;
-                self.interpret(body.clone())?;
-                self.scope = previous_scope;
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.evaluate_expression(arg)?);
+                }
+                self.call_function(name, values)?;
+            }
+            ASTNode::MethodCall(object, method, args) => {
+                self.call_method(object, method, args)?;
+            }
+            ASTNode::With(open, body) => {
+                // 本体の実行が終わったら（エラーや return でも）ブロック内で開いたファイルを閉じる
+                let depth = self.open_files.len();
+                let result = self.evaluate_expression(open)
+                    .and_then(|_| self.execute_block(body));
+                self.open_files.truncate(depth);
+                return result;
+            }
+            ASTNode::Return(expr) => {
+                let value = self.evaluate_expression(expr)?;
+                return Ok(Some(value));
             }
             ASTNode::Exit => {
-                println!("Exiting program.");
-                std::process::exit(0);
+                stdlib::require(self, Capability::Process)?;
+                exit_program()
             }
-            // まだ未実装のバリアントはエラーにする
-            _ => return Err(Error::Runtime(format!("Unexpected statement: {:?}", stmt))),
         }
+        Ok(None)
+    }
+
+    /// package 宣言を実行する（モジュールとして読み込まれた場合は期待されるパッケージ名と照合する）
+    pub fn declare_package(&mut self, name: &str) -> Result<()> {
+        if let Some(expected) = &self.expected_package {
+            if expected != name {
+                return Err(Error::Runtime(format!(
+                    "Module declares package {}, but was imported from package {}",
+                    name, expected
+                )));
+            }
+        }
+        self.package = Some(name.to_string());
         Ok(())
     }
 
+    /// import を実行する
+    pub fn import(&mut self, package: &str, target: Option<&str>) -> Result<()> {
+        // Import from elium to os → モジュール elium.os を名前空間 os として読み込む
+        // import os / Import from os → モジュール os を名前空間 os として読み込む
+        // import com.hello → モジュール com.hello を名前空間 hello として読み込む
+        let (module_name, namespace) = match target {
+            Some(target) => (format!("{}.{}", package, target), target.to_string()),
+            None => {
+                let last = package.rsplit('.').next().unwrap_or(package);
+                (package.to_string(), last.to_string())
+            }
+        };
+        // 組み込みモジュール（elium.os / os など）はファイルより優先する
+        let module = match stdlib::builtin_module(&module_name) {
+            Some(name) => Namespace::Native(name),
+            None => Namespace::Module(module::load(self, &module_name)?),
+        };
+        self.namespaces.insert(namespace, module);
+        Ok(())
+    }
+
+    /// 組み込み関数またはユーザー定義関数を呼び出して戻り値を返す
+    /// （"os.args" のような名前は import したモジュールの関数を呼び出す）
+    pub fn call_function(&mut self, name: &str, args: Vec<Value>) -> Result<Value> {
+        if let Some(native) = self.natives.get(name).copied() {
+            let value = native(self, args)?;
+            self.charge(&value)?;
+            return Ok(value);
+        }
+        if let Some(function) = self.compiled.get(name).cloned() {
+            return self.call_compiled(function, args);
+        }
+        if !self.functions.contains_key(name) {
+            if let Some((Namespace::Module(module), function_name)) = self.namespace_member(name) {
+                // モジュールの関数はモジュール自身の変数・関数の中で実行する
+                return module.borrow_mut().call_function(function_name, args);
+            }
+            // 関数への参照を持つ変数（f = double の f）なら、参照先の関数を呼び出す
+            if let Some(Value::Function(target)) = self.variable(name) {
+                return self.call_function(&target, args);
+            }
+        }
+        let function = self.functions.get(name)
+            .cloned()
+            .ok_or_else(|| Error::Runtime(format!("Function {} not found", name)))?;
+        // 引数を束縛したフレームを積んで本体を実行する（型注釈があれば実行時にも検査する）。
+        // 呼び出し元のフレームは残るので、呼び出し先からも呼び出し元の変数が見える
+        self.push_frame(name, &function.params, &function.locals, args)?;
+        let result = self.execute_block(&function.body);
+        self.pop_frame();
+        let value = result?.unwrap_or(Value::None);
+        if let Some(type_name) = &function.return_type {
+            check_type(&value, type_name, &format!("return value of function {}", name))?;
+        }
+        Ok(value)
+    }
+
+    /// 関数が定義されているか（AST のままの関数とコンパイル済みの関数の両方を探す）
+    pub fn has_function(&self, name: &str) -> bool {
+        self.functions.contains_key(name) || self.compiled.contains_key(name)
+    }
+
+    /// 変数を探す（"config.name" のような名前は import したモジュールの変数を探す）
+    pub fn lookup_variable(&self, name: &str) -> Option<Value> {
+        self.scope.get(name).or_else(|| match self.namespace_member(name)? {
+            (Namespace::Module(module), variable) => {
+                let value = module.borrow().lookup_variable(variable);
+                value
+            }
+            (Namespace::Native(_), _) => None,
+        })
+    }
+
+    // 実行中の関数のローカル変数、またはスコープの変数
+    fn variable(&self, name: &str) -> Option<Value> {
+        self.frame_variable(name).or_else(|| self.scope.get(name))
+    }
+
+    // 変数が定義されているか（名前空間より変数を優先するために使う）
+    fn has_variable(&self, name: &str) -> bool {
+        self.frame_variable(name).is_some() || self.scope.contains(name)
+    }
+
+    /// 式の中の名前を評価する：変数、なければ関数への参照
+    pub fn load_variable(&self, name: &str) -> Result<Value> {
+        self.frame_variable(name)
+            .or_else(|| self.lookup_variable(name))
+            .or_else(|| self.function_reference(name))
+            .ok_or_else(|| Error::Runtime(format!("Undefined variable: {}", name)))
+    }
+
+    /// x.f(args) を呼び出す。x が名前空間ならモジュールの関数、そうでなければ値のメソッド
+    fn call_method(&mut self, object: &Expr, method: &str, args: &[Expr]) -> Result<Value> {
+        if let Some(namespace) = self.resolve_namespace(object) {
+            let mut values = Vec::with_capacity(args.len());
+            for arg in args {
+                values.push(self.evaluate_expression(arg)?);
+            }
+            return self.call_namespace(namespace, method, values);
+        }
+        let receiver = self.evaluate_expression(object)?;
+        let mut values = Vec::with_capacity(args.len() + 1);
+        for arg in args {
+            values.push(self.evaluate_expression(arg)?);
+        }
+        // 変数に対する xs.push(x) などは、書き換えた値を変数に戻す
+        if let (Expr::Variable(_, binding), Some(mutator)) = (object, self.mutator(&receiver, method)) {
+            let (mut value, taken) = match self.bound_slot(*binding) {
+                Some(stored) => take_if_shared(stored, receiver),
+                None => (receiver, false),
+            };
+            let result = self.call_mutator(mutator, &mut value, values);
+            if result.is_ok() || taken {
+                if let Some(stored) = self.bound_slot(*binding) {
+                    *stored = Some(value);
+                }
+            }
+            return result;
+        }
+        self.invoke_method(receiver, method, values)
+    }
+
+    // 解決した変数の値の置き場所（実行中の関数のローカル変数か、トップレベルの変数。名前で探す変数は None）
+    fn bound_slot(&mut self, binding: Binding) -> Option<&mut Option<Value>> {
+        match binding {
+            Binding::Local(slot) => {
+                let frame = self.frames.last_mut().expect("local variable outside of a function");
+                Some(&mut frame.locals[slot as usize])
+            }
+            Binding::Global(slot) => Some(self.scope.slot_mut(slot as usize)),
+            Binding::Dynamic => None,
+        }
+    }
+
+    /// 名前空間の関数を呼び出す
+    pub fn call_namespace(&mut self, namespace: Namespace, method: &str, args: Vec<Value>) -> Result<Value> {
+        match namespace {
+            // モジュールの関数はモジュール自身の変数・関数の中で実行する
+            Namespace::Module(module) => module.borrow_mut().call_function(method, args),
+            Namespace::Native(name) => self.call_function(&format!("{}.{}", name, method), args),
+        }
+    }
+
+    /// 値を書き換えるメソッド（"array.push" など）
+    pub fn mutator(&self, receiver: &Value, method: &str) -> Option<MutatorFn> {
+        self.mutators.get(&format!("{}.{}", receiver.type_name(), method)).copied()
+    }
+
+    /// 値のメソッドを呼び出して結果を返す（値を書き換えるメソッドの書き換えた値は捨てる。
+    /// 変数に書き戻すときは mutator で取り出して呼ぶ）。
+    /// "<型名>.<メソッド名>" の組み込み関数を探し、なければ同名の組み込み関数に値を第 1 引数として渡す（x.len() → len(x)）
+    pub fn invoke_method(&mut self, receiver: Value, method: &str, mut args: Vec<Value>) -> Result<Value> {
+        if let Some(mutator) = self.mutator(&receiver, method) {
+            let mut receiver = receiver;
+            return self.call_mutator(mutator, &mut receiver, args);
+        }
+        let typed_name = format!("{}.{}", receiver.type_name(), method);
+        let native = self.natives.get(&typed_name)
+            .or_else(|| self.natives.get(method))
+            .copied()
+            .ok_or_else(|| Error::Runtime(format!("No method {} on {}", method, receiver.type_name())))?;
+        args.insert(0, receiver);
+        let value = native(self, args)?;
+        self.charge(&value)?;
+        Ok(value)
+    }
+
+    // a.b.c の形の式が import したモジュールを指すなら、そのモジュールを返す。
+    // 同じ名前の変数があれば変数を優先する
+    fn resolve_namespace(&self, expr: &Expr) -> Option<Namespace> {
+        self.resolve_namespace_path(&expr_path(expr)?)
+    }
+
+    /// ["a", "b", "c"] の形の名前の並びが import したモジュールを指すなら、そのモジュールを返す
+    pub fn resolve_namespace_path(&self, path: &[&str]) -> Option<Namespace> {
+        let (first, rest) = path.split_first()?;
+        if self.has_variable(first) {
+            return None;
+        }
+        let mut namespace = self.namespaces.get(*first).cloned()?;
+        for member in rest {
+            namespace = match namespace {
+                Namespace::Module(module) => {
+                    let inner = module.borrow().resolve_namespace_path(&[member]);
+                    inner?
+                }
+                Namespace::Native(_) => return None,
+            };
+        }
+        Some(namespace)
+    }
+
+    /// 名前空間のメンバーの値（モジュールの変数や関数への参照、組み込みモジュールの定数や関数への参照）。
+    /// alias は名前空間を import した名前（モジュールの関数への参照を alias.member の形にする）
+    pub fn namespace_value(&self, namespace: Namespace, alias: Option<&str>, member: &str) -> Result<Value> {
+        let value = match namespace {
+            Namespace::Module(module) => {
+                let module = module.borrow();
+                // モジュールの関数への参照は import した名前で呼び出せる形にする（m.double）
+                module.lookup_variable(member).or_else(|| match alias {
+                    Some(alias) if module.has_function(member) => {
+                        Some(Value::Function(intern(&format!("{}.{}", alias, member))))
+                    }
+                    _ => None,
+                })
+            }
+            Namespace::Native(name) => stdlib::constant(name, member).or_else(|| {
+                let qualified = format!("{}.{}", name, member);
+                self.natives.contains_key(&qualified).then(|| Value::Function(intern(&qualified)))
+            }),
+        };
+        value.ok_or_else(|| Error::Runtime(format!("Undefined variable: {}", member)))
+    }
+
+    // "namespace.member" の形の名前を、import したモジュール と メンバー名に分ける
+    fn namespace_member<'a>(&self, name: &'a str) -> Option<(Namespace, &'a str)> {
+        let (namespace, member) = name.split_once('.')?;
+        self.namespaces.get(namespace).map(|module| (module.clone(), member))
+    }
+
     /// 式 (Expr) を評価して Value を返す
     fn evaluate_expression(&mut self, expr: &Expr) -> Result<Value> {
+        self.meter.step()?;
         match expr {
             Expr::Literal(value) => Ok(value.clone()),
-            Expr::Variable(name) => {
-                self.scope.get(name)
-                    .ok_or_else(|| Error::Runtime(format!("Undefined variable: {}", name)))
-            }
+            Expr::Variable(name, binding) => match *binding {
+                Binding::Local(slot) => self.load_local(slot),
+                Binding::Global(slot) => self.load_global(slot),
+                Binding::Dynamic => self.load_variable(name),
+            },
             Expr::BinaryOp(left, op, right) => {
-                let left_value = self.evaluate_expression(left)?;
-                let right_value = self.evaluate_expression(right)?;
-                // 値の所有権の問題を避けるため、必要に応じて clone する
-                match (left_value.clone(), right_value.clone(), op.as_str()) {
-                    (Value::Number(l), Value::Number(r), "+") => Ok(Value::Number(l + r)),
-                    (Value::Number(l), Value::Number(r), "-") => Ok(Value::Number(l - r)),
-                    (Value::Number(l), Value::Number(r), "*") => Ok(Value::Number(l * r)),
-                    (Value::Number(l), Value::Number(r), "/") => {
-                        if r == 0 {
-                            Err(Error::Runtime("Division by zero".into()))
-                        } else {
-                            Ok(Value::Number(l / r))
+                // and / or は短絡評価する（右辺は必要なときだけ評価）
+                if op == "and" || op == "or" {
+                    let left_value = expect_bool(self.evaluate_expression(left)?, op)?;
+                    if left_value == (op == "or") {
+                        return Ok(Value::Boolean(left_value));
+                    }
+                    return Ok(Value::Boolean(expect_bool(self.evaluate_expression(right)?, op)?));
+                }
+                // 型パターンとの比較は値の型を検査する
+                if op == "==" || op == "is" || op == "!=" {
+                    match (&**left, &**right) {
+                        (value, Expr::TypePattern(type_name)) | (Expr::TypePattern(type_name), value) => {
+                            let value = self.evaluate_expression(value)?;
+                            return Ok(Value::Boolean((value.type_name() == type_name) != (op == "!=")));
                         }
+                        _ => {}
                     }
-                    (Value::Text(l), Value::Text(r), "+") => Ok(Value::Text(l + &r)),
-                    (Value::Number(l), Value::Number(r), ">") => Ok(Value::Boolean(l > r)),
-                    (Value::Number(l), Value::Number(r), "<") => Ok(Value::Boolean(l < r)),
-                    (Value::Number(l), Value::Number(r), "==") => Ok(Value::Boolean(l == r)),
-                    _ => Err(Error::Runtime(format!(
-                        "Invalid operation for {:?} and {:?} with operator {}",
-                        left_value, right_value, op
-                    ))),
-                }
-            }
-            Expr::Input(prompt) => {
-                println!("{}", prompt);
-                let mut buffer = String::new();
-                std::io::stdin()
-                    .read_line(&mut buffer)
-                    .map_err(|_| Error::Runtime("Failed to read input".into()))?;
-                let input = buffer.trim().to_string();
-                if let Ok(n) = input.parse::<i64>() {
-                    Ok(Value::Number(n))
-                } else {
-                    Ok(Value::Text(input))
                 }
+                let left_value = self.evaluate_expression(left)?;
+                let right_value = self.evaluate_expression(right)?;
+                let value = binary_operation(left_value, op, right_value)?;
+                self.charge(&value)?;
+                Ok(value)
+            }
+            Expr::Input(prompt, type_name) => {
+                let prompt = self.evaluate_expression(prompt)?;
+                stdlib::require(self, Capability::Stdin)?;
+                read_input(&prompt, type_name.as_deref())
+            }
+            Expr::UnaryOp(op, operand) => {
+                let value = self.evaluate_expression(operand)?;
+                match op.as_str() {
+                    "not" => Ok(Value::Boolean(!expect_bool(value, op)?)),
+                    _ => Err(Error::Runtime(format!("Unknown unary operator: {}", op))),
+                }
+            }
+            Expr::Member(object, member) => {
+                if let Some(namespace) = self.resolve_namespace(object) {
+                    let alias = match &**object {
+                        Expr::Variable(alias, _) => Some(alias.as_str()),
+                        _ => None,
+                    };
+                    return self.namespace_value(namespace, alias, member);
+                }
+                let value = self.evaluate_expression(object)?;
+                member_of(value, member)
             }
-            Expr::FunctionCall(_name, _args) => {
-                // 式内の関数呼び出しはここでは未実装（または後で実装する）
-                Err(Error::Runtime("Function calls in expressions not supported".into()))
+            Expr::MethodCall(object, method, args) => self.call_method(object, method, args),
+            Expr::TypePattern(type_name) => Err(Error::Runtime(format!(
+                "Type pattern :{}: can only be used with ==, != or is",
+                type_name
+            ))),
+            Expr::FunctionCall(name, args) => {
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.evaluate_expression(arg)?);
+                }
+                self.call_function(name, values)
+            }
+            Expr::Array(items) => {
+                let mut values = Vec::with_capacity(items.len());
+                for item in items {
+                    values.push(self.evaluate_expression(item)?);
+                }
+                let value = Value::array(values);
+                self.charge(&value)?;
+                Ok(value)
+            }
+            Expr::Map(entries) => {
+                let mut map = OrderedMap::with_capacity(entries.len());
+                for (key, value) in entries {
+                    map.insert(key.clone(), self.evaluate_expression(value)?);
+                }
+                let value = Value::map(map);
+                self.charge(&value)?;
+                Ok(value)
+            }
+        }
+    }
+
+    /// 変数でない名前が関数（ユーザー定義関数または組み込み関数）なら、その関数への参照を返す
+    pub fn function_reference(&self, name: &str) -> Option<Value> {
+        (self.has_function(name) || self.natives.contains_key(name))
+            .then(|| Value::Function(intern(name)))
+    }
+
+    /// 関数への参照（Value::Function）を引数を渡して呼び出す。map や filter などの組み込み関数から使う
+    pub fn call_value(&mut self, function: &Value, args: Vec<Value>) -> Result<Value> {
+        match function {
+            Value::Function(name) => self.call_function(name, args),
+            other => Err(Error::Type(format!("Expected function, but got {}", other.type_name()))),
+        }
+    }
+}
+
+/// 値を書き換えるメソッドの対象の値を用意する。変数に置かれた値が受け取った値と中身を共有していれば、
+/// 変数から取り出して受け取った値を捨てる（共有が解けるので、書き換えるときに配列やマップを複製しない）。
+/// 取り出したかどうかも返す（取り出した値はエラーのときも変数に戻す）
+pub fn take_if_shared(stored: &mut Option<Value>, receiver: Value) -> (Value, bool) {
+    match stored.take() {
+        Some(value) if value.shares(&receiver) => (value, true),
+        other => {
+            *stored = other;
+            (receiver, false)
+        }
+    }
+}
+
+/// a.b.c の形の式（変数とメンバーアクセスだけの式）を名前の並びにする
+pub fn expr_path(expr: &Expr) -> Option<Vec<&str>> {
+    match expr {
+        Expr::Variable(name, _) => Some(vec![name.as_str()]),
+        Expr::Member(object, member) => {
+            let mut path = expr_path(object)?;
+            path.push(member.as_str());
+            Some(path)
+        }
+        _ => None,
+    }
+}
+
+/// マップのメンバー（m.key）の値
+pub fn member_of(value: Value, member: &str) -> Result<Value> {
+    match value {
+        Value::Map(map) => map
+            .get(member)
+            .cloned()
+            .ok_or_else(|| Error::Runtime(format!("Key {} not found in map", member))),
+        other => Err(Error::Runtime(format!(
+            "Cannot access member {} on {}",
+            member,
+            other.type_name()
+        ))),
+    }
+}
+
+/// input(prompt) / input(prompt, :num:) を実行する
+pub fn read_input(prompt: &Value, type_name: Option<&str>) -> Result<Value> {
+    println!("{}", prompt);
+    let mut buffer = String::new();
+    std::io::stdin()
+        .read_line(&mut buffer)
+        .map_err(|_| Error::Runtime("Failed to read input".into()))?;
+    // 入力は常にテキストとして扱い、型指定があるときだけ変換する
+    let input = Value::text(buffer.trim_end_matches(['\r', '\n']).to_string());
+    match type_name {
+        Some(type_name) => builtins::convert(input, type_name),
+        None => Ok(input),
+    }
+}
+
+/// exit を実行する
+pub fn exit_program() -> ! {
+    println!("Exiting program.");
+    std::process::exit(0);
+}
+
+/// 論理演算子のオペランドは真偽値でなければならない
+pub fn expect_bool(value: Value, op: &str) -> Result<bool> {
+    match value {
+        Value::Boolean(b) => Ok(b),
+        other => Err(Error::Type(format!(
+            "Operator {} expects bool, but got {}",
+            op,
+            other.type_name()
+        ))),
+    }
+}
+
+/// 二項演算（and / or と型パターンとの比較を除く）
+pub fn binary_operation(left_value: Value, op: &str, right_value: Value) -> Result<Value> {
+    // 等価比較はすべての値の組み合わせで使える（型が違えば等しくない）
+    match op {
+        "==" | "is" => return Ok(Value::Boolean(values_equal(&left_value, &right_value))),
+        "!=" => return Ok(Value::Boolean(!values_equal(&left_value, &right_value))),
+        _ => {}
+    }
+    match (&left_value, &right_value, op) {
+        // 整数の演算は溢れたらエラーにする（i64::MIN / -1 も溢れる）
+        (Value::Number(l), Value::Number(r), "+") => integer_operation(l.checked_add(*r), *l, op, *r),
+        (Value::Number(l), Value::Number(r), "-") => integer_operation(l.checked_sub(*r), *l, op, *r),
+        (Value::Number(l), Value::Number(r), "*") => integer_operation(l.checked_mul(*r), *l, op, *r),
+        (Value::Number(l), Value::Number(r), "/") => {
+            if *r == 0 {
+                Err(Error::Runtime("Division by zero".into()))
+            } else {
+                integer_operation(l.checked_div(*r), *l, op, *r)
             }
         }
+        (Value::Text(l), Value::Text(r), "+") => Ok(Value::text(format!("{}{}", l, r))),
+        (Value::Number(l), Value::Number(r), ">") => Ok(Value::Boolean(l > r)),
+        (Value::Number(l), Value::Number(r), "<") => Ok(Value::Boolean(l < r)),
+        // 整数と小数が混在する場合は小数として計算する
+        (Value::Float(l), Value::Float(r), _) => float_operation(*l, *r, op),
+        (Value::Float(l), Value::Number(r), _) => float_operation(*l, *r as f64, op),
+        (Value::Number(l), Value::Float(r), _) => float_operation(*l as f64, *r, op),
+        _ => Err(Error::Runtime(format!(
+            "Invalid operation for {:?} and {:?} with operator {}",
+            left_value, right_value, op
+        ))),
+    }
+}
+
+// 整数の演算の結果（溢れたら None）を値にする
+fn integer_operation(result: Option<i64>, l: i64, op: &str, r: i64) -> Result<Value> {
+    result
+        .map(Value::Number)
+        .ok_or_else(|| Error::Runtime(format!("Integer overflow: {} {} {}", l, op, r)))
+}
+
+/// 値の等価比較（整数と小数は数値として比較し、配列・マップは要素ごとに比較する）
+pub fn values_equal(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Number(l), Value::Float(r)) | (Value::Float(r), Value::Number(l)) => *l as f64 == *r,
+        (Value::Array(l), Value::Array(r)) => {
+            l.len() == r.len() && l.iter().zip(r.iter()).all(|(l, r)| values_equal(l, r))
+        }
+        (Value::Map(l), Value::Map(r)) => {
+            l.len() == r.len()
+                && l.iter().all(|(key, l)| r.get(key).is_some_and(|r| values_equal(l, r)))
+        }
+        _ => left == right,
+    }
+}
+
+/// 値が型注釈に合わなければ Error::Type を返す
+pub fn check_type(value: &Value, type_name: &str, context: &str) -> Result<()> {
+    if typecheck::value_matches(value, type_name) {
+        Ok(())
+    } else {
+        Err(Error::Type(format!(
+            "Expected {} for {}, but got {}",
+            type_name,
+            context,
+            value.type_name()
+        )))
+    }
+}
+
+/// 小数同士の二項演算
+fn float_operation(l: f64, r: f64, op: &str) -> Result<Value> {
+    match op {
+        "+" => Ok(Value::Float(l + r)),
+        "-" => Ok(Value::Float(l - r)),
+        "*" => Ok(Value::Float(l * r)),
+        "/" => {
+            if r == 0.0 {
+                Err(Error::Runtime("Division by zero".into()))
+            } else {
+                Ok(Value::Float(l / r))
+            }
+        }
+        ">" => Ok(Value::Boolean(l > r)),
+        "<" => Ok(Value::Boolean(l < r)),
+        _ => Err(Error::Runtime(format!(
+            "Invalid operation for {:?} and {:?} with operator {}",
+            Value::Float(l),
+            Value::Float(r),
+            op
+        ))),
+    }
+}
+
+/// テストで使う実行の手順
+#[cfg(test)]
+pub mod testing {
+    use super::{Engine, Interpreter};
+    use crate::limits::{self, Limits};
+    use crate::utils::error::Result;
+    use crate::{optimizer, parser, resolver};
+
+    /// ソースを解析し、名前を検査して最適化してから、指定の実行方式で実行する
+    pub fn run(engine: Engine, source: &str) -> (Interpreter, Result<()>) {
+        run_with_limits(engine, source, Limits::default())
+    }
+
+    /// 実行の上限を指定して run する
+    pub fn run_with_limits(engine: Engine, source: &str, limits: Limits) -> (Interpreter, Result<()>) {
+        let mut interpreter = Interpreter::new();
+        interpreter.engine = engine;
+        interpreter.loader.borrow_mut().cache_bytecode = false;
+        interpreter.seed(0);
+        interpreter.set_limits(limits);
+        let result = parser::parse_source(source)
+            .and_then(|stmts| resolver::check(&stmts).map(|_| optimizer::optimize(stmts, false)))
+            .and_then(|stmts| interpreter.interpret(stmts));
+        (interpreter, result)
+    }
+
+    /// 大きなスタックのスレッドで run_with_limits し、エラーを返す（深い再帰のテスト用）
+    pub fn run_deep(engine: Engine, source: &str, limits: Limits) -> Result<()> {
+        let source = source.to_string();
+        limits::run_with_stack(move || run_with_limits(engine, &source, limits).1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::run;
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn repr_parses_back_to_the_same_value() {
+        let source = r#"
+            values = [1, 0 - 42, -7, -0.5, 2.5, 0.1, 1.0, "q\"x\\y\n\t", true, false, none,
+                [1, [none, "a"]], {b: [1, 2.5], "a key": {"if": 1, "none": none, z_1: "t"}}, [], {}]
+        "#;
+        let (interpreter, result) = run(Engine::Tree, source);
+        result.unwrap();
+        let Some(Value::Array(values)) = interpreter.lookup_variable("values") else { panic!("values is not an array") };
+        for value in values.iter() {
+            let (copy, result) = run(Engine::Tree, &format!("copy = {}", value.repr()));
+            assert!(result.is_ok(), "{} does not parse: {:?}", value.repr(), result);
+            assert_eq!(copy.lookup_variable("copy").as_ref(), Some(value), "{}", value.repr());
+        }
+    }
+
+    #[test]
+    fn type_patterns_test_the_type_of_a_value() {
+        let source = r#"
+            function (name=kind, x) {
+                if (x is :num:) {
+                    return "number"
+                }
+                if (:text: == x) {
+                    return "text"
+                }
+                if (x != :none:) {
+                    return type(x)
+                }
+                return "nothing"
+            }
+            kinds = [kind(1), kind("a"), kind(none), kind(2.5), kind(true), kind([1]), kind({a: 1}), kind(kind)]
+        "#;
+        let expected = Value::array(
+            ["number", "text", "nothing", "float", "bool", "array", "map", "function"].into_iter().map(Value::text).collect(),
+        );
+        for engine in [Engine::Tree, Engine::Vm] {
+            let (interpreter, result) = run(engine, source);
+            result.unwrap();
+            assert_eq!(interpreter.lookup_variable("kinds"), Some(expected.clone()), "{:?}", engine);
+            // 比較の外で使った型パターンは実行時エラー
+            let (_, result) = run(engine, "t = :num:");
+            assert!(matches!(result, Err(Error::Runtime(ref msg)) if msg.contains(":num:")), "{:?}: {:?}", engine, result);
+        }
+    }
+
+    #[test]
+    fn logical_operators_short_circuit() {
+        // 右辺を評価すると boom(1) はエラーになる
+        let source = r#"
+            function (name=boom, x) {
+                return not x
+            }
+            a = false and boom(1)
+            b = true or boom(1)
+            c = 1 < 2 and not false
+            d = not true or 2 == 2
+            e = false or false
+        "#;
+        for engine in [Engine::Tree, Engine::Vm] {
+            let (interpreter, result) = run(engine, source);
+            result.unwrap();
+            let values: Vec<_> = ["a", "b", "c", "d", "e"].iter().map(|name| interpreter.lookup_variable(name)).collect();
+            let expected: Vec<_> = [false, true, true, true, false].into_iter().map(|b| Some(Value::Boolean(b))).collect();
+            assert_eq!(values, expected, "{:?}", engine);
+            let (_, result) = run(engine, "function (name=boom, x) {\n return not x\n}\na = true and boom(1)\n");
+            assert!(matches!(result, Err(Error::Type(ref msg)) if msg.starts_with("Operator not")), "{:?}: {:?}", engine, result);
+        }
+    }
+
+    #[test]
+    fn equality_covers_every_value() {
+        let equal = |l: Value, r: Value| binary_operation(l, "==", r).unwrap() == Value::Boolean(true);
+        let map = |entries: &[(&str, Value)]| Value::map(entries.iter().map(|(k, v)| (k.to_string(), v.clone())).collect());
+        assert!(equal(Value::text("a"), Value::text("a")));
+        assert!(!equal(Value::text("a"), Value::text("b")));
+        assert!(equal(Value::Boolean(false), Value::Boolean(false)));
+        assert!(equal(Value::None, Value::None));
+        assert!(equal(Value::array(vec![Value::Number(1), Value::None]), Value::array(vec![Value::Number(1), Value::None])));
+        assert!(!equal(Value::array(vec![Value::Number(1)]), Value::array(vec![Value::Number(2)])));
+        assert!(equal(map(&[("a", Value::Number(1))]), map(&[("a", Value::Number(1))])));
+        assert!(!equal(map(&[("a", Value::Number(1))]), map(&[("a", Value::Number(2))])));
+        // 型が違う値は等しくない
+        assert!(!equal(Value::Number(1), Value::text("1")));
+        assert!(!equal(Value::None, Value::Boolean(false)));
+        assert_eq!(binary_operation(Value::text("a"), "!=", Value::None).unwrap(), Value::Boolean(true));
+    }
+
+    #[test]
+    fn members_and_methods_are_looked_up_on_values() {
+        let source = r#"
+            import math
+            m = {a: {b: {c: 7}}, xs: [3, 1, 2]}
+            c = m.a.b.c
+            n = "héllo".len()
+            first = m.xs.sort().get(0)
+            pi = math.pi > 3
+        "#;
+        for engine in [Engine::Tree, Engine::Vm] {
+            let (interpreter, result) = run(engine, source);
+            result.unwrap();
+            assert_eq!(interpreter.lookup_variable("c"), Some(Value::Number(7)), "{:?}", engine);
+            assert_eq!(interpreter.lookup_variable("n"), Some(Value::Number(5)), "{:?}", engine);
+            assert_eq!(interpreter.lookup_variable("first"), Some(Value::Number(1)), "{:?}", engine);
+            assert_eq!(interpreter.lookup_variable("pi"), Some(Value::Boolean(true)), "{:?}", engine);
+            let (_, result) = run(engine, "m = {a: 1}\nx = m.b\n");
+            assert!(matches!(result, Err(Error::Runtime(ref msg)) if msg == "Key b not found in map"), "{:?}: {:?}", engine, result);
+            let (_, result) = run(engine, "n = 1\nx = n.b\n");
+            assert!(matches!(result, Err(Error::Runtime(ref msg)) if msg == "Cannot access member b on num"), "{:?}: {:?}", engine, result);
+        }
+    }
+
+    #[test]
+    fn calls_share_the_function_definition() {
+        let source = r#"
+            function (name=fib, n) {
+                if (n < 2) {
+                    return n
+                }
+                return fib(n - 1) + fib(n - 2)
+            }
+            x = fib(15)
+        "#;
+        let mut interpreter = Interpreter::new();
+        let mut stmts = parser::parse_source(source).unwrap();
+        resolver::annotate(&mut stmts, &mut |name| interpreter.scope.slot(name) as u32);
+        let ASTNode::Function(_, def) = &stmts[0] else { panic!("{:?}", stmts[0]) };
+        let def = def.clone();
+        interpreter.execute_block(&stmts).unwrap();
+        assert_eq!(interpreter.lookup_variable("x"), Some(Value::Number(610)));
+        // 登録した関数は構文木の定義そのもので、呼び出しが終われば参照は構文木・登録・ここの 3 つだけ
+        assert!(Rc::ptr_eq(&def, &interpreter.functions["fib"]));
+        assert_eq!(Rc::strong_count(&def), 3);
+        assert!(interpreter.frames.is_empty());
+    }
+
+    #[test]
+    fn variables_share_payloads_until_written() {
+        let source = r#"
+            xs = [1, 2, 3]
+            ys = xs
+            ys.push(4)
+            zs = xs
+            m = {a: xs}
+            n = m
+            n.insert("b", 1)
+        "#;
+        for engine in [Engine::Tree, Engine::Vm] {
+            let (interpreter, result) = run(engine, source);
+            result.unwrap();
+            let variable = |name: &str| interpreter.lookup_variable(name).unwrap();
+            assert_eq!(variable("xs"), Value::array(vec![Value::Number(1), Value::Number(2), Value::Number(3)]), "{:?}", engine);
+            assert_eq!(variable("ys").to_string(), "[1, 2, 3, 4]", "{:?}", engine);
+            // 読み出しは中身を共有し、書き換えたときだけ複製する
+            assert!(variable("xs").shares(&variable("zs")), "{:?}", engine);
+            assert!(!variable("xs").shares(&variable("ys")), "{:?}", engine);
+            assert!(!variable("m").shares(&variable("n")), "{:?}", engine);
+            let Value::Map(m) = variable("m") else { panic!("m is not a map") };
+            assert!(m.get("a").unwrap().shares(&variable("xs")), "{:?}", engine);
+            assert_eq!(m.len(), 1, "{:?}", engine);
+        }
+    }
+
+    #[test]
+    fn integer_overflow_is_an_error() {
+        for (l, op, r) in [(i64::MAX, "+", 1), (i64::MIN, "-", 1), (i64::MAX, "*", 2), (i64::MIN, "/", -1)] {
+            let result = binary_operation(Value::Number(l), op, Value::Number(r));
+            assert!(matches!(result, Err(Error::Runtime(ref msg)) if msg.starts_with("Integer overflow")), "{:?}", result);
+        }
+        assert_eq!(binary_operation(Value::Number(7), "/", Value::Number(-2)).unwrap(), Value::Number(-3));
+    }
+
+    #[test]
+    fn integer_overflow_does_not_abort_either_engine() {
+        for engine in [Engine::Tree, Engine::Vm] {
+            let (_, result) = run(engine, "big = 9223372036854775807\nx = big + 1\n");
+            assert!(matches!(result, Err(Error::Runtime(_))), "{:?}: {:?}", engine, result);
+        }
     }
 }
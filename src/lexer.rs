@@ -6,18 +6,28 @@ use crate::utils::error::{Error, Result};
 pub enum Token {
     Identifier(String),
     Number(i64),
+    Float(f64),
     Text(String),
+    Boolean(bool),
+    None,
     Plus,
     Minus,
+    Arrow,
     Multiply,
     Equals,
+    EqualEqual,
+    NotEqual,
     GreaterThan,
+    LessThan,
     LeftParen,
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Colon,
+    Dot,
     Package,
     Import,
     From,
@@ -25,12 +35,39 @@ pub enum Token {
     Msg,
     If,
     Else,
+    Is,
+    And,
+    Or,
+    Not,
     Function,
     Return,
     Exit,
     Input,
-    Get,
-    Async,
+}
+
+/// 名前がキーワードなら、そのトークンを返す
+pub fn keyword(identifier: &str) -> Option<Token> {
+    Some(match identifier {
+        "package" => Token::Package,
+        "import" | "Import" => Token::Import,
+        "from" => Token::From,
+        "to" => Token::To,
+        "msg" => Token::Msg,
+        "if" => Token::If,
+        "else" => Token::Else,
+        "is" => Token::Is,
+        "and" => Token::And,
+        "or" => Token::Or,
+        "not" => Token::Not,
+        "true" => Token::Boolean(true),
+        "false" => Token::Boolean(false),
+        "none" => Token::None,
+        "function" => Token::Function,
+        "return" => Token::Return,
+        "exit" => Token::Exit,
+        "input" => Token::Input,
+        _ => return None,
+    })
 }
 
 pub struct Lexer {
@@ -70,27 +107,74 @@ impl Lexer {
         self.input.get(self.position).copied()
     }
 
+    /// 入力全体をトークン列に変換する
+    pub fn tokenize(&mut self) -> Result<Vec<Token>> {
+        let mut tokens = Vec::new();
+        while let Some(token) = self.next_token() {
+            tokens.push(token?);
+        }
+        Ok(tokens)
+    }
+
     pub fn next_token(&mut self) -> Option<Result<Token>> {
         while let Some(ch) = self.next_char() {
             match ch {
                 ' ' | '\t' | '\r' | '\n' => continue,
-                '=' => return Some(Ok(Token::Equals)),
+                '=' => {
+                    if self.peek_char() == Some('=') {
+                        self.next_char();
+                        return Some(Ok(Token::EqualEqual));
+                    }
+                    return Some(Ok(Token::Equals));
+                }
+                '!' if self.peek_char() == Some('=') => {
+                    self.next_char();
+                    return Some(Ok(Token::NotEqual));
+                }
+                '>' => return Some(Ok(Token::GreaterThan)),
+                '<' => return Some(Ok(Token::LessThan)),
                 '+' => return Some(Ok(Token::Plus)),
-                '-' => return Some(Ok(Token::Minus)),
+                '-' => {
+                    if self.peek_char() == Some('>') {
+                        self.next_char();
+                        return Some(Ok(Token::Arrow));
+                    }
+                    return Some(Ok(Token::Minus));
+                }
                 '*' => return Some(Ok(Token::Multiply)),
                 '(' => return Some(Ok(Token::LeftParen)),
                 ')' => return Some(Ok(Token::RightParen)),
                 '{' => return Some(Ok(Token::LeftBrace)),
                 '}' => return Some(Ok(Token::RightBrace)),
+                '[' => return Some(Ok(Token::LeftBracket)),
+                ']' => return Some(Ok(Token::RightBracket)),
                 ',' => return Some(Ok(Token::Comma)),
                 ':' => return Some(Ok(Token::Colon)),
+                '.' => return Some(Ok(Token::Dot)),
                 '"' => {
                     let mut text = String::new();
-                    while let Some(next) = self.next_char() {
-                        if next == '"' {
-                            break;
+                    loop {
+                        match self.next_char() {
+                            Some('"') => break,
+                            // エスケープシーケンス: \n \t \r \" \\ \0
+                            Some('\\') => match self.next_char() {
+                                Some('n') => text.push('\n'),
+                                Some('t') => text.push('\t'),
+                                Some('r') => text.push('\r'),
+                                Some('0') => text.push('\0'),
+                                Some('"') => text.push('"'),
+                                Some('\\') => text.push('\\'),
+                                Some(other) => {
+                                    return Some(Err(Error::Syntax(format!(
+                                        "Unknown escape sequence \\{} at line {}, column {}",
+                                        other, self.line, self.column
+                                    ))));
+                                }
+                                None => return Some(Err(Error::UnexpectedEOF)),
+                            },
+                            Some(next) => text.push(next),
+                            None => return Some(Err(Error::Syntax("Unterminated text literal".into()))),
                         }
-                        text.push(next);
                     }
                     return Some(Ok(Token::Text(text)));
                 }
@@ -104,34 +188,45 @@ impl Lexer {
                             break;
                         }
                     }
-                    return Some(Ok(Token::Number(number.parse().unwrap())));
+                    // 小数点の後に数字が続く場合は浮動小数点数
+                    if self.peek_char() == Some('.')
+                        && self.input.get(self.position + 1).is_some_and(|c| c.is_ascii_digit())
+                    {
+                        number.push('.');
+                        self.next_char();
+                        while let Some(next) = self.peek_char() {
+                            if next.is_ascii_digit() {
+                                number.push(next);
+                                self.next_char();
+                            } else {
+                                break;
+                            }
+                        }
+                        return Some(number.parse().map(Token::Float).map_err(|_| {
+                            Error::Syntax(format!("Invalid number literal: {}", number))
+                        }));
+                    }
+                    return Some(number.parse().map(Token::Number).map_err(|_| {
+                        Error::Syntax(format!("Number literal out of range: {}", number))
+                    }));
                 }
-                c if c.is_alphabetic() => {
+                c if c.is_alphabetic() || c == '_' => {
+                    // '.' の直後はメンバー名なので、キーワードも識別子として扱う（os.exit など）
+                    let start = self.position - 1;
+                    let after_dot = start > 0 && self.input[start - 1] == '.';
                     let mut identifier = c.to_string();
                     while let Some(next) = self.peek_char() {
-                        if next.is_alphanumeric() {
+                        if next.is_alphanumeric() || next == '_' {
                             identifier.push(next);
                             self.next_char();
                         } else {
                             break;
                         }
                     }
-                    return Some(match identifier.as_str() {
-                        "package" => Ok(Token::Package),
-                        "import" => Ok(Token::Import),
-                        "from" => Ok(Token::From),
-                        "to" => Ok(Token::To),
-                        "msg" => Ok(Token::Msg),
-                        "if" => Ok(Token::If),
-                        "else" => Ok(Token::Else),
-                        "function" => Ok(Token::Function),
-                        "return" => Ok(Token::Return),
-                        "exit" => Ok(Token::Exit),
-                        "input" => Ok(Token::Input),
-                        "get" => Ok(Token::Get),
-                        "async" => Ok(Token::Async),
-                        _ => Ok(Token::Identifier(identifier)),
-                    });
+                    if after_dot {
+                        return Some(Ok(Token::Identifier(identifier)));
+                    }
+                    return Some(Ok(keyword(&identifier).unwrap_or(Token::Identifier(identifier))));
                 }
                 _ => return Some(Err(Error::Runtime(format!("Unexpected character: {}", ch)))),
             }
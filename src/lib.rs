@@ -0,0 +1,23 @@
+// src/lib.rs
+//
+// Elium の処理系。eliumw（src/main.rs）のほか、スクリプトを埋め込むホストや結合テストから使う
+
+pub mod lexer;
+pub mod parser;
+pub mod ast;
+pub mod builtins;
+pub mod bytecode;
+pub mod compiler;
+pub mod heap;
+pub mod intern;
+pub mod interpreter;
+pub mod limits;
+pub mod module;
+pub mod optimizer;
+pub mod ordered_map;
+pub mod resolver;
+pub mod scope;
+pub mod stdlib;
+pub mod typecheck;
+pub mod utils;
+pub mod vm;
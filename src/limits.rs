@@ -0,0 +1,333 @@
+// src/limits.rs
+
+use crate::ast::Value;
+use crate::builtins::MutatorFn;
+use crate::heap;
+use crate::interpreter::Interpreter;
+use crate::utils::error::{Error, Result};
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+// 経過時間はこのステップ数ごとに調べる（時計を読む回数を減らす）
+const CLOCK_INTERVAL: u64 = 1024;
+
+/// 既定の関数呼び出しの深さの上限
+pub const DEFAULT_DEPTH: usize = 100_000;
+
+/// 組み込み関数が一度に作る値の大きさ（バイト）の上限。メモリの上限を指定しなくても、
+/// これより大きな値は作る前にエラーにする（確保に失敗してプロセスが止まらないように）
+pub const MAX_VALUE_BYTES: usize = 1 << 30;
+
+/// run_with_stack でスクリプトを実行するスレッドのスタックの大きさ
+pub const STACK_SIZE: usize = 512 * 1024 * 1024;
+
+// 関数呼び出しに使ってよいスタックの大きさ（最初の呼び出しの位置から数える）。
+// 残りは呼び出した関数の中の式の評価や組み込み関数（json.parse など）のために空けておく。
+// run_with_stack のスレッドでなければ、大きさの分からないスタックでも溢れない控えめな大きさにする
+const DEFAULT_STACK_BUDGET: usize = 1024 * 1024;
+const STACK_RESERVE: usize = 16 * 1024 * 1024;
+
+thread_local! {
+    // このスレッドで関数呼び出しに使ってよいスタックの大きさ
+    static STACK_BUDGET: Cell<usize> = const { Cell::new(DEFAULT_STACK_BUDGET) };
+}
+
+/// 大きなスタック（STACK_SIZE）のスレッドで f を実行して結果を返す。tree の関数呼び出しや
+/// 組み込み関数からの呼び出しは Rust のスタックを使うので、深い再帰を許すときはこの中でインタプリタを作って実行する
+pub fn run_with_stack<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    let thread = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            STACK_BUDGET.with(|budget| budget.set(STACK_SIZE - STACK_RESERVE));
+            f()
+        })
+        .expect("failed to spawn the interpreter thread");
+    thread.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+// 今のスタックの位置（スタックは下位のアドレスに向かって伸びる）
+fn stack_address() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+/// 実行の上限。None の項目は制限しない（既定は呼び出しの深さだけを DEFAULT_DEPTH までにする）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    // 実行するステップ数（tree は文と式の評価、vm は命令の実行をそれぞれ 1 ステップと数える）
+    pub steps: Option<u64>,
+    // 関数呼び出しの深さ。これより浅くても、スタックを使い切りそうなら DepthLimit にする
+    pub depth: Option<usize>,
+    // 作った値（テキスト・配列・マップの中身）の大きさの合計（バイト）
+    pub memory: Option<usize>,
+    // 経過時間
+    pub time: Option<Duration>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self { steps: None, depth: Some(DEFAULT_DEPTH), memory: None, time: None }
+    }
+}
+
+/// 上限に対する使用量。インタプリタと読み込んだモジュールで共有し、
+/// 上限を超えたら上限ごとのエラー（Error::StepLimit など）を返す
+#[derive(Debug)]
+pub struct Meter {
+    limits: Limits,
+    steps: Cell<u64>,
+    depth: Cell<usize>,
+    // 最初の関数呼び出しのスタックの位置（呼び出しの外では 0）
+    stack_base: Cell<usize>,
+    allocated: Cell<usize>,
+    // 経過時間を測り始めた時刻（上限を設定した時刻）
+    started: Instant,
+}
+
+impl Meter {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            steps: Cell::new(0),
+            depth: Cell::new(0),
+            stack_base: Cell::new(0),
+            allocated: Cell::new(0),
+            started: Instant::now(),
+        }
+    }
+
+    /// 実行したステップ数
+    pub fn steps(&self) -> u64 {
+        self.steps.get()
+    }
+
+    /// 作った値の大きさの合計（メモリの上限を設定したときだけ数える）
+    pub fn allocated(&self) -> usize {
+        self.allocated.get()
+    }
+
+    /// 1 ステップ進める
+    pub fn step(&self) -> Result<()> {
+        let steps = self.steps.get() + 1;
+        self.steps.set(steps);
+        if let Some(limit) = self.limits.steps.filter(|&limit| steps > limit) {
+            return Err(Error::StepLimit(limit));
+        }
+        if steps.is_multiple_of(CLOCK_INTERVAL) {
+            self.check_time()?;
+        }
+        Ok(())
+    }
+
+    /// 関数呼び出しに入る（上限を超えるか、スタックを使い切りそうなら入らずにエラーを返す）。
+    /// 抜けるときは leave を呼ぶ
+    pub fn enter(&self) -> Result<()> {
+        let depth = self.depth.get() + 1;
+        if let Some(limit) = self.limits.depth.filter(|&limit| depth > limit) {
+            return Err(Error::DepthLimit(limit));
+        }
+        let address = stack_address();
+        if depth == 1 {
+            self.stack_base.set(address);
+        } else if self.stack_base.get().saturating_sub(address) > STACK_BUDGET.with(Cell::get) {
+            return Err(Error::DepthLimit(depth - 1));
+        }
+        self.depth.set(depth);
+        Ok(())
+    }
+
+    pub fn leave(&self) {
+        self.depth.set(self.depth.get() - 1);
+    }
+
+    /// 値を作った大きさを加える
+    pub fn allocate(&self, bytes: usize) -> Result<()> {
+        let allocated = self.allocated.get().saturating_add(bytes);
+        self.allocated.set(allocated);
+        match self.limits.memory {
+            Some(limit) if allocated > limit => Err(Error::MemoryLimit(limit)),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_time(&self) -> Result<()> {
+        match self.limits.time {
+            Some(limit) if self.started.elapsed() > limit => Err(Error::Timeout(limit)),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Default for Meter {
+    fn default() -> Self {
+        Self::new(Limits::default())
+    }
+}
+
+impl Interpreter {
+    /// 実行の上限を設定する。使用量（ステップ数、作った値の大きさ、経過時間）はここから数え直す
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.meter = Rc::new(Meter::new(limits));
+    }
+
+    /// 作ったばかりの値の大きさを使用量に加える（メモリの上限がなければ数えない）
+    pub fn charge(&self, value: &Value) -> Result<()> {
+        if self.meter.limits.memory.is_none() {
+            return Ok(());
+        }
+        self.meter.allocate(heap::fresh_bytes(value))
+    }
+
+    /// これから作る値の大きさ（バイト）が上限に収まるかを、作る前に検査する。使用量には加えない
+    /// （作った後に charge で加える）。大きさが計算できないほど大きければ None を渡す。
+    /// メモリの上限がなくても、MAX_VALUE_BYTES を超える値はエラーにする
+    pub fn reserve(&self, bytes: Option<usize>) -> Result<()> {
+        let too_large = || Error::Runtime(format!("Value is too large (more than {} bytes)", MAX_VALUE_BYTES));
+        let bytes = bytes.ok_or_else(too_large)?;
+        match self.meter.limits.memory {
+            Some(limit) if self.meter.allocated().saturating_add(bytes) > limit => Err(Error::MemoryLimit(limit)),
+            _ if bytes > MAX_VALUE_BYTES => Err(too_large()),
+            _ => Ok(()),
+        }
+    }
+
+    /// 値を書き換えるメソッドを呼び出し、書き換えで増えた大きさを使用量に加える
+    /// （共有していた中身を複製したときは複製した大きさを加える）
+    pub fn call_mutator(&mut self, mutator: MutatorFn, receiver: &mut Value, args: Vec<Value>) -> Result<Value> {
+        if self.meter.limits.memory.is_none() {
+            return mutator(self, receiver, args);
+        }
+        // 値を複製して比べると中身を共有してしまうので、中身の場所と大きさだけを覚えておく
+        let (pointer, bytes) = (heap::payload_pointer(receiver), heap::payload_bytes(receiver));
+        let result = mutator(self, receiver, args);
+        let grown = if heap::payload_pointer(receiver) == pointer {
+            heap::payload_bytes(receiver).saturating_sub(bytes)
+        } else {
+            heap::payload_bytes(receiver)
+        };
+        self.meter.allocate(grown)?;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::testing::{run, run_deep, run_with_limits};
+    use crate::interpreter::Engine;
+
+    const ENGINES: [Engine; 2] = [Engine::Tree, Engine::Vm];
+
+    const RECURSION: &str = "
+function (name=f, n) {
+    if (n < 1) {
+        return 0
+    }
+    return 1 + f(n - 1)
+}
+";
+
+    const FIB: &str = "
+function (name=fib, n) {
+    if (n < 2) {
+        return n
+    }
+    return fib(n - 1) + fib(n - 2)
+}
+";
+
+    #[test]
+    fn default_limits_allow_ordinary_scripts() {
+        for engine in ENGINES {
+            let (interpreter, result) = run(engine, &format!("{}{}x = f(40)\ny = fib(15)\n", RECURSION, FIB));
+            assert!(result.is_ok(), "{:?}: {:?}", engine, result);
+            assert_eq!(interpreter.scope.get("x"), Some(Value::Number(40)));
+            assert_eq!(interpreter.scope.get("y"), Some(Value::Number(610)));
+        }
+    }
+
+    #[test]
+    fn deep_recursion_stops_at_the_default_depth() {
+        let source = format!("{}x = f(1000000)\n", RECURSION);
+        for engine in ENGINES {
+            let result = run_deep(engine, &source, Limits::default());
+            assert!(matches!(result, Err(Error::DepthLimit(_))), "{:?}: {:?}", engine, result);
+        }
+    }
+
+    #[test]
+    fn deep_recursion_stops_before_the_stack_overflows() {
+        // 上限を設定しなくても、スタックを使い切る前に DepthLimit にする
+        let source = format!("{}x = f(1000000)\n", RECURSION);
+        let limits = Limits { depth: None, ..Limits::default() };
+        let result = run_deep(Engine::Tree, &source, limits);
+        assert!(matches!(result, Err(Error::DepthLimit(_))), "{:?}", result);
+    }
+
+    #[test]
+    fn depth_limit_is_configurable() {
+        let limits = Limits { depth: Some(50), ..Limits::default() };
+        for engine in ENGINES {
+            let (_, result) = run_with_limits(engine, &format!("{}x = f(40)\n", RECURSION), limits);
+            assert!(result.is_ok(), "{:?}: {:?}", engine, result);
+            let (interpreter, result) = run_with_limits(engine, &format!("{}x = f(60)\n", RECURSION), limits);
+            assert!(matches!(result, Err(Error::DepthLimit(50))), "{:?}: {:?}", engine, result);
+            // 打ち切った後も呼び出しの深さは元に戻る
+            assert_eq!(interpreter.meter.depth.get(), 0);
+        }
+    }
+
+    #[test]
+    fn step_limit_stops_runaway_scripts() {
+        let limits = Limits { steps: Some(10_000), ..Limits::default() };
+        for engine in ENGINES {
+            let (interpreter, result) = run_with_limits(engine, &format!("{}x = fib(25)\n", FIB), limits);
+            assert!(matches!(result, Err(Error::StepLimit(10_000))), "{:?}: {:?}", engine, result);
+            assert_eq!(interpreter.meter.steps(), 10_001);
+        }
+    }
+
+    #[test]
+    fn memory_limit_counts_created_values() {
+        let limits = Limits { memory: Some(100_000), ..Limits::default() };
+        let source = "
+function (name=grow, xs, n) {
+    if (n < 1) {
+        return xs
+    }
+    return grow(xs.concat(xs), n - 1)
+}
+xs = grow([1, 2, 3], 30)
+";
+        for engine in ENGINES {
+            let (_, result) = run_with_limits(engine, source, limits);
+            assert!(matches!(result, Err(Error::MemoryLimit(100_000))), "{:?}: {:?}", engine, result);
+        }
+    }
+
+    #[test]
+    fn memory_limit_is_checked_before_allocating() {
+        let limits = Limits { memory: Some(1_000_000), ..Limits::default() };
+        for source in [
+            "x = \"ab\".repeat(4000000000000)\n",
+            "x = 5.format(9000000000000000, 2)\n",
+            "x = 5.format(2, 9000000000000000)\n",
+            "import text\nx = text.pad_start(\"a\", 9000000000000000)\n",
+        ] {
+            for engine in ENGINES {
+                let (_, result) = run_with_limits(engine, source, limits);
+                assert!(matches!(result, Err(Error::MemoryLimit(_))), "{:?} {}: {:?}", engine, source, result);
+            }
+        }
+    }
+
+    #[test]
+    fn time_limit_stops_long_runs() {
+        let limits = Limits { time: Some(Duration::from_millis(20)), ..Limits::default() };
+        for engine in ENGINES {
+            let (_, result) = run_with_limits(engine, &format!("{}x = fib(40)\n", FIB), limits);
+            assert!(matches!(result, Err(Error::Timeout(_))), "{:?}: {:?}", engine, result);
+        }
+    }
+}
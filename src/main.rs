@@ -1,19 +1,14 @@
 // src/main.rs
 
-mod lexer;
-mod parser;
-mod ast;
-mod interpreter;
-mod scope;
-mod utils;
-
+use elium::{ast, bytecode, compiler, interpreter, lexer, limits, optimizer, parser, resolver, stdlib, typecheck, utils};
 use lexer::Lexer;
 use parser::Parser;
-use interpreter::Interpreter;
-use utils::error::{Error, Result};
+use interpreter::{Engine, Interpreter};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
-fn main() {
-    let code = r#"
+// スクリプトが指定されなかったときに実行するデモ
+const DEMO: &str = r#"
     package elium
     Import from elium to os
     msg "Hello World!"
@@ -25,7 +20,7 @@ fn main() {
     } else {
         msg "x is not 3"
     }
-    function (name=add, a, b) {
+    function (name=add, a: num, b: num) -> num {
         i = a + b
         return i
     }
@@ -33,27 +28,490 @@ fn main() {
     exit
     "#;
 
-    let mut lexer = Lexer::new(code);
-    let mut tokens = Vec::new();
-    while let Some(Ok(token)) = lexer.next_token() {
-        tokens.push(token);
+// コマンドラインのオプション（スクリプト名より前に書く）
+#[derive(Debug, Default)]
+struct Options {
+    // --types: 推論した型を表示する
+    types: bool,
+    // --seed N: random モジュールのシード
+    seed: Option<u64>,
+    // --engine tree|vm: 実行方式（既定はバイトコードの vm）
+    engine: Engine,
+    // --compare: 両方の実行方式で実行し、結果と最後の変数を比べる
+    compare: bool,
+    // --ast: 解析した構文木を表示する
+    ast: bool,
+    // --no-cache: バイトコードのキャッシュを読み書きしない
+    no_cache: bool,
+    // --no-optimize: 構文木を最適化せずに実行する
+    no_optimize: bool,
+    // --dump-passes: 最適化の各パスの前後の構文木を表示する
+    dump_passes: bool,
+    // --heap-stats: 実行後に到達できる値の数と大きさを表示する
+    heap_stats: bool,
+    // --max-steps N / --max-depth N / --max-memory BYTES / --timeout MS: 実行の上限
+    limits: limits::Limits,
+    // --sandbox / --allow-*: 組み込み関数の使用許可（どちらかを指定すると、許可したものだけを使える。import するモジュールのファイルも --allow-read で許可する）
+    capabilities: Option<stdlib::Capabilities>,
+}
+
+impl Options {
+    // 使用許可を制限する（まだ制限していなければ何も許可しない状態から始める）
+    fn sandbox(&mut self) -> &mut stdlib::Capabilities {
+        self.capabilities.get_or_insert_with(stdlib::Capabilities::none)
     }
+}
 
-    let mut parser = Parser::new(tokens);
-    match parser.parse() {
-        Ok(ast) => {
-            println!("{:#?}", ast);
-            let mut interpreter = Interpreter::new();
-            let stmts = match ast {
-                ast::ASTNode::Program(s) => s,
-                other => vec![other],
-            };
-            if let Err(err) = interpreter.interpret(stmts) {
-                eprintln!("Runtime error: {}", err);
+// オプションを解析し、スクリプト名の位置（なければ None）とともに返す
+fn parse_options(cli_args: &[String]) -> Result<(Options, Option<usize>), String> {
+    let mut options = Options::default();
+    let mut i = 0;
+    while i < cli_args.len() && cli_args[i].starts_with("--") {
+        let (flag, inline_value) = match cli_args[i].split_once('=') {
+            Some((flag, value)) => (flag, Some(value.to_string())),
+            None => (cli_args[i].as_str(), None),
+        };
+        // --seed N と --seed=N のどちらの形でも値を受け取る
+        let mut value = |expected: &str| match inline_value.clone() {
+            Some(value) => Ok(value),
+            None => {
+                i += 1;
+                cli_args.get(i).cloned().ok_or(format!("{} expects {}", flag, expected))
+            }
+        };
+        match flag {
+            "--types" => options.types = true,
+            "--compare" => options.compare = true,
+            "--ast" => options.ast = true,
+            "--no-cache" => options.no_cache = true,
+            "--no-optimize" => options.no_optimize = true,
+            "--dump-passes" => options.dump_passes = true,
+            "--heap-stats" => options.heap_stats = true,
+            "--seed" => {
+                let value = value("a number")?;
+                let seed = value.parse::<u64>().map_err(|_| format!("Invalid seed: {}", value))?;
+                options.seed = Some(seed);
+            }
+            "--max-steps" => options.limits.steps = Some(parse_limit(flag, &value("a number")?)?),
+            "--max-depth" => options.limits.depth = Some(parse_limit(flag, &value("a number")?)?),
+            "--max-memory" => options.limits.memory = Some(parse_limit(flag, &value("a number of bytes")?)?),
+            "--timeout" => {
+                let millis = parse_limit(flag, &value("milliseconds")?)?;
+                options.limits.time = Some(std::time::Duration::from_millis(millis));
+            }
+            "--sandbox" => {
+                options.sandbox();
+            }
+            "--allow-read" | "--allow-write" => {
+                let capabilities = options.sandbox();
+                let allowed = if flag == "--allow-read" { &mut capabilities.read } else { &mut capabilities.write };
+                // 場所を指定しなければどこでも許可する
+                match (allowed, inline_value) {
+                    (allowed, None) => *allowed = None,
+                    (Some(prefixes), Some(paths)) => prefixes.extend(paths.split(',').map(PathBuf::from)),
+                    (None, Some(_)) => {}
+                }
+            }
+            "--engine" => {
+                options.engine = match value("tree or vm")?.as_str() {
+                    "tree" => Engine::Tree,
+                    "vm" => Engine::Vm,
+                    other => return Err(format!("Unknown engine: {}", other)),
+                };
+            }
+            // --allow-env、--allow-process、--allow-net、--allow-clock、--allow-stdin
+            _ => match flag.strip_prefix("--allow-").and_then(stdlib::Capability::named) {
+                Some(capability) if inline_value.is_none() => options.sandbox().set(capability, true),
+                _ => return Err(format!("Unknown option: {}", cli_args[i])),
+            },
+        }
+        i += 1;
+    }
+    Ok((options, (i < cli_args.len()).then_some(i)))
+}
+
+// 実行時エラーを表示する。上限を超えて打ち切ったときは、それまでの使用量も表示する
+fn report_runtime_error(interpreter: &Interpreter, err: &utils::error::Error) {
+    eprintln!("Runtime error: {}", err);
+    if err.is_limit() {
+        let meter = &interpreter.meter;
+        eprintln!("after {} steps, {} bytes allocated", meter.steps(), meter.allocated());
+    }
+}
+
+// 上限の値（0 以上の整数）を読む
+fn parse_limit<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Invalid value for {}: {}", flag, value))
+}
+
+// 実行方式を比べる（--compare）。同じシードで両方を実行し、結果とトップレベルの変数が
+// 一致しなければ違いを表示して終了コード 1 で終わる。input や exit を使うスクリプトは比べられない
+fn compare_engines(stmts: Vec<ast::ASTNode>, make_interpreter: impl Fn(Engine) -> Interpreter) {
+    let run = |engine: Engine| {
+        println!("== {:?} ==", engine);
+        let mut interpreter = make_interpreter(engine);
+        let result = match interpreter.interpret(stmts.clone()) {
+            Ok(()) => "ok".to_string(),
+            Err(err) => format!("error: {}", err),
+        };
+        let variables: Vec<(String, String)> = interpreter.scope
+            .variables()
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.repr()))
+            .collect();
+        (result, variables)
+    };
+    let tree = run(Engine::Tree);
+    let vm = run(Engine::Vm);
+    if tree == vm {
+        println!("== engines agree ==");
+        return;
+    }
+    println!("== engines differ ==");
+    if tree.0 != vm.0 {
+        println!("result: tree {} / vm {}", tree.0, vm.0);
+    }
+    let mut names: Vec<&String> = tree.1.iter().chain(&vm.1).map(|(name, _)| name).collect();
+    names.sort();
+    names.dedup();
+    let find = |variables: &[(String, String)], name: &str| {
+        variables.iter().find(|(n, _)| n == name).map_or("(undefined)".to_string(), |(_, v)| v.clone())
+    };
+    for name in names {
+        let (tree_value, vm_value) = (find(&tree.1, name), find(&vm.1, name));
+        if tree_value != vm_value {
+            println!("variable {}: tree {} / vm {}", name, tree_value, vm_value);
+        }
+    }
+    std::process::exit(1);
+}
+
+// ソースを解析して型と名前を検査し、最適化する（--ast で構文木、--types で推論結果、--dump-passes で
+// 最適化の各パスの前後の構文木を表示）。失敗したらエラーを表示して None
+fn parse_program(code: &str, options: &Options) -> Option<Vec<ast::ASTNode>> {
+    let tokens = match Lexer::new(code).tokenize() {
+        Ok(tokens) => tokens,
+        Err(e) => {
+            eprintln!("Parse error: {}", e);
+            return None;
+        }
+    };
+    let stmts = match Parser::new(tokens).parse() {
+        Ok(stmts) => {
+            if options.ast {
+                println!("{:#?}", stmts);
             }
+            stmts
         }
         Err(e) => {
             eprintln!("Parse error: {}", e);
+            return None;
+        }
+    };
+    // 実行前に型を推論・検査する
+    match typecheck::infer(&stmts) {
+        Ok(info) => {
+            if options.types {
+                print!("{}", info);
+            }
+            // 未定義の変数と代入前の参照を実行前に検出する
+            if let Err(err) = resolver::check(&stmts) {
+                eprintln!("Name resolution failed: {}", err);
+                return None;
+            }
+            if options.no_optimize {
+                Some(stmts)
+            } else {
+                Some(optimizer::optimize(stmts, options.dump_passes))
+            }
+        }
+        Err(err) => {
+            eprintln!("Type check failed: {}", err);
+            None
+        }
+    }
+}
+
+// ソースを解析・検査してバイトコードにコンパイルする
+fn compile_source(code: &str, options: &Options) -> Option<compiler::FunctionProto> {
+    let stmts = parse_program(code, options)?;
+    match compiler::compile(&stmts) {
+        Ok(program) => Some(program),
+        Err(err) => {
+            eprintln!("Compile error: {}", err);
+            None
+        }
+    }
+}
+
+// コンパイル済みファイル（.eliumc）を読み込む
+fn read_bytecode(path: &str) -> Option<compiler::FunctionProto> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", path, e);
+            return None;
+        }
+    };
+    match bytecode::decode(&bytes) {
+        Ok((program, _)) => Some(program),
+        Err(err) => {
+            eprintln!("{}: {}", path, err);
+            None
+        }
+    }
+}
+
+fn is_bytecode_file(path: &str) -> bool {
+    Path::new(path).extension().is_some_and(|ext| ext == bytecode::BYTECODE_EXTENSION)
+}
+
+// eliumw compile script.el [-o script.eliumc]
+fn compile_command(args: &[String]) {
+    let (source, output) = match args {
+        [source] => (source, Path::new(source).with_extension(bytecode::BYTECODE_EXTENSION)),
+        [source, flag, output] if flag == "-o" => (source, Path::new(output).to_path_buf()),
+        _ => {
+            eprintln!("Usage: eliumw compile script.el [-o script.eliumc]");
+            std::process::exit(2);
+        }
+    };
+    let code = match std::fs::read_to_string(source) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Failed to read {}: {}", source, e);
+            std::process::exit(1);
+        }
+    };
+    let Some(program) = compile_source(&code, &Options::default()) else {
+        std::process::exit(1);
+    };
+    if let Err(e) = std::fs::write(&output, bytecode::encode(&program, bytecode::source_hash(&code))) {
+        eprintln!("Failed to write {}: {}", output.display(), e);
+        std::process::exit(1);
+    }
+    println!("Compiled {} -> {}", source, output.display());
+}
+
+// eliumw disasm file.eliumc / eliumw disasm script.el
+fn disasm_command(args: &[String]) {
+    let [path] = args else {
+        eprintln!("Usage: eliumw disasm file.eliumc|script.el");
+        std::process::exit(2);
+    };
+    let program = if is_bytecode_file(path) {
+        read_bytecode(path)
+    } else {
+        match std::fs::read_to_string(path) {
+            Ok(code) => compile_source(&code, &Options::default()),
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", path, e);
+                None
+            }
+        }
+    };
+    match program {
+        Some(program) => print!("{}", bytecode::disassemble(&program)),
+        None => std::process::exit(1),
+    }
+}
+
+// eliumw bench [--runs N] script.el...
+// スクリプトごとに両方の実行方式で N 回（既定 5 回）実行し、実行時間の中央値と最小値を表示する。
+// 解析・検査・最適化は計測に含めない（Vm はコンパイルを含む）
+fn bench_command(args: &[String]) {
+    let usage = || {
+        eprintln!("Usage: eliumw bench [--runs N] script.el...");
+        std::process::exit(2);
+    };
+    let (runs, scripts) = match args {
+        [flag, runs, scripts @ ..] if flag == "--runs" => match runs.parse::<usize>() {
+            Ok(runs) if runs > 0 => (runs, scripts),
+            _ => usage(),
+        },
+        scripts => (5, scripts),
+    };
+    if scripts.is_empty() {
+        usage();
+    }
+    for script in scripts {
+        println!("{}", script);
+        let code = match std::fs::read_to_string(script) {
+            Ok(code) => code,
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", script, e);
+                continue;
+            }
+        };
+        let Some(stmts) = parse_program(&code, &Options::default()) else {
+            continue;
+        };
+        'engines: for engine in [Engine::Tree, Engine::Vm] {
+            let mut times = Vec::with_capacity(runs);
+            for _ in 0..runs {
+                let mut interpreter = Interpreter::new();
+                interpreter.engine = engine;
+                interpreter.loader.borrow_mut().cache_bytecode = false;
+                if let Some(dir) = Path::new(script).parent() {
+                    interpreter.loader.borrow_mut().search_paths.insert(0, dir.to_path_buf());
+                }
+                interpreter.seed(0);
+                let stmts = stmts.clone();
+                let start = Instant::now();
+                let result = interpreter.interpret(stmts);
+                times.push(start.elapsed());
+                if let Err(err) = result {
+                    eprintln!("  {:<6} Runtime error: {}", format!("{:?}", engine).to_lowercase(), err);
+                    continue 'engines;
+                }
+            }
+            times.sort();
+            println!(
+                "  {:<6} {:>10.3} ms  (min {:.3} ms, {} runs)",
+                format!("{:?}", engine).to_lowercase(),
+                times[times.len() / 2].as_secs_f64() * 1000.0,
+                times[0].as_secs_f64() * 1000.0,
+                runs
+            );
+        }
+    }
+}
+
+fn main() {
+    // tree の関数呼び出しは Rust のスタックを使うので、大きなスタックのスレッドで実行する
+    limits::run_with_stack(run);
+}
+
+fn run() {
+    // eliumw [options] [script.el|script.eliumc [args...]]
+    // eliumw compile script.el [-o script.eliumc]
+    // eliumw disasm file.eliumc|script.el
+    // eliumw bench [--runs N] script.el...
+    let cli_args: Vec<String> = std::env::args().skip(1).collect();
+    match cli_args.first().map(String::as_str) {
+        Some("compile") => return compile_command(&cli_args[1..]),
+        Some("disasm") => return disasm_command(&cli_args[1..]),
+        Some("bench") => return bench_command(&cli_args[1..]),
+        _ => {}
+    }
+    let (options, script_index) = match parse_options(&cli_args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("Usage: eliumw [--types] [--ast] [--dump-passes] [--seed N] [--engine tree|vm] [--compare]");
+            eprintln!("              [--no-cache] [--no-optimize] [--heap-stats]");
+            eprintln!("              [--max-steps N] [--max-depth N] [--max-memory BYTES] [--timeout MS]");
+            eprintln!("              [--sandbox] [--allow-read[=PATHS]] [--allow-write[=PATHS]] [--allow-env] [--allow-process]");
+            eprintln!("              [--allow-net] [--allow-clock] [--allow-stdin] [script.el|script.eliumc [args...]]");
+            eprintln!("       eliumw compile script.el [-o script.eliumc]");
+            eprintln!("       eliumw disasm file.eliumc|script.el");
+            eprintln!("       eliumw bench [--runs N] script.el...");
+            return;
+        }
+    };
+    let script = script_index.map(|i| cli_args[i].clone());
+
+    // 比べるときは両方の実行方式で同じ乱数列を使う
+    let seed = match options.seed {
+        None if options.compare => Some(stdlib::random::Random::from_entropy().next_u64()),
+        seed => seed,
+    };
+    let make_interpreter = |engine: Engine| {
+        let mut interpreter = Interpreter::new();
+        interpreter.engine = engine;
+        interpreter.loader.borrow_mut().cache_bytecode = !options.no_cache;
+        // スクリプトと同じディレクトリのモジュールを優先して探す
+        if let Some(dir) = script.as_deref().and_then(|path| Path::new(path).parent()) {
+            interpreter.loader.borrow_mut().search_paths.insert(0, dir.to_path_buf());
+        }
+        // スクリプトの後の引数は os.args() で参照できる
+        if let Some(i) = script_index {
+            interpreter.args = cli_args[i + 1..].to_vec();
+        }
+        if let Some(seed) = seed {
+            interpreter.seed(seed);
+        }
+        interpreter.set_limits(options.limits);
+        if let Some(capabilities) = &options.capabilities {
+            interpreter.capabilities = capabilities.clone();
+        }
+        interpreter
+    };
+    let run = |program: &compiler::FunctionProto| {
+        let mut interpreter = make_interpreter(Engine::Vm);
+        if let Err(err) = interpreter.run_program(program) {
+            report_runtime_error(&interpreter, &err);
+        }
+        if options.heap_stats {
+            eprintln!("heap: {}", interpreter.heap_stats());
+        }
+    };
+
+    // コンパイル済みファイルはそのまま VM で実行する
+    if let Some(path) = script.as_deref().filter(|path| is_bytecode_file(path)) {
+        if options.engine == Engine::Tree || options.compare || options.types || options.ast || options.dump_passes {
+            eprintln!("{} is precompiled bytecode; --engine tree, --compare, --types, --ast and --dump-passes need the source", path);
+            return;
+        }
+        if let Some(program) = read_bytecode(path) {
+            run(&program);
+        }
+        return;
+    }
+
+    let code = match &script {
+        Some(path) => match std::fs::read_to_string(path) {
+            Ok(code) => code,
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", path, e);
+                return;
+            }
+        },
+        None => DEMO.to_string(),
+    };
+
+    // VM で実行するスクリプトは、ソースが変わっていなければキャッシュしたバイトコードを使う
+    // （構文木や型を表示するとき、最適化しないとき、使用許可でキャッシュの場所を読み書きできないときは解析し直す）
+    let cache = script.as_deref().map(Path::new).filter(|path| {
+        options.engine == Engine::Vm
+            && !(options.compare || options.types || options.ast || options.dump_passes)
+            && !(options.no_cache || options.no_optimize)
+            && options.capabilities.as_ref().is_none_or(|capabilities| bytecode::cache_allowed(capabilities, path))
+    });
+    if let Some(program) = cache.and_then(|path| bytecode::load_cache(path, &code)) {
+        run(&program);
+        return;
+    }
+
+    let Some(stmts) = parse_program(&code, &options) else {
+        return;
+    };
+    if options.compare {
+        compare_engines(stmts, make_interpreter);
+        return;
+    }
+    match options.engine {
+        Engine::Tree => {
+            let mut interpreter = make_interpreter(Engine::Tree);
+            if let Err(err) = interpreter.interpret(stmts) {
+                report_runtime_error(&interpreter, &err);
+            }
+            if options.heap_stats {
+                eprintln!("heap: {}", interpreter.heap_stats());
+            }
+        }
+        Engine::Vm => {
+            let program = match compiler::compile(&stmts) {
+                Ok(program) => program,
+                Err(err) => {
+                    eprintln!("Compile error: {}", err);
+                    return;
+                }
+            };
+            if let Some(path) = cache {
+                bytecode::store_cache(path, &code, &program);
+            }
+            run(&program);
         }
     }
 }
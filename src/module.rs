@@ -0,0 +1,253 @@
+// src/module.rs
+
+use crate::bytecode;
+use crate::compiler;
+use crate::interpreter::{Engine, Interpreter};
+use crate::optimizer;
+use crate::resolver;
+use crate::stdlib::{self, Access};
+use crate::typecheck;
+use crate::utils::error::{Error, Result};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::{Rc, Weak};
+
+/// ソースファイルの拡張子
+pub const SOURCE_EXTENSION: &str = "el";
+
+/// モジュールの読み込みを管理する。インタプリタとその読み込んだモジュールの間で共有する
+#[derive(Debug, Default)]
+pub struct ModuleLoader {
+    // モジュールを探すディレクトリ（先頭から順に探す）
+    pub search_paths: Vec<PathBuf>,
+    // 読み込み済みモジュール：ファイルパス → モジュールを実行したインタプリタ。
+    // モジュールのインタプリタはこのローダーを共有するので、ここから強く参照すると循環して解放されない。
+    // モジュールは読み込んだインタプリタの名前空間が持ち、ここでは弱く参照する
+    cache: HashMap<PathBuf, Weak<RefCell<Interpreter>>>,
+    // 読み込み中のモジュール名（循環 import の検出に使う）
+    loading: Vec<String>,
+    // VM で実行するモジュールのバイトコードをキャッシュするか（__eliumcache__ に読み書きする）
+    pub cache_bytecode: bool,
+}
+
+impl ModuleLoader {
+    /// カレントディレクトリと環境変数 ELIUM_PATH を検索パスとするローダーを作成
+    pub fn new() -> Self {
+        let mut search_paths = vec![PathBuf::from(".")];
+        if let Some(paths) = std::env::var_os("ELIUM_PATH") {
+            search_paths.extend(std::env::split_paths(&paths));
+        }
+        Self {
+            search_paths,
+            cache_bytecode: true,
+            ..Self::default()
+        }
+    }
+
+    /// モジュール名（package.module の形）から検索パス上のファイルを探す
+    pub fn resolve(&self, module_name: &str) -> Result<PathBuf> {
+        let relative: PathBuf = module_name.split('.').collect();
+        let relative = relative.with_extension(SOURCE_EXTENSION);
+        self.search_paths
+            .iter()
+            .map(|dir| dir.join(&relative))
+            .find(|path| path.is_file())
+            .ok_or_else(|| {
+                Error::Runtime(format!(
+                    "Module {} not found (looked for {} in {})",
+                    module_name,
+                    relative.display(),
+                    self.search_paths
+                        .iter()
+                        .map(|dir| dir.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            })
+    }
+}
+
+/// モジュールを読み込んで、そのモジュールを実行したインタプリタを返す。
+/// 同じファイルは一度だけ実行し、2 回目以降は（モジュールがまだどこかの名前空間にあれば）キャッシュを返す
+pub fn load(importer: &Interpreter, module_name: &str) -> Result<Rc<RefCell<Interpreter>>> {
+    let loader = &importer.loader;
+    let path = loader.borrow().resolve(module_name)?;
+    let key = path.canonicalize().unwrap_or_else(|_| path.clone());
+    if let Some(module) = loader.borrow().cache.get(&key).and_then(Weak::upgrade) {
+        return Ok(module);
+    }
+
+    {
+        let mut loader = loader.borrow_mut();
+        if let Some(start) = loader.loading.iter().position(|name| name == module_name) {
+            let mut chain = loader.loading[start..].to_vec();
+            chain.push(module_name.to_string());
+            return Err(Error::Runtime(format!("Circular import: {}", chain.join(" -> "))));
+        }
+        loader.loading.push(module_name.to_string());
+    }
+    let result = execute(importer, module_name, &path);
+    loader.borrow_mut().loading.pop();
+
+    let module = Rc::new(RefCell::new(result?));
+    loader.borrow_mut().cache.insert(key, Rc::downgrade(&module));
+    Ok(module)
+}
+
+// モジュールのファイルを読み込み、新しいインタプリタで実行する
+// （使用許可と引数と実行方式は読み込み元から引き継ぎ、乱数生成器と実行の上限は共有する）
+fn execute(importer: &Interpreter, module_name: &str, path: &PathBuf) -> Result<Interpreter> {
+    // モジュールのファイルもほかのファイルと同じく読み込みの許可を検査する
+    stdlib::require_path(importer, Access::Read, path)?;
+    let code = std::fs::read_to_string(path)
+        .map_err(|e| Error::Runtime(format!("Failed to read module {}: {}", path.display(), e)))?;
+    let parse = || {
+        crate::parser::parse_source(&code)
+            .and_then(|stmts| typecheck::infer(&stmts).map(|_| stmts))
+            .and_then(|stmts| resolver::check(&stmts).map(|_| optimizer::optimize(stmts, false)))
+            .map_err(|e| e.context(&format!("In module {}", module_name)))
+    };
+    let mut interpreter = Interpreter::with_loader(importer.loader.clone());
+    interpreter.capabilities = importer.capabilities.clone();
+    interpreter.args = importer.args.clone();
+    interpreter.random = importer.random.clone();
+    interpreter.meter = importer.meter.clone();
+    interpreter.engine = importer.engine;
+    // package.module の形で読み込んだ場合は、ファイル側の package 宣言と一致するかを検査する
+    interpreter.expected_package = module_name.rsplit_once('.').map(|(package, _)| package.to_string());
+    // VM で実行するモジュールは、ソースが変わっていなければキャッシュしたバイトコードを使う
+    if interpreter.engine == Engine::Vm
+        && importer.loader.borrow().cache_bytecode
+        && bytecode::cache_allowed(&importer.capabilities, path)
+    {
+        let program = match bytecode::load_cache(path, &code) {
+            Some(program) => program,
+            None => {
+                let program = compiler::compile(&parse()?)?;
+                bytecode::store_cache(path, &code, &program);
+                program
+            }
+        };
+        interpreter.run_program(&program)?;
+    } else {
+        interpreter.interpret(parse()?)?;
+    }
+    Ok(interpreter)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::Value;
+    use crate::interpreter::{Engine, Interpreter};
+    use crate::stdlib::Capabilities;
+    use crate::utils::error::{Error, Result};
+    use std::path::{Path, PathBuf};
+    use std::rc::Rc;
+
+    // テストごとに別のディレクトリにモジュールを置く
+    fn module_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("elium-module-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("helper.el"), "base = 10\n").unwrap();
+        std::fs::write(dir.join("typo.el"), "function (name=half, n: num) -> num {\n    return \"x\"\n}\n").unwrap();
+        std::fs::write(dir.join("broken.el"), "x = (1 +\n").unwrap();
+        std::fs::create_dir_all(dir.join("com")).unwrap();
+        std::fs::write(dir.join("com/hello.el"), "package com\ngreeting = \"hi\"\nfunction (name=twice, n) {\n    return n * 2\n}\n").unwrap();
+        std::fs::write(dir.join("cycle_a.el"), "import cycle_b\n").unwrap();
+        std::fs::write(dir.join("cycle_b.el"), "import cycle_a\n").unwrap();
+        dir
+    }
+
+    fn import(engine: Engine, dir: &Path, capabilities: Capabilities) -> Result<()> {
+        run(engine, dir, capabilities, "import helper\nx = helper.base\n")
+    }
+
+    fn run(engine: Engine, dir: &Path, capabilities: Capabilities, source: &str) -> Result<()> {
+        let mut interpreter = Interpreter::new();
+        interpreter.engine = engine;
+        interpreter.capabilities = capabilities;
+        interpreter.loader.borrow_mut().search_paths = vec![dir.to_path_buf()];
+        interpreter.interpret(crate::parser::parse_source(source)?)
+    }
+
+    #[test]
+    fn modules_are_found_on_the_search_path_and_loaded_once() {
+        let dir = module_dir("load");
+        for engine in [Engine::Tree, Engine::Vm] {
+            for source in ["import com.hello\n", "Import from com to hello\n"] {
+                let source = format!("{}x = hello.greeting\ny = hello.twice(21)\n", source);
+                run(engine, &dir, Capabilities::default(), &source).unwrap();
+            }
+            let mut interpreter = Interpreter::new();
+            interpreter.engine = engine;
+            interpreter.loader.borrow_mut().search_paths = vec![dir.clone()];
+            let first = super::load(&interpreter, "com.hello").unwrap();
+            let again = super::load(&interpreter, "com.hello").unwrap();
+            assert!(Rc::ptr_eq(&first, &again), "{:?}: com.hello was executed twice", engine);
+            assert_eq!(first.borrow().lookup_variable("greeting"), Some(Value::text("hi")));
+            let missing = run(engine, &dir, Capabilities::default(), "import nowhere\n");
+            assert!(matches!(missing, Err(Error::Runtime(ref msg)) if msg.starts_with("Module nowhere not found")), "{:?}", missing);
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn circular_imports_are_reported() {
+        let dir = module_dir("cycle");
+        for engine in [Engine::Tree, Engine::Vm] {
+            match run(engine, &dir, Capabilities::default(), "import cycle_a\n") {
+                Err(Error::Runtime(message)) => {
+                    assert!(message.ends_with("Circular import: cycle_a -> cycle_b -> cycle_a"), "{}", message)
+                }
+                other => panic!("{:?}: {:?}", engine, other),
+            }
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn importing_a_module_requires_read_access() {
+        let dir = module_dir("read");
+        for engine in [Engine::Tree, Engine::Vm] {
+            let denied = import(engine, &dir, Capabilities::none());
+            assert!(matches!(denied, Err(Error::Denied(_))), "{:?}: {:?}", engine, denied);
+            let allowed = Capabilities { read: Some(vec![dir.clone()]), ..Capabilities::none() };
+            import(engine, &dir, allowed).unwrap();
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn sandboxed_imports_write_no_cache() {
+        let dir = module_dir("cache");
+        let cache = dir.join("__eliumcache__");
+        let read_only = Capabilities { read: Some(vec![dir.clone()]), ..Capabilities::none() };
+        import(Engine::Vm, &dir, read_only.clone()).unwrap();
+        assert!(!cache.exists(), "a sandbox without write access wrote {}", cache.display());
+        let read_write = Capabilities { write: Some(vec![dir.clone()]), ..read_only };
+        import(Engine::Vm, &dir, read_write).unwrap();
+        assert!(cache.exists(), "the cache is used when its directory is writable");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn modules_are_type_checked() {
+        let dir = module_dir("types");
+        for engine in [Engine::Tree, Engine::Vm] {
+            // 関数を呼ばなくても、読み込む時点で型の誤りを報告する
+            match run(engine, &dir, Capabilities::default(), "import typo\n") {
+                Err(Error::Type(message)) => {
+                    assert!(message.starts_with("In module typo: Expected return value of type num"), "{}", message)
+                }
+                other => panic!("{:?}: {:?}", engine, other),
+            }
+            // 構文エラーも種類を保ったまま、モジュール名を付けて返す
+            match run(engine, &dir, Capabilities::default(), "import broken\n") {
+                Err(Error::Syntax(message)) => assert!(message.starts_with("In module broken: "), "{}", message),
+                other => panic!("{:?}: {:?}", engine, other),
+            }
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
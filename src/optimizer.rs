@@ -0,0 +1,489 @@
+// src/optimizer.rs
+
+use crate::ast::{ASTNode, Expr, FunctionDef, Value};
+use crate::builtins::{self, NativeFn};
+use crate::interpreter::binary_operation;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// 最適化のパス（構文木を書き換える関数）
+pub struct Pass {
+    pub name: &'static str,
+    pub run: fn(Vec<ASTNode>) -> Vec<ASTNode>,
+}
+
+/// 実行する順に並べたパス（展開した関数の本体も畳み込めるよう、展開を先にする）
+pub const PASSES: [Pass; 4] = [
+    Pass { name: "inline", run: inline_functions },
+    Pass { name: "fold", run: fold_constants },
+    Pass { name: "branches", run: eliminate_branches },
+    Pass { name: "dead-code", run: eliminate_dead_code },
+];
+
+// 展開する関数の本体（return の式）の大きさ（式の節の数）の上限
+const MAX_INLINE_SIZE: usize = 16;
+
+/// 型検査を通った構文木を最適化する。dump なら各パスの前後の構文木を表示する
+pub fn optimize(mut stmts: Vec<ASTNode>, dump: bool) -> Vec<ASTNode> {
+    for pass in &PASSES {
+        if dump {
+            println!("== before {} ==\n{:#?}", pass.name, stmts);
+        }
+        stmts = (pass.run)(stmts);
+        if dump {
+            println!("== after {} ==\n{:#?}", pass.name, stmts);
+        }
+    }
+    stmts
+}
+
+// 文のリストの中の式と、入れ子の文のリスト（if の分岐、file.open のブロック、関数の本体）を書き換える
+fn map_block(stmts: Vec<ASTNode>, f: &mut dyn FnMut(Expr) -> Expr) -> Vec<ASTNode> {
+    stmts.into_iter().map(|stmt| map_statement(stmt, f)).collect()
+}
+
+fn map_statement(stmt: ASTNode, f: &mut dyn FnMut(Expr) -> Expr) -> ASTNode {
+    let map_args = |args: Vec<Expr>, f: &mut dyn FnMut(Expr) -> Expr| args.into_iter().map(&mut *f).collect();
+    match stmt {
+        ASTNode::Variable(name, type_name, expr, binding) => {
+            ASTNode::Variable(name, type_name, Box::new(f(*expr)), binding)
+        }
+        ASTNode::If(condition, then_body, else_body) => {
+            let condition = Box::new(f(*condition));
+            ASTNode::If(condition, map_block(then_body, f), map_block(else_body, f))
+        }
+        ASTNode::Function(name, def) => {
+            let def = Rc::unwrap_or_clone(def);
+            let body = map_block(def.body, f);
+            ASTNode::Function(name, Rc::new(FunctionDef { body, ..def }))
+        }
+        ASTNode::FunctionCall(name, args) => ASTNode::FunctionCall(name, map_args(args, f)),
+        ASTNode::MethodCall(object, method, args) => {
+            let object = Box::new(f(*object));
+            ASTNode::MethodCall(object, method, map_args(args, f))
+        }
+        ASTNode::With(open, body) => {
+            let open = Box::new(f(*open));
+            ASTNode::With(open, map_block(body, f))
+        }
+        ASTNode::Return(expr) => ASTNode::Return(Box::new(f(*expr))),
+        other => other,
+    }
+}
+
+// 式の直下の子を書き換える（子から先に書き換える再帰は呼び出し側で行う）
+fn map_children(expr: Expr, f: &mut dyn FnMut(Expr) -> Expr) -> Expr {
+    let mut boxed = |expr: Box<Expr>| Box::new(f(*expr));
+    match expr {
+        Expr::BinaryOp(left, op, right) => {
+            let left = boxed(left);
+            Expr::BinaryOp(left, op, boxed(right))
+        }
+        Expr::Input(prompt, type_name) => Expr::Input(boxed(prompt), type_name),
+        Expr::UnaryOp(op, operand) => Expr::UnaryOp(op, boxed(operand)),
+        Expr::Member(object, member) => Expr::Member(boxed(object), member),
+        Expr::MethodCall(object, method, args) => {
+            let object = boxed(object);
+            Expr::MethodCall(object, method, args.into_iter().map(&mut *f).collect())
+        }
+        Expr::FunctionCall(name, args) => Expr::FunctionCall(name, args.into_iter().map(f).collect()),
+        Expr::Array(items) => Expr::Array(items.into_iter().map(f).collect()),
+        Expr::Map(entries) => Expr::Map(entries.into_iter().map(|(key, value)| (key, f(value))).collect()),
+        other => other,
+    }
+}
+
+// ---- 定数の畳み込み ----
+
+/// リテラルだけの演算（1 + 2、not true、"a" + "b"、x is :num: の x がリテラルの場合など）を計算しておく。
+/// 実行時にエラーになる演算（0 での除算や桁あふれ）はそのまま残す
+pub fn fold_constants(stmts: Vec<ASTNode>) -> Vec<ASTNode> {
+    map_block(stmts, &mut fold_expr)
+}
+
+fn fold_expr(expr: Expr) -> Expr {
+    match map_children(expr, &mut fold_expr) {
+        Expr::BinaryOp(left, op, right) => match (*left, op.as_str(), *right) {
+            // and / or は左辺で結果が決まる場合と、両辺が真偽値の場合だけ畳み込む
+            (Expr::Literal(Value::Boolean(l)), "and" | "or", _) if l == (op == "or") => {
+                Expr::Literal(Value::Boolean(l))
+            }
+            (Expr::Literal(Value::Boolean(_)), "and" | "or", Expr::Literal(Value::Boolean(r))) => {
+                Expr::Literal(Value::Boolean(r))
+            }
+            (Expr::Literal(value), "==" | "is" | "!=", Expr::TypePattern(type_name))
+            | (Expr::TypePattern(type_name), "==" | "is" | "!=", Expr::Literal(value)) => {
+                Expr::Literal(Value::Boolean((value.type_name() == type_name) != (op == "!=")))
+            }
+            (Expr::Literal(l), _, Expr::Literal(r)) if op != "and" && op != "or" && !overflows(&l, &op, &r) => {
+                match binary_operation(l.clone(), &op, r.clone()) {
+                    Ok(value) => Expr::Literal(value),
+                    Err(_) => Expr::BinaryOp(Box::new(Expr::Literal(l)), op, Box::new(Expr::Literal(r))),
+                }
+            }
+            (left, _, right) => Expr::BinaryOp(Box::new(left), op, Box::new(right)),
+        },
+        Expr::UnaryOp(op, operand) => match *operand {
+            Expr::Literal(Value::Boolean(b)) if op == "not" => Expr::Literal(Value::Boolean(!b)),
+            operand => Expr::UnaryOp(op, Box::new(operand)),
+        },
+        // 要素がすべてリテラルの配列・マップはリテラルにする
+        Expr::Array(items) if items.iter().all(|item| matches!(item, Expr::Literal(_))) => {
+            Expr::Literal(Value::array(items.into_iter().map(literal_value).collect()))
+        }
+        Expr::Map(entries) if entries.iter().all(|(_, value)| matches!(value, Expr::Literal(_))) => {
+            Expr::Literal(Value::map(entries.into_iter().map(|(key, value)| (key, literal_value(value))).collect()))
+        }
+        other => other,
+    }
+}
+
+fn literal_value(expr: Expr) -> Value {
+    match expr {
+        Expr::Literal(value) => value,
+        other => unreachable!("not a literal: {:?}", other),
+    }
+}
+
+// 整数の演算が桁あふれするか（実行時の動作に任せるため畳み込まない）
+fn overflows(left: &Value, op: &str, right: &Value) -> bool {
+    match (left, op, right) {
+        (Value::Number(l), "+", Value::Number(r)) => l.checked_add(*r).is_none(),
+        (Value::Number(l), "-", Value::Number(r)) => l.checked_sub(*r).is_none(),
+        (Value::Number(l), "*", Value::Number(r)) => l.checked_mul(*r).is_none(),
+        (Value::Number(l), "/", Value::Number(r)) => l.checked_div(*r).is_none(),
+        _ => false,
+    }
+}
+
+// ---- 条件が定数の if の除去 ----
+
+/// 条件がリテラルの if を、実行される側の分岐の文に置き換える（true 以外の条件は else 側）
+pub fn eliminate_branches(stmts: Vec<ASTNode>) -> Vec<ASTNode> {
+    let mut result = Vec::with_capacity(stmts.len());
+    for stmt in stmts {
+        match stmt {
+            ASTNode::If(condition, then_body, else_body) => match *condition {
+                Expr::Literal(value) => {
+                    let taken = if value == Value::Boolean(true) { then_body } else { else_body };
+                    result.extend(eliminate_branches(taken));
+                }
+                condition => result.push(ASTNode::If(
+                    Box::new(condition),
+                    eliminate_branches(then_body),
+                    eliminate_branches(else_body),
+                )),
+            },
+            other => result.push(map_nested_blocks(other, eliminate_branches)),
+        }
+    }
+    result
+}
+
+// 入れ子の文のリスト（file.open のブロックと関数の本体）に pass を適用する
+fn map_nested_blocks(stmt: ASTNode, pass: fn(Vec<ASTNode>) -> Vec<ASTNode>) -> ASTNode {
+    match stmt {
+        ASTNode::With(open, body) => ASTNode::With(open, pass(body)),
+        ASTNode::Function(name, def) => {
+            let def = Rc::unwrap_or_clone(def);
+            let body = pass(def.body);
+            ASTNode::Function(name, Rc::new(FunctionDef { body, ..def }))
+        }
+        ASTNode::If(condition, then_body, else_body) => ASTNode::If(condition, pass(then_body), pass(else_body)),
+        other => other,
+    }
+}
+
+// ---- 到達しない文の除去 ----
+
+/// return と exit の後の文を取り除く
+pub fn eliminate_dead_code(stmts: Vec<ASTNode>) -> Vec<ASTNode> {
+    let mut result = Vec::with_capacity(stmts.len());
+    for stmt in stmts {
+        let terminates = matches!(stmt, ASTNode::Return(_) | ASTNode::Exit);
+        result.push(map_nested_blocks(stmt, eliminate_dead_code));
+        if terminates {
+            break;
+        }
+    }
+    result
+}
+
+// ---- 小さな関数の展開 ----
+
+/// 本体が return 1 つだけの小さな関数（型注釈がなく、引数と組み込み関数だけを使う）の呼び出しを、
+/// 引数を置き換えた本体の式に展開する。引数がリテラルか変数で、どの引数も本体で使う場合だけ展開する
+/// （引数の評価を省いたり繰り返したりしても結果が変わらないように）。
+/// 関数の定義より後にある文の中の呼び出しだけを展開する
+pub fn inline_functions(stmts: Vec<ASTNode>) -> Vec<ASTNode> {
+    let mut definitions = HashMap::new();
+    count_definitions(&stmts, &mut definitions);
+    let mut natives = HashMap::new();
+    builtins::register(&mut natives);
+
+    let mut inlinable: HashMap<String, (Vec<String>, Expr)> = HashMap::new();
+    let mut result = Vec::with_capacity(stmts.len());
+    for stmt in stmts {
+        let stmt = if inlinable.is_empty() {
+            stmt
+        } else {
+            map_statement(stmt, &mut |expr| inline_expr(expr, &inlinable))
+        };
+        if let ASTNode::Function(name, def) = &stmt {
+            if definitions.get(name) == Some(&1) && !natives.contains_key(name) {
+                if let Some(body) = inline_body(def, &natives) {
+                    let params = def.params.iter().map(|(param, _)| param.clone()).collect();
+                    inlinable.insert(name.clone(), (params, body));
+                }
+            }
+        }
+        result.push(stmt);
+    }
+    result
+}
+
+// 関数名ごとの定義の数（入れ子の定義も数える。複数回定義される関数は展開しない）
+fn count_definitions(stmts: &[ASTNode], definitions: &mut HashMap<String, usize>) {
+    for stmt in stmts {
+        match stmt {
+            ASTNode::Function(name, def) => {
+                *definitions.entry(name.clone()).or_insert(0) += 1;
+                count_definitions(&def.body, definitions);
+            }
+            ASTNode::If(_, then_body, else_body) => {
+                count_definitions(then_body, definitions);
+                count_definitions(else_body, definitions);
+            }
+            ASTNode::With(_, body) => count_definitions(body, definitions),
+            _ => {}
+        }
+    }
+}
+
+// 展開できる関数なら本体の式を返す
+fn inline_body(def: &FunctionDef, natives: &HashMap<String, NativeFn>) -> Option<Expr> {
+    if def.return_type.is_some() || def.params.iter().any(|(_, type_name)| type_name.is_some()) {
+        return None;
+    }
+    let [ASTNode::Return(expr)] = def.body.as_slice() else {
+        return None;
+    };
+    let params: Vec<&str> = def.params.iter().map(|(param, _)| param.as_str()).collect();
+    let mut size = 0;
+    if !inlinable_expr(expr, &params, natives, &mut size) || size > MAX_INLINE_SIZE {
+        return None;
+    }
+    let all_used = def.params.iter().all(|(param, _)| count_uses(expr, param) > 0);
+    all_used.then(|| (**expr).clone())
+}
+
+// 展開しても意味が変わらない式か（メソッド呼び出しは変数を書き換えうるので、入力やメンバーアクセスは
+// 名前空間の解決が変わりうるので含めない）。変数は動的スコープで探すので、引数以外の変数を読む式や、
+// 呼び出し元の引数が見える利用者の関数の呼び出しは、展開すると見える変数が変わるので含めない
+fn inlinable_expr(expr: &Expr, params: &[&str], natives: &HashMap<String, NativeFn>, size: &mut usize) -> bool {
+    *size += 1;
+    let mut inlinable = |expr: &Expr| inlinable_expr(expr, params, natives, size);
+    match expr {
+        Expr::Literal(_) | Expr::TypePattern(_) => true,
+        Expr::Variable(variable, _) => params.contains(&variable.as_str()),
+        Expr::BinaryOp(left, _, right) => inlinable(left) && inlinable(right),
+        Expr::UnaryOp(_, operand) => inlinable(operand),
+        Expr::FunctionCall(callee, args) => natives.contains_key(callee) && args.iter().all(inlinable),
+        Expr::Array(items) => items.iter().all(inlinable),
+        Expr::Map(entries) => entries.iter().all(|(_, value)| inlinable(value)),
+        Expr::Input(..) | Expr::Member(..) | Expr::MethodCall(..) => false,
+    }
+}
+
+// 式の中で変数 param を参照する回数
+fn count_uses(expr: &Expr, param: &str) -> usize {
+    match expr {
+        Expr::Variable(name, _) => usize::from(name == param),
+        Expr::BinaryOp(left, _, right) => count_uses(left, param) + count_uses(right, param),
+        Expr::UnaryOp(_, operand) | Expr::Input(operand, _) | Expr::Member(operand, _) => count_uses(operand, param),
+        Expr::MethodCall(object, _, args) => {
+            count_uses(object, param) + args.iter().map(|arg| count_uses(arg, param)).sum::<usize>()
+        }
+        Expr::FunctionCall(_, items) | Expr::Array(items) => items.iter().map(|item| count_uses(item, param)).sum(),
+        Expr::Map(entries) => entries.iter().map(|(_, value)| count_uses(value, param)).sum(),
+        Expr::Literal(_) | Expr::TypePattern(_) => 0,
+    }
+}
+
+fn inline_expr(expr: Expr, inlinable: &HashMap<String, (Vec<String>, Expr)>) -> Expr {
+    match map_children(expr, &mut |expr| inline_expr(expr, inlinable)) {
+        Expr::FunctionCall(name, args) => match inlinable.get(&name) {
+            Some((params, body))
+                if params.len() == args.len()
+                    && args.iter().all(|arg| matches!(arg, Expr::Literal(_) | Expr::Variable(..))) =>
+            {
+                let bindings: HashMap<&str, &Expr> = params.iter().map(String::as_str).zip(&args).collect();
+                substitute(body.clone(), &bindings)
+            }
+            _ => Expr::FunctionCall(name, args),
+        },
+        other => other,
+    }
+}
+
+// 本体の式の引数を呼び出し側の式に置き換える
+fn substitute(expr: Expr, bindings: &HashMap<&str, &Expr>) -> Expr {
+    match expr {
+        Expr::Variable(name, binding) => match bindings.get(name.as_str()) {
+            Some(arg) => (*arg).clone(),
+            None => Expr::Variable(name, binding),
+        },
+        other => map_children(other, &mut |expr| substitute(expr, bindings)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn optimized(source: &str) -> Vec<ASTNode> {
+        optimize(parser::parse_source(source).unwrap(), false)
+    }
+
+    // トップレベルで最後に name に代入する式
+    fn assigned(stmts: &[ASTNode], name: &str) -> Expr {
+        stmts
+            .iter()
+            .rev()
+            .find_map(|stmt| match stmt {
+                ASTNode::Variable(variable, _, expr, _) if variable == name => Some((**expr).clone()),
+                _ => None,
+            })
+            .unwrap_or_else(|| panic!("{} is not assigned in {:?}", name, stmts))
+    }
+
+    // 畳み込まれてリテラルになった値（畳み込まれていなければ None）
+    fn literal(source: &str) -> Option<Value> {
+        match assigned(&optimized(source), "x") {
+            Expr::Literal(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn folds_literal_operations() {
+        assert_eq!(literal("x = 2 * 3 + 1\n"), Some(Value::Number(7)));
+        assert_eq!(literal("x = 1.5 * 2\n"), Some(Value::Float(3.0)));
+        assert_eq!(literal("x = \"a\" + \"b\"\n"), Some(Value::text("ab")));
+        assert_eq!(literal("x = not true\n"), Some(Value::Boolean(false)));
+        assert_eq!(literal("x = 5 is :num:\n"), Some(Value::Boolean(true)));
+        assert_eq!(literal("x = [1, 2 + 3]\n"), Some(Value::array(vec![Value::Number(1), Value::Number(5)])));
+        // 左辺で結果が決まる and / or は右辺を評価しない
+        assert_eq!(literal("x = false and len(y)\n"), Some(Value::Boolean(false)));
+        assert_eq!(literal("x = true or len(y)\n"), Some(Value::Boolean(true)));
+        assert_eq!(literal("x = true and len(y)\n"), None);
+        assert_eq!(literal("y = 1\nx = y + 1\n"), None);
+    }
+
+    #[test]
+    fn overflows_detects_integer_overflow() {
+        let n = Value::Number;
+        assert!(overflows(&n(i64::MAX), "+", &n(1)));
+        assert!(overflows(&n(i64::MIN), "-", &n(1)));
+        assert!(overflows(&n(i64::MAX), "*", &n(2)));
+        assert!(overflows(&n(i64::MIN), "/", &n(-1)));
+        assert!(overflows(&n(1), "/", &n(0)));
+        assert!(!overflows(&n(i64::MAX), "-", &n(1)));
+        assert!(!overflows(&n(7), "/", &n(2)));
+        assert!(!overflows(&Value::Float(f64::MAX), "*", &Value::Float(2.0)));
+        assert!(!overflows(&n(1), "==", &n(2)));
+    }
+
+    #[test]
+    fn leaves_failing_operations_to_run_time() {
+        // 桁あふれや型の合わない演算は実行時のエラーになるよう畳み込まない
+        for source in ["x = 9223372036854775807 + 1\n", "x = 0 - 9223372036854775807 - 2\n", "x = 9223372036854775807 * 2\n", "x = \"a\" - 1\n"] {
+            assert!(
+                matches!(assigned(&optimized(source), "x"), Expr::BinaryOp(..)),
+                "{:?} was folded",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn removes_constant_branches_and_dead_code() {
+        let stmts = optimized("if (1 > 2) {\n    x = 1\n} else {\n    x = 2\n}\n");
+        assert_eq!(stmts.len(), 1);
+        assert!(matches!(assigned(&stmts, "x"), Expr::Literal(Value::Number(2))));
+        // true 以外のリテラルの条件は else 側
+        let stmts = optimized("if (\"yes\") {\n    x = 1\n}\n");
+        assert!(stmts.is_empty(), "{:?}", stmts);
+        let stmts = optimized("function (name=f, a) {\n    return a\n    msg \"never\"\n}\nx = 1\nreturn 0\nx = 2\n");
+        let ASTNode::Function(_, def) = &stmts[0] else {
+            panic!("expected a function definition, got {:?}", stmts[0]);
+        };
+        assert_eq!(def.body.len(), 1);
+        assert_eq!(stmts.len(), 3);
+    }
+
+    #[test]
+    fn inlines_small_functions() {
+        let double = "function (name=double, n) {\n    return n * 2\n}\n";
+        assert_eq!(literal(&format!("{}x = double(3)\n", double)), Some(Value::Number(6)));
+        assert!(matches!(assigned(&optimized(&format!("{}y = 1\nx = double(y)\n", double)), "x"), Expr::BinaryOp(..)));
+        // 展開しない呼び出し
+        for source in [
+            // 定義より前の呼び出し
+            format!("x = double(3)\n{}", double),
+            // 引数がリテラルでも変数でもない
+            format!("{}x = double(len([1]))\n", double),
+            // 型注釈のある関数
+            "function (name=double, n: num) {\n    return n * 2\n}\nx = double(3)\n".to_string(),
+            // 使わない引数がある
+            "function (name=first, a, b) {\n    return a\n}\nx = first(1, 2)\n".to_string(),
+            // 自分自身を呼ぶ
+            "function (name=loop, n) {\n    return loop(n)\n}\nx = loop(1)\n".to_string(),
+            // 2 回定義される
+            format!("{}{}x = double(3)\n", double, double),
+            // 利用者の関数を呼ぶ（呼び出し先が呼び出し元の引数を見うる）
+            "function (name=g) {\n    return n\n}\nfunction (name=f, n) {\n    return g() + n\n}\nn = 1\nx = f(5)\n".to_string(),
+            // 引数以外の変数を読む
+            "k = 1\nfunction (name=f, n) {\n    return n + k\n}\nx = f(5)\n".to_string(),
+        ] {
+            assert!(matches!(assigned(&optimized(&source), "x"), Expr::FunctionCall(..)), "inlined in {:?}", source);
+        }
+    }
+
+    // 最適化してもしなくても、両方の実行方式で結果とトップレベルの変数が同じになること
+    #[test]
+    fn optimizing_does_not_change_results() {
+        use crate::interpreter::{Engine, Interpreter};
+        use crate::resolver;
+        let run = |engine: Engine, source: &str, optimize: bool| {
+            let stmts = parser::parse_source(source).unwrap();
+            resolver::check(&stmts).unwrap();
+            let stmts = if optimize { super::optimize(stmts, false) } else { stmts };
+            let mut interpreter = Interpreter::new();
+            interpreter.engine = engine;
+            let result = interpreter.interpret(stmts).map_err(|err| err.to_string());
+            let variables: Vec<(String, String)> = interpreter
+                .scope
+                .variables()
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.repr()))
+                .collect();
+            (result, variables)
+        };
+        let sources = [
+            // 展開すると g から見える n が変わる（動的スコープ）
+            "function (name=g) {\n    y = n * 2\n    return y\n}\nfunction (name=f, n) {\n    return g() + n\n}\nn = 100\nx = f(5)\n",
+            "function (name=h) {\n    return n\n}\nfunction (name=f, n) {\n    return h()\n}\nn = 1\nx = f(2)\n",
+            "function (name=double, n) {\n    return n * 2\n}\nfunction (name=size, xs) {\n    return len(xs) + 1\n}\nx = double(21) + size([1, 2])\n",
+            "if (2 > 1 and not false) {\n    x = \"a\" + \"b\"\n} else {\n    x = 0\n}\ny = [1, 2 + 3, {k: 4 * 5}]\n",
+            "big = 9223372036854775807\nx = big + 1\n",
+            "function (name=f, a) {\n    return a\n    x = 0\n}\nx = f(3)\n",
+        ];
+        for source in sources {
+            for engine in [Engine::Tree, Engine::Vm] {
+                assert_eq!(run(engine, source, true), run(engine, source, false), "{:?} on {:?}", engine, source);
+            }
+        }
+    }
+}
@@ -0,0 +1,189 @@
+// src/ordered_map.rs
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+
+/// 挿入順を保つマップ。Value::Map の中身で、反復・表示・JSON 出力はすべて挿入順になる。
+/// 既にあるキーに insert しても位置は変わらない
+#[derive(Clone)]
+pub struct OrderedMap<K, V> {
+    // 挿入順に並べた要素
+    entries: Vec<(K, V)>,
+    // キー → entries の位置
+    indices: HashMap<K, usize>,
+}
+
+impl<K: Hash + Eq + Clone, V> OrderedMap<K, V> {
+    pub fn new() -> Self {
+        Self { entries: Vec::new(), indices: HashMap::new() }
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self { entries: Vec::with_capacity(capacity), indices: HashMap::with_capacity(capacity) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.indices.get(key).map(|&i| &self.entries[i].1)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.indices.contains_key(key)
+    }
+
+    /// 値を設定し、以前の値があれば返す（既にあるキーは元の位置のまま）
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.indices.get(&key) {
+            Some(&i) => Some(std::mem::replace(&mut self.entries[i].1, value)),
+            None => {
+                self.indices.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+                None
+            }
+        }
+    }
+
+    /// キーを取り除いて値を返す（残りの要素の順序は保つ）
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let index = self.indices.remove(key)?;
+        let (_, value) = self.entries.remove(index);
+        for (moved, _) in &self.entries[index..] {
+            if let Some(i) = self.indices.get_mut::<K>(moved) {
+                *i -= 1;
+            }
+        }
+        Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> Default for OrderedMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> FromIterator<(K, V)> for OrderedMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = Self::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> Extend<(K, V)> for OrderedMap<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K, V> IntoIterator for OrderedMap<K, V> {
+    type Item = (K, V);
+    type IntoIter = std::vec::IntoIter<(K, V)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+/// 等価比較は順序を問わない（同じキーと値の組を持てば等しい）
+impl<K: Hash + Eq + Clone, V: PartialEq> PartialEq for OrderedMap<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|(key, value)| other.get(key) == Some(value))
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for OrderedMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.entries.iter().map(|(key, value)| (key, value))).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(map: &OrderedMap<String, i64>) -> Vec<&str> {
+        map.iter().map(|(key, _)| key.as_str()).collect()
+    }
+
+    fn map(entries: &[(&str, i64)]) -> OrderedMap<String, i64> {
+        entries.iter().map(|&(key, value)| (key.to_string(), value)).collect()
+    }
+
+    #[test]
+    fn insert_keeps_the_first_position() {
+        let mut m = map(&[("z", 1), ("a", 2), ("m", 3)]);
+        assert_eq!(keys(&m), ["z", "a", "m"]);
+        assert_eq!(m.insert("a".into(), 20), Some(2));
+        assert_eq!(m.insert("b".into(), 4), None);
+        assert_eq!(keys(&m), ["z", "a", "m", "b"]);
+        assert_eq!(m.get("a"), Some(&20));
+        assert_eq!(m.len(), 4);
+    }
+
+    #[test]
+    fn remove_keeps_the_order_of_the_rest() {
+        let mut m = map(&[("a", 1), ("b", 2), ("c", 3), ("d", 4)]);
+        assert_eq!(m.remove("b"), Some(2));
+        assert_eq!(m.remove("b"), None);
+        assert_eq!(keys(&m), ["a", "c", "d"]);
+        // 後ろの要素の位置も正しく詰める
+        assert_eq!(m.get("c"), Some(&3));
+        assert_eq!(m.get("d"), Some(&4));
+        assert!(!m.contains_key("b"));
+        // 取り除いたキーを入れ直すと末尾に加わる
+        m.insert("b".into(), 5);
+        assert_eq!(m.remove("a"), Some(1));
+        assert_eq!(keys(&m), ["c", "d", "b"]);
+        assert_eq!(m.into_iter().collect::<Vec<_>>(), [("c".to_string(), 3), ("d".to_string(), 4), ("b".to_string(), 5)]);
+    }
+
+    #[test]
+    fn equality_ignores_order() {
+        assert_eq!(map(&[("a", 1), ("b", 2)]), map(&[("b", 2), ("a", 1)]));
+        assert_ne!(map(&[("a", 1), ("b", 2)]), map(&[("a", 1), ("b", 3)]));
+        assert_ne!(map(&[("a", 1)]), map(&[("a", 1), ("b", 2)]));
+        assert!(OrderedMap::<String, i64>::default().is_empty());
+        assert_eq!(format!("{:?}", map(&[("y", 1), ("x", 2)])), r#"{"y": 1, "x": 2}"#);
+    }
+
+    #[test]
+    fn elium_maps_iterate_in_insertion_order() {
+        use crate::interpreter::{testing, Engine};
+        let source = "m = {z: 1, a: 2}\nm.insert(\"b\", 3)\nm.insert(\"z\", 4)\ngone = m.remove(\"a\")\nk = m.keys()\nshown = str(m.merge({a: 5}))\n";
+        for engine in [Engine::Tree, Engine::Vm] {
+            let (interpreter, result) = testing::run(engine, source);
+            result.unwrap();
+            let get = |name: &str| interpreter.scope.get(name).map(|value| value.repr());
+            assert_eq!(get("k").as_deref(), Some(r#"["z", "b"]"#), "{:?}", engine);
+            assert_eq!(get("shown").as_deref(), Some(r#""{z: 4, b: 3, a: 5}""#), "{:?}", engine);
+        }
+    }
+}
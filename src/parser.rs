@@ -1,8 +1,16 @@
 // src/parser.rs
 
-use crate::ast::{ASTNode, Expr, Value};
-use crate::lexer::Token;
+use crate::ast::{ASTNode, Expr, FunctionDef, Value};
+use crate::lexer::{Lexer, Token};
+use crate::resolver::Binding;
 use crate::utils::error::{Error, Result};
+use std::rc::Rc;
+
+/// ソースコードを字句解析・構文解析して文のリストを返す
+pub fn parse_source(code: &str) -> Result<Vec<ASTNode>> {
+    let tokens = Lexer::new(code).tokenize()?;
+    Parser::new(tokens).parse()
+}
 
 #[derive(Debug, Clone)]
 pub struct Parser {
@@ -31,167 +39,270 @@ impl Parser {
         self.tokens.get(self.position).cloned()
     }
 
-    pub fn parse(&mut self) -> Result<ASTNode> {
+    pub fn parse(&mut self) -> Result<Vec<ASTNode>> {
         let mut statements = Vec::new();
+        while self.peek_token().is_some() {
+            statements.push(self.parse_statement()?);
+        }
+        Ok(statements)
+    }
 
-        while let Some(token) = self.peek_token() {
-            match token {
-                Token::Package => {
-                    self.next_token();
-                    if let Some(Token::Identifier(name)) = self.next_token() {
-                        statements.push(ASTNode::Package(name));
-                    } else {
-                        return Err(Error::Syntax("Expected package name".into()));
-                    }
+    // parse_statement: 文を 1 つ解析する（トップレベルとブロック内で共通）
+    fn parse_statement(&mut self) -> Result<ASTNode> {
+        let token = self.peek_token().ok_or(Error::UnexpectedEOF)?;
+        match token {
+            Token::Package => {
+                self.next_token();
+                // 例: package com.hello
+                if let Some(Token::Identifier(name)) = self.next_token() {
+                    Ok(ASTNode::Package(self.parse_dotted_name(name)?))
+                } else {
+                    Err(Error::Syntax("Expected package name".into()))
                 }
-                Token::Import => {
-                    self.next_token();
+            }
+            Token::Import => {
+                self.next_token();
+                match self.next_token() {
                     // 例: Import from elium to os
-                    if let Some(Token::From) = self.next_token() {
+                    Some(Token::From) => {
                         if let Some(Token::Identifier(pkg)) = self.next_token() {
-                            if let Some(Token::To) = self.next_token() {
+                            let pkg = self.parse_dotted_name(pkg)?;
+                            if let Some(Token::To) = self.peek_token() {
+                                self.next_token(); // consume 'to'
                                 if let Some(Token::Identifier(target)) = self.next_token() {
-                                    statements.push(ASTNode::Import(pkg, Some(target)));
+                                    Ok(ASTNode::Import(pkg, Some(target)))
                                 } else {
-                                    return Err(Error::Syntax("Expected target package for import".into()));
+                                    Err(Error::Syntax("Expected target package for import".into()))
                                 }
                             } else {
                                 // "from" のみの場合
-                                statements.push(ASTNode::Import(pkg, None));
+                                Ok(ASTNode::Import(pkg, None))
                             }
                         } else {
-                            return Err(Error::Syntax("Expected package name after 'from'".into()));
+                            Err(Error::Syntax("Expected package name after 'from'".into()))
                         }
-                    } else {
-                        return Err(Error::Syntax("Expected 'from' in import statement".into()));
                     }
+                    // 例: import os
+                    Some(Token::Identifier(module)) => Ok(ASTNode::Import(self.parse_dotted_name(module)?, None)),
+                    _ => Err(Error::Syntax("Expected 'from' or module name in import statement".into())),
                 }
-                Token::Msg => {
-                    self.next_token();
-                    if let Some(Token::Text(msg)) = self.next_token() {
-                        statements.push(ASTNode::Msg(msg));
-                    } else {
-                        return Err(Error::Syntax("Expected message string".into()));
-                    }
+            }
+            Token::Msg => {
+                self.next_token();
+                if let Some(Token::Text(msg)) = self.next_token() {
+                    Ok(ASTNode::Msg(msg))
+                } else {
+                    Err(Error::Syntax("Expected message string".into()))
                 }
-                Token::If => {
-                    // if 文: if ( condition ) { then } else { else }
-                    self.next_token(); // consume 'if'
-                    // 期待: '(' condition ')'
-                    if let Some(Token::LeftParen) = self.next_token() {
-                        let condition = self.parse_expression()?;
-                        if let Some(Token::RightParen) = self.next_token() {
-                            // 期待: '{' then statements '}'
-                            if let Some(Token::LeftBrace) = self.next_token() {
-                                let then_body = self.parse_block()?;
-                                // 期待: '}' already consumed by parse_block
-                                // else 部分は任意
-                                let else_body = if let Some(Token::Else) = self.peek_token() {
-                                    self.next_token(); // consume 'else'
-                                    if let Some(Token::LeftBrace) = self.next_token() {
-                                        self.parse_block()?
-                                    } else {
-                                        return Err(Error::Syntax("Expected '{' after else".into()));
-                                    }
+            }
+            Token::If => {
+                // if 文: if ( condition ) { then } else { else }
+                self.next_token(); // consume 'if'
+                // 期待: '(' condition ')'
+                if let Some(Token::LeftParen) = self.next_token() {
+                    let condition = self.parse_expression()?;
+                    if let Some(Token::RightParen) = self.next_token() {
+                        // 期待: '{' then statements '}'
+                        if let Some(Token::LeftBrace) = self.next_token() {
+                            let then_body = self.parse_block()?;
+                            // 期待: '}' already consumed by parse_block
+                            // else 部分は任意
+                            let else_body = if let Some(Token::Else) = self.peek_token() {
+                                self.next_token(); // consume 'else'
+                                if let Some(Token::LeftBrace) = self.next_token() {
+                                    self.parse_block()?
                                 } else {
-                                    Vec::new()
-                                };
-                                // condition を Expr から ASTNode::Literal を介して表現
-                                let cond_node = ASTNode::Literal(match condition {
-                                    Expr::Literal(v) => v,
-                                    _ => return Err(Error::Syntax("Complex condition expressions not supported yet".into())),
-                                });
-                                statements.push(ASTNode::If(Box::new(cond_node), then_body, else_body));
+                                    return Err(Error::Syntax("Expected '{' after else".into()));
+                                }
                             } else {
-                                return Err(Error::Syntax("Expected '{' after if condition".into()));
-                            }
+                                Vec::new()
+                            };
+                            Ok(ASTNode::If(Box::new(condition), then_body, else_body))
                         } else {
-                            return Err(Error::Syntax("Expected ')' after if condition".into()));
+                            Err(Error::Syntax("Expected '{' after if condition".into()))
                         }
                     } else {
-                        return Err(Error::Syntax("Expected '(' after if".into()));
+                        Err(Error::Syntax("Expected ')' after if condition".into()))
                     }
+                } else {
+                    Err(Error::Syntax("Expected '(' after if".into()))
                 }
-                Token::Function => {
-                    // function 定義: function ( name=add, a, b ) { ... } return expr
-                    self.next_token(); // consume 'function'
-                    if let Some(Token::LeftParen) = self.next_token() {
-                        // parse parameter list
-                        let mut params = Vec::new();
-                        let mut func_name = String::new();
-                        while let Some(token) = self.peek_token() {
-                            match token {
-                                Token::RightParen => { self.next_token(); break; }
-                                Token::Comma => { self.next_token(); continue; }
-                                Token::Identifier(_) => {
-                                    let id = self.next_token().unwrap(); // Identifier
-                                    if let Token::Identifier(name) = id {
-                                        // もしパラメーターの中で "name=add" のような記述があれば、
-                                        // それを関数名として採用
-                                        if let Some(Token::Equals) = self.peek_token() {
-                                            self.next_token(); // consume '='
-                                            if let Some(Token::Identifier(n)) = self.next_token() {
-                                                func_name = n;
-                                            } else {
-                                                return Err(Error::Syntax("Expected function name after '='".into()));
-                                            }
-                                        } else {
-                                            params.push(name);
-                                        }
+            }
+            Token::Function => {
+                // function 定義: function ( name=add, a: num, b ) -> num { ... }
+                self.next_token(); // consume 'function'
+                if let Some(Token::LeftParen) = self.next_token() {
+                    // parse parameter list
+                    let mut params = Vec::new();
+                    let mut func_name = String::new();
+                    while let Some(token) = self.peek_token() {
+                        match token {
+                            Token::RightParen => { self.next_token(); break; }
+                            Token::Comma => { self.next_token(); continue; }
+                            Token::Identifier(name) => {
+                                self.next_token(); // consume identifier
+                                // もしパラメーターの中で "name=add" のような記述があれば、
+                                // それを関数名として採用
+                                if let Some(Token::Equals) = self.peek_token() {
+                                    self.next_token(); // consume '='
+                                    if let Some(Token::Identifier(n)) = self.next_token() {
+                                        func_name = n;
+                                    } else {
+                                        return Err(Error::Syntax("Expected function name after '='".into()));
                                     }
+                                } else {
+                                    // 引数の型注釈は任意: a: num
+                                    let type_name = if let Some(Token::Colon) = self.peek_token() {
+                                        self.next_token(); // consume ':'
+                                        Some(self.parse_annotation()?)
+                                    } else {
+                                        None
+                                    };
+                                    params.push((name, type_name));
                                 }
-                                _ => return Err(Error::Syntax(format!("Unexpected token in function parameters: {:?}", token))),
                             }
+                            _ => return Err(Error::Syntax(format!("Unexpected token in function parameters: {:?}", token))),
                         }
-                        // 期待: '{'
-                        if let Some(Token::LeftBrace) = self.next_token() {
-                            let body = self.parse_block()?;
-                            // 期待: optional return statement at the end of the block is parsed as part of the body
-                            statements.push(ASTNode::Function(func_name, params, body));
-                        } else {
-                            return Err(Error::Syntax("Expected '{' to start function body".into()));
-                        }
+                    }
+                    // 戻り値の型注釈は任意: -> num
+                    let return_type = if let Some(Token::Arrow) = self.peek_token() {
+                        self.next_token(); // consume '->'
+                        Some(self.parse_annotation()?)
+                    } else {
+                        None
+                    };
+                    // 期待: '{'
+                    if let Some(Token::LeftBrace) = self.next_token() {
+                        let body = self.parse_block()?;
+                        Ok(ASTNode::Function(func_name, Rc::new(FunctionDef { params, return_type, body, locals: Rc::default() })))
                     } else {
-                        return Err(Error::Syntax("Expected '(' after function".into()));
+                        Err(Error::Syntax("Expected '{' to start function body".into()))
                     }
+                } else {
+                    Err(Error::Syntax("Expected '(' after function".into()))
                 }
-                Token::Identifier(name) => {
-                    // 変数代入または関数呼び出し
-                    let id = self.next_token().unwrap(); // consume identifier
-                    if let Some(token) = self.peek_token() {
-                        match token {
-                            Token::Equals => {
-                                self.next_token(); // consume '='
+            }
+            Token::Identifier(name) => {
+                // 変数代入または関数呼び出し
+                self.next_token(); // consume identifier
+                if let Some(token) = self.peek_token() {
+                    match token {
+                        Token::Equals => {
+                            self.next_token(); // consume '='
+                            let expr = self.parse_expression()?;
+                            Ok(ASTNode::Variable(name, None, Box::new(expr), Binding::Dynamic))
+                        }
+                        Token::Colon => {
+                            // 型注釈付きの変数代入: x: num = expr
+                            self.next_token(); // consume ':'
+                            let type_name = self.parse_annotation()?;
+                            if let Some(Token::Equals) = self.next_token() {
                                 let expr = self.parse_expression()?;
-                                statements.push(ASTNode::Variable(name, Box::new(expr)));
+                                Ok(ASTNode::Variable(name, Some(type_name), Box::new(expr), Binding::Dynamic))
+                            } else {
+                                Err(Error::Syntax("Expected '=' after type annotation".into()))
                             }
-                            Token::LeftParen => {
-                                self.next_token(); // consume '('
-                                let args = self.parse_expression_list()?; // parse comma-separated expressions
-                                // 期待: ')'
-                                if let Some(Token::RightParen) = self.next_token() {
-                                    statements.push(ASTNode::FunctionCall(name, args));
-                                } else {
-                                    return Err(Error::Syntax("Expected ')' after function call arguments".into()));
-                                }
+                        }
+                        Token::LeftParen => {
+                            self.next_token(); // consume '('
+                            let args = self.parse_expression_list(Token::RightParen)?; // parse comma-separated expressions
+                            // 期待: ')'
+                            if let Some(Token::RightParen) = self.next_token() {
+                                Ok(ASTNode::FunctionCall(name, args))
+                            } else {
+                                Err(Error::Syntax("Expected ')' after function call arguments".into()))
                             }
-                            _ => {
-                                return Err(Error::Syntax("Expected '=' for variable assignment or '(' for function call after identifier".into()));
+                        }
+                        Token::Dot => {
+                            // メソッド呼び出し: x.f(args) / file.edit(...)
+                            match self.parse_postfix(Expr::Variable(name, Binding::Dynamic))? {
+                                Expr::MethodCall(object, method, args) => {
+                                    // ブロック付きの呼び出し: file.open(path) { ... }
+                                    if let Some(Token::LeftBrace) = self.peek_token() {
+                                        self.next_token(); // consume '{'
+                                        let body = self.parse_block()?;
+                                        let call = Expr::MethodCall(object, method, args);
+                                        Ok(ASTNode::With(Box::new(call), body))
+                                    } else {
+                                        Ok(ASTNode::MethodCall(object, method, args))
+                                    }
+                                }
+                                _ => Err(Error::Syntax("Expected method call after member access".into())),
                             }
                         }
-                    } else {
-                        return Err(Error::Syntax("Unexpected end of input after identifier".into()));
+                        _ => Err(Error::Syntax("Expected '=' for variable assignment or '(' for function call after identifier".into())),
                     }
+                } else {
+                    Err(Error::Syntax("Unexpected end of input after identifier".into()))
                 }
-                _ => return Err(Error::Syntax(format!("Unexpected token: {:?}", token))),
             }
+            Token::Return => {
+                self.next_token(); // consume 'return'
+                // return の後に式がなければ None を返す
+                let expr = match self.peek_token() {
+                    Some(Token::RightBrace) | None => Expr::Literal(Value::None),
+                    Some(_) => self.parse_expression()?,
+                };
+                Ok(ASTNode::Return(Box::new(expr)))
+            }
+            Token::Exit => {
+                self.next_token();
+                Ok(ASTNode::Exit)
+            }
+            _ => Err(Error::Syntax(format!("Unexpected token: {:?}", token))),
         }
-        Ok(ASTNode::Program(statements))
     }
 
-    // 簡易的な式解析：二項演算は左結合とする（加減乗算のみ対応）
+    // 式の解析：優先順位は低い順に or, and, not, 比較, 加減乗算
     fn parse_expression(&mut self) -> Result<Expr> {
-        let mut expr = self.parse_primary()?;
+        let mut expr = self.parse_and()?;
+        while let Some(Token::Or) = self.peek_token() {
+            self.next_token(); // consume 'or'
+            let right = self.parse_and()?;
+            expr = Expr::BinaryOp(Box::new(expr), "or".to_string(), Box::new(right));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_not()?;
+        while let Some(Token::And) = self.peek_token() {
+            self.next_token(); // consume 'and'
+            let right = self.parse_not()?;
+            expr = Expr::BinaryOp(Box::new(expr), "and".to_string(), Box::new(right));
+        }
+        Ok(expr)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr> {
+        if let Some(Token::Not) = self.peek_token() {
+            self.next_token(); // consume 'not'
+            let operand = self.parse_not()?;
+            return Ok(Expr::UnaryOp("not".to_string(), Box::new(operand)));
+        }
+        self.parse_comparison()
+    }
+
+    // 比較演算（==, !=, is, >, <）は加減乗算より優先順位が低い
+    fn parse_comparison(&mut self) -> Result<Expr> {
+        let expr = self.parse_additive()?;
+        let op = match self.peek_token() {
+            Some(Token::EqualEqual) => "==",
+            Some(Token::NotEqual) => "!=",
+            Some(Token::Is) => "is",
+            Some(Token::GreaterThan) => ">",
+            Some(Token::LessThan) => "<",
+            _ => return Ok(expr),
+        };
+        self.next_token(); // consume operator
+        let right = self.parse_additive()?;
+        Ok(Expr::BinaryOp(Box::new(expr), op.to_string(), Box::new(right)))
+    }
+
+    // 簡易的な式解析：二項演算は左結合とする（加減乗算のみ対応）
+    fn parse_additive(&mut self) -> Result<Expr> {
+        let expr = self.parse_primary()?;
+        let mut expr = self.parse_postfix(expr)?;
         while let Some(token) = self.peek_token() {
             match token {
                 Token::Plus | Token::Minus | Token::Multiply => {
@@ -203,6 +314,7 @@ impl Parser {
                     };
                     self.next_token(); // consume operator
                     let right = self.parse_primary()?;
+                    let right = self.parse_postfix(right)?;
                     expr = Expr::BinaryOp(Box::new(expr), op, Box::new(right));
                 }
                 _ => break,
@@ -219,9 +331,43 @@ impl Parser {
                     self.next_token();
                     Ok(Expr::Literal(Value::Number(n)))
                 }
+                Token::Float(f) => {
+                    self.next_token();
+                    Ok(Expr::Literal(Value::Float(f)))
+                }
+                // 負の数のリテラル（repr の出力を読み戻せるように）。二項演算の - とは式の先頭かどうかで区別する
+                Token::Minus => {
+                    self.next_token();
+                    match self.next_token() {
+                        Some(Token::Number(n)) => Ok(Expr::Literal(Value::Number(-n))),
+                        Some(Token::Float(f)) => Ok(Expr::Literal(Value::Float(-f))),
+                        _ => Err(Error::Syntax("Expected a number after '-'".into())),
+                    }
+                }
                 Token::Text(s) => {
                     self.next_token();
-                    Ok(Expr::Literal(Value::Text(s)))
+                    Ok(Expr::Literal(Value::text(s)))
+                }
+                Token::Boolean(b) => {
+                    self.next_token();
+                    Ok(Expr::Literal(Value::Boolean(b)))
+                }
+                Token::None => {
+                    self.next_token();
+                    Ok(Expr::Literal(Value::None))
+                }
+                Token::Colon => {
+                    // 型パターン: :num: など
+                    let type_name = self.parse_type_name()?;
+                    if Value::TYPE_NAMES.contains(&type_name.as_str()) {
+                        Ok(Expr::TypePattern(type_name))
+                    } else {
+                        Err(Error::Syntax(format!("Unknown type pattern: :{}:", type_name)))
+                    }
+                }
+                Token::Input => {
+                    self.next_token(); // consume 'input'
+                    self.parse_input()
                 }
                 Token::Identifier(name) => {
                     let ident = name.clone();
@@ -229,16 +375,30 @@ impl Parser {
                     // 関数呼び出しの場合、後ろに '(' が続く
                     if let Some(Token::LeftParen) = self.peek_token() {
                         self.next_token(); // consume '('
-                        let args = self.parse_expression_list()?;
+                        let args = self.parse_expression_list(Token::RightParen)?;
                         if let Some(Token::RightParen) = self.next_token() {
                             Ok(Expr::FunctionCall(ident, args))
                         } else {
                             Err(Error::Syntax("Expected ')' after function call arguments".into()))
                         }
                     } else {
-                        Ok(Expr::Variable(ident))
+                        Ok(Expr::Variable(ident, Binding::Dynamic))
                     }
                 }
+                Token::LeftBracket => {
+                    // 配列リテラル: [1, 2, 3]
+                    self.next_token(); // consume '['
+                    let items = self.parse_expression_list(Token::RightBracket)?;
+                    if let Some(Token::RightBracket) = self.next_token() {
+                        Ok(Expr::Array(items))
+                    } else {
+                        Err(Error::Syntax("Expected ']' after array items".into()))
+                    }
+                }
+                Token::LeftBrace => {
+                    self.next_token(); // consume '{'
+                    self.parse_map()
+                }
                 _ => Err(Error::Syntax(format!("Unexpected token in expression: {:?}", token))),
             }
         } else {
@@ -246,15 +406,125 @@ impl Parser {
         }
     }
 
-    // parse_expression_list: カンマ区切りの式リストを解析して Vec<Expr> を返す
-    fn parse_expression_list(&mut self) -> Result<Vec<Expr>> {
-        let mut args = Vec::new();
-        loop {
-            if let Some(token) = self.peek_token() {
-                if token == Token::RightParen {
-                    break;
+    // parse_postfix: 式の後に続くメンバーアクセス a.b とメソッド呼び出し a.f(args) を解析する
+    fn parse_postfix(&mut self, mut expr: Expr) -> Result<Expr> {
+        while let Some(Token::Dot) = self.peek_token() {
+            self.next_token(); // consume '.'
+            let member = match self.next_token() {
+                Some(Token::Identifier(member)) => member,
+                _ => return Err(Error::Syntax("Expected member name after '.'".into())),
+            };
+            if let Some(Token::LeftParen) = self.peek_token() {
+                self.next_token(); // consume '('
+                let args = self.parse_expression_list(Token::RightParen)?;
+                if let Some(Token::RightParen) = self.next_token() {
+                    expr = Expr::MethodCall(Box::new(expr), member, args);
+                } else {
+                    return Err(Error::Syntax("Expected ')' after method call arguments".into()));
                 }
             } else {
+                expr = Expr::Member(Box::new(expr), member);
+            }
+        }
+        Ok(expr)
+    }
+
+    // parse_map: マップリテラル {name: "elium", "a key": 1} を解析する（'{' は消費済み）
+    fn parse_map(&mut self) -> Result<Expr> {
+        let mut entries = Vec::new();
+        loop {
+            let key = match self.next_token() {
+                Some(Token::RightBrace) => break,
+                Some(Token::Identifier(key)) | Some(Token::Text(key)) => key,
+                Some(token) => return Err(Error::Syntax(format!("Expected map key, but got {:?}", token))),
+                None => return Err(Error::UnexpectedEOF),
+            };
+            if self.next_token() != Some(Token::Colon) {
+                return Err(Error::Syntax(format!("Expected ':' after map key {}", key)));
+            }
+            entries.push((key, self.parse_expression()?));
+            match self.next_token() {
+                Some(Token::Comma) => continue,
+                Some(Token::RightBrace) => break,
+                _ => return Err(Error::Syntax("Expected ',' or '}' after map entry".into())),
+            }
+        }
+        Ok(Expr::Map(entries))
+    }
+
+    // parse_dotted_name: com.hello のようなドット区切りの名前を解析する（先頭の名前は消費済み）
+    fn parse_dotted_name(&mut self, first: String) -> Result<String> {
+        let mut name = first;
+        while let Some(Token::Dot) = self.peek_token() {
+            self.next_token(); // consume '.'
+            if let Some(Token::Identifier(part)) = self.next_token() {
+                name.push('.');
+                name.push_str(&part);
+            } else {
+                return Err(Error::Syntax(format!("Expected name after '{}.'", name)));
+            }
+        }
+        Ok(name)
+    }
+
+    // parse_input: input(prompt) または input(prompt, :num:) を解析する
+    fn parse_input(&mut self) -> Result<Expr> {
+        if let Some(Token::LeftParen) = self.next_token() {
+            let prompt = self.parse_expression()?;
+            let type_name = if let Some(Token::Comma) = self.peek_token() {
+                self.next_token(); // consume ','
+                Some(self.parse_type_name()?)
+            } else {
+                None
+            };
+            if let Some(Token::RightParen) = self.next_token() {
+                Ok(Expr::Input(Box::new(prompt), type_name))
+            } else {
+                Err(Error::Syntax("Expected ')' after input arguments".into()))
+            }
+        } else {
+            Err(Error::Syntax("Expected '(' after input".into()))
+        }
+    }
+
+    // parse_type_name: :num: のような型名を解析して型名の文字列を返す
+    fn parse_type_name(&mut self) -> Result<String> {
+        if let Some(Token::Colon) = self.next_token() {
+            // none はキーワードだが型名としても書ける
+            let name = match self.next_token() {
+                Some(Token::Identifier(name)) => Some(name),
+                Some(Token::None) => Some("none".to_string()),
+                _ => None,
+            };
+            if let Some(name) = name {
+                if let Some(Token::Colon) = self.next_token() {
+                    return Ok(name);
+                }
+            }
+        }
+        Err(Error::Syntax("Expected type pattern like :num:".into()))
+    }
+
+    // parse_annotation: 型注釈の型名（num, text, any など）を解析する
+    fn parse_annotation(&mut self) -> Result<String> {
+        match self.next_token() {
+            Some(Token::None) => Ok("none".to_string()),
+            Some(Token::Identifier(name)) => {
+                if name == "any" || Value::TYPE_NAMES.contains(&name.as_str()) {
+                    Ok(name)
+                } else {
+                    Err(Error::Syntax(format!("Unknown type in annotation: {}", name)))
+                }
+            }
+            _ => Err(Error::Syntax("Expected type name in annotation".into())),
+        }
+    }
+
+    // parse_expression_list: カンマ区切りの式リストを解析して Vec<Expr> を返す（end の手前まで。end は消費しない）
+    fn parse_expression_list(&mut self, end: Token) -> Result<Vec<Expr>> {
+        let mut args = Vec::new();
+        while let Some(token) = self.peek_token() {
+            if token == end {
                 break;
             }
             let expr = self.parse_expression()?;
@@ -273,52 +543,39 @@ impl Parser {
         Ok(args)
     }
 
-    // parse_block: '{' ... '}' の中の文を解析する
+    // parse_block: '{' ... '}' の中の文を解析する（'{' は消費済み）
     fn parse_block(&mut self) -> Result<Vec<ASTNode>> {
         let mut stmts = Vec::new();
-        while let Some(token) = self.peek_token() {
-            if token == Token::RightBrace {
-                self.next_token(); // consume '}'
-                break;
-            }
-            // 文ごとに parse_expression() やその他の文解析を呼び出す
-            // ここでは簡単のため、変数代入、msg などを parse() の処理と同様に行う
-            match token {
-                Token::Identifier(name) => {
-                    let id = self.next_token().unwrap(); // consume identifier
-                    if let Some(tok) = self.peek_token() {
-                        match tok {
-                            Token::Equals => {
-                                self.next_token(); // consume '='
-                                let expr = self.parse_expression()?;
-                                stmts.push(ASTNode::Variable(name.clone(), Box::new(expr)));
-                            }
-                            Token::LeftParen => {
-                                self.next_token(); // consume '('
-                                let args = self.parse_expression_list()?;
-                                if let Some(Token::RightParen) = self.next_token() {
-                                    stmts.push(ASTNode::FunctionCall(name.clone(), args));
-                                } else {
-                                    return Err(Error::Syntax("Expected ')' in function call".into()));
-                                }
-                            }
-                            _ => return Err(Error::Syntax("Unexpected token in block after identifier".into())),
-                        }
-                    } else {
-                        return Err(Error::Syntax("Unexpected end of input in block".into()));
-                    }
-                }
-                Token::Msg => {
-                    self.next_token();
-                    if let Some(Token::Text(msg)) = self.next_token() {
-                        stmts.push(ASTNode::Msg(msg));
-                    } else {
-                        return Err(Error::Syntax("Expected message string in block".into()));
-                    }
+        loop {
+            match self.peek_token() {
+                Some(Token::RightBrace) => {
+                    self.next_token(); // consume '}'
+                    return Ok(stmts);
                 }
-                _ => return Err(Error::Syntax(format!("Unexpected token in block: {:?}", token))),
+                Some(_) => stmts.push(self.parse_statement()?),
+                None => return Err(Error::UnexpectedEOF),
             }
         }
-        Ok(stmts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dotted_names_and_member_access() {
+        let stmts = parse_source("package com.hello\nimport com.hello.world\nx = a.b.c\na.b.push(1)\n").unwrap();
+        assert!(matches!(&stmts[0], ASTNode::Package(name) if name == "com.hello"), "{:?}", stmts[0]);
+        assert!(matches!(&stmts[1], ASTNode::Import(name, None) if name == "com.hello.world"), "{:?}", stmts[1]);
+        // a.b.c は左から順にメンバーを取り出す
+        let ASTNode::Variable(_, _, value, _) = &stmts[2] else { panic!("{:?}", stmts[2]) };
+        let Expr::Member(inner, c) = &**value else { panic!("{:?}", value) };
+        assert_eq!(c, "c");
+        assert!(matches!(&**inner, Expr::Member(a, b) if b == "b" && matches!(&**a, Expr::Variable(a, _) if a == "a")), "{:?}", inner);
+        let ASTNode::MethodCall(object, method, args) = &stmts[3] else { panic!("{:?}", stmts[3]) };
+        assert_eq!((method.as_str(), args.len()), ("push", 1));
+        assert!(matches!(&**object, Expr::Member(_, b) if b == "b"), "{:?}", object);
+        assert!(parse_source("package com.\n").is_err());
     }
 }
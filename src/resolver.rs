@@ -0,0 +1,554 @@
+// src/resolver.rs
+
+use crate::ast::{ASTNode, Expr, FunctionDef};
+use crate::builtins;
+use crate::interpreter::expr_path;
+use crate::utils::error::{Error, Result};
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
+use std::rc::Rc;
+
+/// 変数の参照の解決結果。Local は実行中の関数のフレーム（深さ 0）のスロット、
+/// Global はトップレベル（いちばん外側）のスロット。どちらとも決まらない名前は Dynamic で、実行時に
+/// 呼び出し中の関数のフレームを内側から順に名前で探す（動的スコープなので、どのフレームかは実行するまで決まらない）。
+/// コンパイラは命令に、木構造の評価器は annotate で構文木に書き込んだものを使う
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Binding {
+    // 関数のローカル変数。まだ代入されていなければ呼び出し元の変数を名前で探す（動的スコープ）
+    Local(u32),
+    // トップレベルの変数（どの関数のローカル変数とも名前が重ならないもの）
+    Global(u32),
+    // 呼び出し元の関数のローカル変数でありうる名前、関数への参照、組み込み関数の名前
+    Dynamic,
+}
+
+/// 関数のローカル変数の配置：スロットの順の名前と、名前からスロットへの索引
+/// （Dynamic の名前を呼び出し元のフレームで探すときに使う）
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Layout {
+    names: Vec<String>,
+    slots: HashMap<String, u32>,
+}
+
+impl Layout {
+    pub fn new(names: Vec<String>) -> Self {
+        let slots = names.iter().enumerate().map(|(slot, name)| (name.clone(), slot as u32)).collect();
+        Self { names, slots }
+    }
+
+    /// 名前のスロット
+    pub fn slot(&self, name: &str) -> Option<usize> {
+        self.slots.get(name).map(|&slot| slot as usize)
+    }
+}
+
+// スロットの順の名前として読める
+impl Deref for Layout {
+    type Target = [String];
+
+    fn deref(&self) -> &[String] {
+        &self.names
+    }
+}
+
+/// プログラム全体の変数の配置。トップレベルの変数にスロットを割り当て、関数の中の名前を解決する
+#[derive(Debug, Clone)]
+pub struct Resolver {
+    // トップレベルの変数（スロットの順）
+    globals: Vec<String>,
+    global_slots: HashMap<String, u32>,
+    // いずれかの関数の引数またはローカル変数になっている名前（呼び出し先からは動的に見える）
+    shadowed: HashSet<String>,
+}
+
+impl Resolver {
+    pub fn new(stmts: &[ASTNode]) -> Self {
+        let mut globals = Vec::new();
+        collect_locals(stmts, &mut globals);
+        let global_slots = globals.iter().enumerate().map(|(slot, name)| (name.clone(), slot as u32)).collect();
+        let mut shadowed = HashSet::new();
+        for_each_function(stmts, &mut |_, def| shadowed.extend(function_locals(def).iter().cloned()));
+        Self { globals, global_slots, shadowed }
+    }
+
+    /// トップレベルの変数の名前（スロットの順）
+    pub fn globals(&self) -> &[String] {
+        &self.globals
+    }
+
+    /// 名前を解決する。locals は関数の中ならその関数のローカル変数、トップレベルなら None
+    pub fn bind(&self, name: &str, locals: Option<&Layout>) -> Binding {
+        if let Some(slot) = locals.and_then(|locals| locals.slot(name)) {
+            return Binding::Local(slot as u32);
+        }
+        match self.global_slots.get(name) {
+            // 関数の中では、呼び出し元のローカル変数が同じ名前のトップレベルの変数を隠しうる
+            Some(&slot) if locals.is_none() || !self.shadowed.contains(name) => Binding::Global(slot),
+            _ => Binding::Dynamic,
+        }
+    }
+}
+
+/// 関数のローカル変数（引数と、本体で値を書き込む変数）をスロットの順に並べる
+pub fn function_locals(def: &FunctionDef) -> Layout {
+    let mut locals: Vec<String> = def.params.iter().map(|(param, _)| param.clone()).collect();
+    collect_locals(&def.body, &mut locals);
+    Layout::new(locals)
+}
+
+/// 木構造の評価器のために、変数の参照と代入の解決結果を構文木に書き込み、関数の定義にローカル変数の
+/// 配置を持たせる。トップレベルの変数は global_slot で評価器のスコープのスロットに付け替える
+pub fn annotate(stmts: &mut [ASTNode], global_slot: &mut dyn FnMut(&str) -> u32) {
+    let resolver = Resolver::new(stmts);
+    let globals: Vec<u32> = resolver.globals.iter().map(|name| global_slot(name)).collect();
+    Annotator { resolver: &resolver, globals: &globals }.block(stmts, None);
+}
+
+struct Annotator<'a> {
+    resolver: &'a Resolver,
+    // プログラムのトップレベルの変数のスロット → スコープのスロット
+    globals: &'a [u32],
+}
+
+impl Annotator<'_> {
+    fn bind(&self, name: &str, locals: Option<&Layout>) -> Binding {
+        match self.resolver.bind(name, locals) {
+            Binding::Global(slot) => Binding::Global(self.globals[slot as usize]),
+            other => other,
+        }
+    }
+
+    // locals は関数の中ならその関数のローカル変数、トップレベルなら None
+    fn block(&self, stmts: &mut [ASTNode], locals: Option<&Layout>) {
+        for stmt in stmts {
+            match stmt {
+                ASTNode::Variable(name, _, expr, binding) => {
+                    self.expr(expr, locals);
+                    *binding = self.bind(name, locals);
+                }
+                ASTNode::If(condition, then_body, else_body) => {
+                    self.expr(condition, locals);
+                    self.block(then_body, locals);
+                    self.block(else_body, locals);
+                }
+                ASTNode::With(open, body) => {
+                    self.expr(open, locals);
+                    self.block(body, locals);
+                }
+                ASTNode::Function(_, def) => {
+                    // 定義を共有していれば複製してから書き込む
+                    let def = Rc::make_mut(def);
+                    let layout = function_locals(def);
+                    self.block(&mut def.body, Some(&layout));
+                    def.locals = Rc::new(layout);
+                }
+                ASTNode::FunctionCall(_, args) => self.exprs(args, locals),
+                ASTNode::MethodCall(object, _, args) => {
+                    self.expr(object, locals);
+                    self.exprs(args, locals);
+                }
+                ASTNode::Return(expr) => self.expr(expr, locals),
+                _ => {}
+            }
+        }
+    }
+
+    fn exprs(&self, exprs: &mut [Expr], locals: Option<&Layout>) {
+        for expr in exprs {
+            self.expr(expr, locals);
+        }
+    }
+
+    fn expr(&self, expr: &mut Expr, locals: Option<&Layout>) {
+        match expr {
+            Expr::Variable(name, binding) => *binding = self.bind(name, locals),
+            Expr::BinaryOp(left, _, right) => {
+                self.expr(left, locals);
+                self.expr(right, locals);
+            }
+            Expr::UnaryOp(_, operand) | Expr::Input(operand, _) | Expr::Member(operand, _) => self.expr(operand, locals),
+            Expr::MethodCall(object, _, args) => {
+                self.expr(object, locals);
+                self.exprs(args, locals);
+            }
+            Expr::FunctionCall(_, items) | Expr::Array(items) => self.exprs(items, locals),
+            Expr::Map(entries) => {
+                for (_, value) in entries {
+                    self.expr(value, locals);
+                }
+            }
+            Expr::Literal(_) | Expr::TypePattern(_) => {}
+        }
+    }
+}
+
+// 文のリストの中の関数定義（入れ子の定義も含む）を順に渡す
+fn for_each_function(stmts: &[ASTNode], f: &mut dyn FnMut(&str, &FunctionDef)) {
+    for stmt in stmts {
+        match stmt {
+            ASTNode::Function(name, def) => {
+                f(name, def);
+                for_each_function(&def.body, f);
+            }
+            ASTNode::If(_, then_body, else_body) => {
+                for_each_function(then_body, f);
+                for_each_function(else_body, f);
+            }
+            ASTNode::With(_, body) => for_each_function(body, f),
+            _ => {}
+        }
+    }
+}
+
+// 本体で値を書き込む変数（代入と、変数に対する xs.push(x) などの書き戻し）を集める。
+// 入れ子の関数定義の中は別の関数なので含めない
+fn collect_locals(stmts: &[ASTNode], locals: &mut Vec<String>) {
+    for stmt in stmts {
+        match stmt {
+            ASTNode::Variable(name, _, expr, _) => {
+                collect_expr_locals(expr, locals);
+                add_local(name, locals);
+            }
+            ASTNode::If(condition, then_body, else_body) => {
+                collect_expr_locals(condition, locals);
+                collect_locals(then_body, locals);
+                collect_locals(else_body, locals);
+            }
+            ASTNode::With(open, body) => {
+                collect_expr_locals(open, locals);
+                collect_locals(body, locals);
+            }
+            ASTNode::MethodCall(object, _, args) => collect_method_locals(object, args, locals),
+            ASTNode::FunctionCall(_, args) => {
+                for arg in args {
+                    collect_expr_locals(arg, locals);
+                }
+            }
+            ASTNode::Return(expr) => collect_expr_locals(expr, locals),
+            _ => {}
+        }
+    }
+}
+
+// 式の中のメソッド呼び出しで書き戻す変数を集める
+fn collect_expr_locals(expr: &Expr, locals: &mut Vec<String>) {
+    match expr {
+        Expr::MethodCall(object, _, args) => collect_method_locals(object, args, locals),
+        Expr::BinaryOp(left, _, right) => {
+            collect_expr_locals(left, locals);
+            collect_expr_locals(right, locals);
+        }
+        Expr::UnaryOp(_, operand) | Expr::Input(operand, _) | Expr::Member(operand, _) => {
+            collect_expr_locals(operand, locals)
+        }
+        Expr::FunctionCall(_, items) | Expr::Array(items) => {
+            for item in items {
+                collect_expr_locals(item, locals);
+            }
+        }
+        Expr::Map(entries) => {
+            for (_, value) in entries {
+                collect_expr_locals(value, locals);
+            }
+        }
+        Expr::Literal(_) | Expr::Variable(..) | Expr::TypePattern(_) => {}
+    }
+}
+
+// メソッド呼び出しの対象が変数なら、書き換えた値を書き戻す変数として加える
+fn collect_method_locals(object: &Expr, args: &[Expr], locals: &mut Vec<String>) {
+    match object {
+        Expr::Variable(name, _) => add_local(name, locals),
+        other => collect_expr_locals(other, locals),
+    }
+    for arg in args {
+        collect_expr_locals(arg, locals);
+    }
+}
+
+fn add_local(name: &str, locals: &mut Vec<String>) {
+    if !locals.iter().any(|local| local == name) {
+        locals.push(name.to_string());
+    }
+}
+
+/// 名前を静的に検査する。どこにも定義のない変数の参照と、トップレベルで代入より前に
+/// 読む変数を Error::Name としてまとめて返す（関数の中の名前は呼び出し元の変数でありうるので、
+/// プログラムのどこかで定義されていればよい）
+pub fn check(stmts: &[ASTNode]) -> Result<()> {
+    let resolver = Resolver::new(stmts);
+    let mut natives = HashMap::new();
+    builtins::register(&mut natives);
+    let mut callables: HashSet<String> = natives.into_keys().collect();
+    for_each_function(stmts, &mut |name, _| {
+        callables.insert(name.to_string());
+    });
+    let mut aliases = HashSet::new();
+    collect_aliases(stmts, &mut aliases);
+    let mut checker = Checker {
+        globals: resolver.globals.iter().cloned().collect(),
+        shadowed: resolver.shadowed,
+        callables,
+        aliases,
+        errors: Vec::new(),
+    };
+    checker.check_top_level(stmts, &mut HashSet::new());
+    if checker.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Name(checker.errors.join("\n")))
+    }
+}
+
+// import した名前空間の名前
+fn collect_aliases(stmts: &[ASTNode], aliases: &mut HashSet<String>) {
+    for stmt in stmts {
+        match stmt {
+            ASTNode::Import(package, target) => {
+                let alias = target.as_deref().unwrap_or_else(|| package.rsplit('.').next().unwrap_or(package));
+                aliases.insert(alias.to_string());
+            }
+            ASTNode::If(_, then_body, else_body) => {
+                collect_aliases(then_body, aliases);
+                collect_aliases(else_body, aliases);
+            }
+            ASTNode::With(_, body) => collect_aliases(body, aliases),
+            ASTNode::Function(_, def) => collect_aliases(&def.body, aliases),
+            _ => {}
+        }
+    }
+}
+
+struct Checker {
+    globals: HashSet<String>,
+    shadowed: HashSet<String>,
+    // 関数の名前と組み込み関数の名前（変数としては関数への参照になる）
+    callables: HashSet<String>,
+    aliases: HashSet<String>,
+    errors: Vec<String>,
+}
+
+impl Checker {
+    // トップレベルの文を実行順に検査する。assigned は代入されている可能性のある変数。
+    // return / exit の後は実行されないので検査しない
+    fn check_top_level(&mut self, stmts: &[ASTNode], assigned: &mut HashSet<String>) {
+        for stmt in stmts {
+            match stmt {
+                ASTNode::Variable(name, _, expr, _) => {
+                    self.check_expr(expr, Some(assigned));
+                    assigned.insert(name.clone());
+                }
+                ASTNode::If(condition, then_body, else_body) => {
+                    self.check_expr(condition, Some(assigned));
+                    let mut after_then = assigned.clone();
+                    self.check_top_level(then_body, &mut after_then);
+                    self.check_top_level(else_body, assigned);
+                    assigned.extend(after_then);
+                }
+                ASTNode::With(open, body) => {
+                    self.check_expr(open, Some(assigned));
+                    self.check_top_level(body, assigned);
+                }
+                ASTNode::Function(_, def) => self.check_function(def),
+                ASTNode::FunctionCall(_, args) => {
+                    for arg in args {
+                        self.check_expr(arg, Some(assigned));
+                    }
+                }
+                ASTNode::MethodCall(object, _, args) => self.check_method_call(object, args, Some(assigned)),
+                ASTNode::Return(expr) => {
+                    self.check_expr(expr, Some(assigned));
+                    return;
+                }
+                ASTNode::Exit => return,
+                _ => {}
+            }
+        }
+    }
+
+    fn check_function(&mut self, def: &FunctionDef) {
+        self.check_function_block(&def.body);
+    }
+
+    fn check_function_block(&mut self, stmts: &[ASTNode]) {
+        for stmt in stmts {
+            match stmt {
+                ASTNode::Variable(_, _, expr, _) | ASTNode::Return(expr) => self.check_expr(expr, None),
+                ASTNode::If(condition, then_body, else_body) => {
+                    self.check_expr(condition, None);
+                    self.check_function_block(then_body);
+                    self.check_function_block(else_body);
+                }
+                ASTNode::With(open, body) => {
+                    self.check_expr(open, None);
+                    self.check_function_block(body);
+                }
+                ASTNode::Function(_, def) => self.check_function(def),
+                ASTNode::FunctionCall(_, args) => {
+                    for arg in args {
+                        self.check_expr(arg, None);
+                    }
+                }
+                ASTNode::MethodCall(object, _, args) => self.check_method_call(object, args, None),
+                _ => {}
+            }
+        }
+    }
+
+    fn check_method_call(&mut self, object: &Expr, args: &[Expr], assigned: Option<&HashSet<String>>) {
+        self.check_object(object, assigned);
+        for arg in args {
+            self.check_expr(arg, assigned);
+        }
+    }
+
+    // メンバーアクセスやメソッド呼び出しの対象。import した名前空間の名前は変数として検査しない
+    fn check_object(&mut self, object: &Expr, assigned: Option<&HashSet<String>>) {
+        let is_namespace = expr_path(object).is_some_and(|path| self.aliases.contains(path[0]));
+        if !is_namespace {
+            self.check_expr(object, assigned);
+        }
+    }
+
+    // assigned はトップレベルで代入されている可能性のある変数（関数の中では None）
+    fn check_expr(&mut self, expr: &Expr, assigned: Option<&HashSet<String>>) {
+        match expr {
+            Expr::Variable(name, _) => self.check_name(name, assigned),
+            Expr::Member(object, _) => self.check_object(object, assigned),
+            Expr::MethodCall(object, _, args) => self.check_method_call(object, args, assigned),
+            Expr::BinaryOp(left, _, right) => {
+                self.check_expr(left, assigned);
+                self.check_expr(right, assigned);
+            }
+            Expr::UnaryOp(_, operand) | Expr::Input(operand, _) => self.check_expr(operand, assigned),
+            Expr::FunctionCall(_, items) | Expr::Array(items) => {
+                for item in items {
+                    self.check_expr(item, assigned);
+                }
+            }
+            Expr::Map(entries) => {
+                for (_, value) in entries {
+                    self.check_expr(value, assigned);
+                }
+            }
+            Expr::Literal(_) | Expr::TypePattern(_) => {}
+        }
+    }
+
+    fn check_name(&mut self, name: &str, assigned: Option<&HashSet<String>>) {
+        if self.callables.contains(name) {
+            return;
+        }
+        let error = match assigned {
+            // トップレベルでは関数のローカル変数は見えない
+            Some(assigned) if assigned.contains(name) => return,
+            Some(_) if self.globals.contains(name) => format!("Variable {} is used before assignment", name),
+            None if self.globals.contains(name) || self.shadowed.contains(name) => return,
+            _ => format!("Undefined variable: {}", name),
+        };
+        if !self.errors.contains(&error) {
+            self.errors.push(error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn names(source: &str) -> std::result::Result<(), String> {
+        check(&parser::parse_source(source).unwrap()).map_err(|err| match err {
+            Error::Name(message) => message,
+            other => panic!("expected a name error, got {:?}", other),
+        })
+    }
+
+    #[test]
+    fn undefined_variables_are_reported() {
+        assert_eq!(names("y = x + 1\n"), Err("Undefined variable: x".to_string()));
+        assert_eq!(
+            names("function (name=f) {\n    return missing\n}\n"),
+            Err("Undefined variable: missing".to_string())
+        );
+        // 同じ誤りは 1 回だけ、違う誤りはまとめて報告する
+        assert_eq!(names("a = x\nb = x\nc = y\n"), Err("Undefined variable: x\nUndefined variable: y".to_string()));
+        // 関数、組み込み関数、import した名前空間は変数として使える
+        names("import math\nfunction (name=f) {\n    return 1\n}\ng = f\nh = len\nr = math.sqrt(4.0)\n").unwrap();
+    }
+
+    #[test]
+    fn use_before_assignment_is_reported() {
+        assert_eq!(names("y = x\nx = 1\n"), Err("Variable x is used before assignment".to_string()));
+        // 関数の中からは、後で代入されるトップレベルの変数も呼び出し元のローカル変数も見える
+        names("function (name=f) {\n    return later + seen\n}\nfunction (name=g, seen) {\n    return f()\n}\nlater = 1\n").unwrap();
+        // return の後は実行されない
+        names("return 0\ny = x\nx = 1\n").unwrap();
+    }
+
+    #[test]
+    fn branches_merge_assignments() {
+        // どちらかの分岐で代入されていれば、後の文では代入されている可能性がある
+        names("c = true\nif (c) {\n    x = 1\n} else {\n    x = 2\n}\ny = x\n").unwrap();
+        names("c = true\nif (c) {\n    x = 1\n}\ny = x\n").unwrap();
+        names("c = true\nif (c) {\n    msg \"no\"\n} else {\n    x = 2\n}\ny = x\n").unwrap();
+        // then の分岐での代入は else の分岐からは見えない
+        assert_eq!(
+            names("c = true\nif (c) {\n    x = 1\n} else {\n    y = x\n}\n"),
+            Err("Variable x is used before assignment".to_string())
+        );
+        // 条件は分岐より先に評価する
+        assert_eq!(
+            names("if (x > 0) {\n    x = 1\n}\n"),
+            Err("Variable x is used before assignment".to_string())
+        );
+    }
+
+    #[test]
+    fn bindings_use_slots_where_the_name_is_fixed() {
+        let stmts = parser::parse_source("a = 1\nb = 2\nfunction (name=f, b) {\n    c = a + b\n    return c\n}\n").unwrap();
+        let resolver = Resolver::new(&stmts);
+        assert_eq!(resolver.globals(), ["a", "b"]);
+        let ASTNode::Function(_, def) = &stmts[2] else {
+            panic!("expected a function definition");
+        };
+        let locals = function_locals(def);
+        assert_eq!(*locals, ["b", "c"]);
+        assert_eq!(resolver.bind("b", Some(&locals)), Binding::Local(0));
+        assert_eq!(resolver.bind("a", Some(&locals)), Binding::Global(0));
+        assert_eq!(resolver.bind("a", None), Binding::Global(0));
+        assert_eq!(resolver.bind("b", None), Binding::Global(1));
+        // f の引数 b は、f が呼び出す関数からはトップレベルの b を隠しうる
+        assert_eq!(resolver.bind("b", Some(&Layout::default())), Binding::Dynamic);
+        assert_eq!(resolver.bind("f", None), Binding::Dynamic);
+    }
+
+    #[test]
+    fn annotate_writes_slots_into_the_tree() {
+        let mut stmts = parser::parse_source(
+            "a = 1\nb = 2\nfunction (name=f, b) {\n    c = a + b\n    return c\n}\nfunction (name=g) {\n    return b\n}\n",
+        )
+        .unwrap();
+        // トップレベルの変数は評価器のスコープのスロットに付け替える
+        annotate(&mut stmts, &mut |name| if name == "a" { 7 } else { 3 });
+        assert!(matches!(&stmts[0], ASTNode::Variable(_, _, _, Binding::Global(7))), "{:?}", stmts[0]);
+        assert!(matches!(&stmts[1], ASTNode::Variable(_, _, _, Binding::Global(3))), "{:?}", stmts[1]);
+        let ASTNode::Function(_, f) = &stmts[2] else {
+            panic!("expected a function definition");
+        };
+        assert_eq!(**f.locals, ["b", "c"]);
+        let ASTNode::Variable(_, _, sum, Binding::Local(1)) = &f.body[0] else {
+            panic!("c is not local slot 1: {:?}", f.body[0]);
+        };
+        let Expr::BinaryOp(a, _, b) = &**sum else {
+            panic!("expected a + b: {:?}", sum);
+        };
+        assert!(matches!(**a, Expr::Variable(_, Binding::Global(7))), "{:?}", a);
+        assert!(matches!(**b, Expr::Variable(_, Binding::Local(0))), "{:?}", b);
+        // g の b は呼び出し元の f の引数でありうるので、実行時に名前で探す
+        let ASTNode::Function(_, g) = &stmts[3] else {
+            panic!("expected a function definition");
+        };
+        assert!(matches!(&g.body[0], ASTNode::Return(b) if matches!(**b, Expr::Variable(_, Binding::Dynamic))));
+    }
+}
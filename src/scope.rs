@@ -1,29 +1,77 @@
 // src/scope.rs
 
 use std::collections::HashMap;
+use std::rc::Rc;
 use crate::ast::Value;
+use crate::intern::intern;
 
-#[derive(Debug, Clone)]
+/// トップレベルの変数のスコープ。変数は名前ごとのスロットに置き、名前でもスロットの番号でも読み書きできる
+/// （どちらの実行方式も、解決した変数はスロットの番号で扱う）。変数名は共有した名前（intern）を使う
+#[derive(Debug, Clone, Default)]
 pub struct Scope {
-    parent: Option<Box<Scope>>,
-    variables: HashMap<String, Value>,
+    // 変数名 → スロット
+    slots: HashMap<Rc<str>, usize>,
+    // スロットの変数名と値（まだ代入されていない変数は None）
+    names: Vec<Rc<str>>,
+    values: Vec<Option<Value>>,
 }
 
 impl Scope {
-    pub fn new(parent: Option<Scope>) -> Self {
-        Self {
-            parent: parent.map(Box::new),
-            variables: HashMap::new(),
-        }
+    pub fn new() -> Self {
+        Self::default()
     }
 
     pub fn get(&self, name: &str) -> Option<Value> {
-        self.variables.get(name).cloned().or_else(|| {
-            self.parent.as_ref().and_then(|parent_scope| parent_scope.get(name))
-        })
+        self.slots.get(name).and_then(|&slot| self.values[slot].clone())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.slots.get(name).is_some_and(|&slot| self.values[slot].is_some())
+    }
+
+    /// 変数のスロット（なければ値のないスロットを作る）
+    pub fn slot(&mut self, name: &str) -> usize {
+        if let Some(&slot) = self.slots.get(name) {
+            return slot;
+        }
+        let name = intern(name);
+        self.slots.insert(name.clone(), self.names.len());
+        self.names.push(name);
+        self.values.push(None);
+        self.names.len() - 1
+    }
+
+    /// スロットの値
+    pub fn get_slot(&self, slot: usize) -> Option<&Value> {
+        self.values[slot].as_ref()
+    }
+
+    pub fn set_slot(&mut self, slot: usize, value: Value) {
+        self.values[slot] = Some(value);
+    }
+
+    /// スロットの値の置き場所
+    pub fn slot_mut(&mut self, slot: usize) -> &mut Option<Value> {
+        &mut self.values[slot]
+    }
+
+    pub fn slot_name(&self, slot: usize) -> &str {
+        &self.names[slot]
+    }
+
+    /// 変数の値
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.values.iter().flatten()
     }
 
-    pub fn set(&mut self, name: String, value: Value) {
-        self.variables.insert(name, value);
+    /// 変数を名前順に並べた一覧
+    pub fn variables(&self) -> Vec<(&str, &Value)> {
+        let mut variables: Vec<_> = self.names
+            .iter()
+            .zip(&self.values)
+            .filter_map(|(name, value)| Some((&**name, value.as_ref()?)))
+            .collect();
+        variables.sort_by(|a, b| a.0.cmp(b.0));
+        variables
     }
 }
@@ -0,0 +1,306 @@
+// src/stdlib/capabilities.rs
+
+use crate::interpreter::Interpreter;
+use crate::utils::error::{Error, Result};
+use std::path::{Path, PathBuf};
+
+/// 組み込み関数が使う資源
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Capability {
+    // 環境変数とカレントディレクトリ、実行環境の情報（os.env、os.set_env、os.cwd、os.platform）
+    Env,
+    // プロセスの操作と引数（os.exit、exit、os.args）
+    Process,
+    // ネットワーク（ネットワークを使う標準モジュールは使う前にこれを検査する）
+    Network,
+    // 時計（os.time）
+    Clock,
+    // 標準入力（input）
+    Stdin,
+}
+
+impl Capability {
+    /// すべての資源
+    pub const ALL: [Capability; 5] =
+        [Capability::Env, Capability::Process, Capability::Network, Capability::Clock, Capability::Stdin];
+
+    /// 資源の名前（eliumw の --allow-<名前>）
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::Env => "env",
+            Capability::Process => "process",
+            Capability::Network => "net",
+            Capability::Clock => "clock",
+            Capability::Stdin => "stdin",
+        }
+    }
+
+    /// 名前から資源を探す
+    pub fn named(name: &str) -> Option<Capability> {
+        Capability::ALL.into_iter().find(|capability| capability.name() == name)
+    }
+}
+
+/// ファイルへのアクセスの種類
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl Access {
+    /// アクセスの名前（eliumw の --allow-<名前>）
+    pub fn name(self) -> &'static str {
+        match self {
+            Access::Read => "read",
+            Access::Write => "write",
+        }
+    }
+}
+
+/// 組み込み関数の使用許可。埋め込み先のホストは許可しない資源を外す
+/// （既定はすべて許可し、Capabilities::none() はすべて許可しない）
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    // 読めるファイルの場所（この下のパスだけを読める。None ならどこでも読める）
+    pub read: Option<Vec<PathBuf>>,
+    // 書けるファイルの場所（作成、書き込み、削除、名前の変更）
+    pub write: Option<Vec<PathBuf>>,
+    pub env: bool,
+    pub process: bool,
+    pub network: bool,
+    pub clock: bool,
+    pub stdin: bool,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self {
+            read: None,
+            write: None,
+            env: true,
+            process: true,
+            network: true,
+            clock: true,
+            stdin: true,
+        }
+    }
+}
+
+impl Capabilities {
+    /// 何も許可しない
+    pub fn none() -> Self {
+        Self {
+            read: Some(Vec::new()),
+            write: Some(Vec::new()),
+            env: false,
+            process: false,
+            network: false,
+            clock: false,
+            stdin: false,
+        }
+    }
+
+    /// 資源の使用が許可されているか
+    pub fn allows(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Env => self.env,
+            Capability::Process => self.process,
+            Capability::Network => self.network,
+            Capability::Clock => self.clock,
+            Capability::Stdin => self.stdin,
+        }
+    }
+
+    /// 資源の使用を許可する（または許可しない）
+    pub fn set(&mut self, capability: Capability, allowed: bool) {
+        let flag = match capability {
+            Capability::Env => &mut self.env,
+            Capability::Process => &mut self.process,
+            Capability::Network => &mut self.network,
+            Capability::Clock => &mut self.clock,
+            Capability::Stdin => &mut self.stdin,
+        };
+        *flag = allowed;
+    }
+
+    /// パスへのアクセスが許可されているか。パスも許可する場所も絶対パスにし、
+    /// シンボリックリンクや .. を解決してから比べる（許可する場所の外へ抜けられないように）
+    pub fn allows_path(&self, access: Access, path: &Path) -> bool {
+        let prefixes = match access {
+            Access::Read => &self.read,
+            Access::Write => &self.write,
+        };
+        let Some(prefixes) = prefixes else {
+            return true;
+        };
+        let Some(path) = resolve(path) else {
+            return false;
+        };
+        prefixes.iter().filter_map(|prefix| resolve(prefix)).any(|prefix| path.starts_with(prefix))
+    }
+}
+
+/// 資源の使用許可があるかを検査する（各関数が資源を使う前に呼ぶ）
+pub fn require(interpreter: &Interpreter, capability: Capability) -> Result<()> {
+    if interpreter.capabilities.allows(capability) {
+        Ok(())
+    } else {
+        Err(Error::Denied(format!("Access to {} is not allowed", capability.name())))
+    }
+}
+
+/// パスへのアクセスの許可があるかを検査する
+pub fn require_path(interpreter: &Interpreter, access: Access, path: &Path) -> Result<()> {
+    if interpreter.capabilities.allows_path(access, path) {
+        Ok(())
+    } else {
+        Err(Error::Denied(format!("Not allowed to {} {}", access.name(), path.display())))
+    }
+}
+
+// パスを絶対パスにする。存在する部分はシンボリックリンクと .. を解決し、残り（まだないファイル）の
+// 名前を後ろにつなげる。解決できなければ（存在しない場所の .. やリンク先のないリンクなど）None
+fn resolve(path: &Path) -> Option<PathBuf> {
+    let absolute = std::env::current_dir().ok()?.join(path);
+    let mut existing = absolute.as_path();
+    let mut rest = Vec::new();
+    let mut resolved = loop {
+        if let Ok(resolved) = existing.canonicalize() {
+            break resolved;
+        }
+        // 解決できないシンボリックリンク（リンク先がない）は、リンク先に作られるので許可しない
+        if existing.symlink_metadata().is_ok() {
+            return None;
+        }
+        rest.push(existing.file_name()?);
+        existing = existing.parent()?;
+    };
+    resolved.extend(rest.iter().rev());
+    Some(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Engine;
+
+    // テストごとに別のディレクトリを作る（allowed と、その隣の outside）
+    fn sandbox(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("elium-capabilities-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(dir.join("allowed/sub")).unwrap();
+        std::fs::create_dir_all(dir.join("outside")).unwrap();
+        std::fs::write(dir.join("outside/secret.txt"), "secret").unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    fn reading(dir: &Path) -> Capabilities {
+        Capabilities { read: Some(vec![dir.join("allowed")]), ..Capabilities::none() }
+    }
+
+    // 使用許可を指定してソースを実行する
+    fn run(engine: Engine, capabilities: Capabilities, source: &str) -> Result<()> {
+        let mut interpreter = Interpreter::new();
+        interpreter.engine = engine;
+        interpreter.capabilities = capabilities;
+        interpreter.interpret(crate::parser::parse_source(source)?)
+    }
+
+    #[test]
+    fn resources_are_denied_without_capabilities() {
+        // 許可すれば実行できるもの（終了と入力の待ち以外）は、その資源だけを許可して実行してみる
+        let cases = [
+            (Capability::Env, "x = os.env(\"HOME\")", true),
+            (Capability::Env, "x = os.cwd()", true),
+            (Capability::Env, "x = os.platform()", true),
+            (Capability::Process, "x = os.args()", true),
+            (Capability::Process, "os.exit(0)", false),
+            (Capability::Process, "exit", false),
+            (Capability::Clock, "x = os.time()", true),
+            (Capability::Stdin, "x = input(\"name? \")", false),
+        ];
+        for engine in [Engine::Tree, Engine::Vm] {
+            for (capability, source, runs) in cases {
+                let source = format!("import os\n{}\n", source);
+                match run(engine, Capabilities::none(), &source) {
+                    Err(Error::Denied(message)) => assert!(message.contains(capability.name()), "{}: {}", source, message),
+                    other => panic!("{:?} {}: {:?}", engine, source, other),
+                }
+                if runs {
+                    let mut allowed = Capabilities::none();
+                    allowed.set(capability, true);
+                    run(engine, allowed, &source).unwrap();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn capabilities_are_named_for_the_allow_flags() {
+        for capability in Capability::ALL {
+            assert_eq!(Capability::named(capability.name()), Some(capability));
+            assert!(Capabilities::default().allows(capability));
+            assert!(!Capabilities::none().allows(capability));
+            let mut capabilities = Capabilities::none();
+            capabilities.set(capability, true);
+            assert!(Capability::ALL.iter().all(|&other| capabilities.allows(other) == (other == capability)));
+        }
+        assert_eq!(Capability::named("net"), Some(Capability::Network));
+        assert_eq!(Capability::named("read"), None);
+    }
+
+    #[test]
+    fn resolve_keeps_names_that_do_not_exist_yet() {
+        let dir = sandbox("resolve");
+        assert_eq!(resolve(&dir.join("allowed/new/file.txt")), Some(dir.join("allowed/new/file.txt")));
+        assert_eq!(resolve(&dir.join("allowed/sub/../sub/x")), Some(dir.join("allowed/sub/x")));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn paths_match_whole_components() {
+        let dir = sandbox("prefix");
+        let capabilities = reading(&dir);
+        assert!(capabilities.allows_path(Access::Read, &dir.join("allowed")));
+        assert!(capabilities.allows_path(Access::Read, &dir.join("allowed/sub/new.txt")));
+        // 文字列として前方一致するだけの兄弟は許可しない
+        std::fs::create_dir_all(dir.join("allowed2")).unwrap();
+        assert!(!capabilities.allows_path(Access::Read, &dir.join("allowed2/x")));
+        assert!(!capabilities.allows_path(Access::Write, &dir.join("allowed/sub/new.txt")));
+        assert!(Capabilities::default().allows_path(Access::Write, &dir.join("outside/secret.txt")));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn dot_dot_cannot_escape() {
+        let dir = sandbox("dotdot");
+        let capabilities = reading(&dir);
+        assert!(!capabilities.allows_path(Access::Read, &dir.join("allowed/../outside/secret.txt")));
+        assert!(!capabilities.allows_path(Access::Read, &dir.join("allowed/sub/../../outside/secret.txt")));
+        // まだない場所を経由する .. は解決できないので許可しない
+        assert!(!capabilities.allows_path(Access::Read, &dir.join("allowed/missing/../../outside/secret.txt")));
+        assert!(capabilities.allows_path(Access::Read, &dir.join("allowed/sub/../new.txt")));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_cannot_escape() {
+        use std::os::unix::fs::symlink;
+        let dir = sandbox("symlink");
+        let capabilities = reading(&dir);
+        symlink(dir.join("outside/secret.txt"), dir.join("allowed/link.txt")).unwrap();
+        symlink(dir.join("outside"), dir.join("allowed/out")).unwrap();
+        symlink(dir.join("outside/missing.txt"), dir.join("allowed/dangling.txt")).unwrap();
+        assert!(!capabilities.allows_path(Access::Read, &dir.join("allowed/link.txt")));
+        assert!(!capabilities.allows_path(Access::Read, &dir.join("allowed/out/secret.txt")));
+        assert!(!capabilities.allows_path(Access::Read, &dir.join("allowed/out/new.txt")));
+        // リンク先のないリンクに書くとリンク先に作られる
+        assert_eq!(resolve(&dir.join("allowed/dangling.txt")), None);
+        // 許可した場所そのものがリンクでも、リンク先の中なら許可する
+        symlink(dir.join("allowed"), dir.join("alias")).unwrap();
+        let aliased = Capabilities { read: Some(vec![dir.join("alias")]), ..Capabilities::none() };
+        assert!(aliased.allows_path(Access::Read, &dir.join("allowed/sub")));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,475 @@
+// src/stdlib/collections.rs
+
+use super::{clamp_index, expect_arg_range, expect_args, num_arg, text_arg};
+use crate::ast::Value;
+use crate::builtins::{MutatorFn, NativeFn};
+use crate::interpreter::{values_equal, Interpreter};
+use crate::ordered_map::OrderedMap;
+use crate::utils::error::{Error, Result};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// 配列・マップの関数は値を第 1 引数に取り、"array.slice" のような型名つきの名前で登録する（xs.slice(1) の形で呼ぶ）。
+// push / pop / insert / remove は値を書き換えるので mutators に登録する（変数に対して呼ぶと変数が書き換わる）
+pub fn register(natives: &mut HashMap<String, NativeFn>, mutators: &mut HashMap<String, MutatorFn>) {
+    mutators.insert("array.push".into(), array_push);
+    mutators.insert("array.pop".into(), array_pop);
+    mutators.insert("array.insert".into(), array_insert);
+    mutators.insert("array.remove".into(), array_remove);
+    mutators.insert("map.insert".into(), map_insert);
+    mutators.insert("map.remove".into(), map_remove);
+
+    natives.insert("array.get".into(), array_get);
+    natives.insert("array.slice".into(), array_slice);
+    natives.insert("array.concat".into(), array_concat);
+    natives.insert("array.reverse".into(), array_reverse);
+    natives.insert("array.sort".into(), array_sort);
+    natives.insert("array.contains".into(), array_contains);
+    natives.insert("array.index_of".into(), array_index_of);
+    natives.insert("array.map".into(), array_map);
+    natives.insert("array.filter".into(), array_filter);
+    natives.insert("array.reduce".into(), array_reduce);
+    natives.insert("array.any".into(), array_any);
+    natives.insert("array.all".into(), array_all);
+
+    natives.insert("map.get".into(), map_get);
+    natives.insert("map.keys".into(), map_keys);
+    natives.insert("map.values".into(), map_values);
+    natives.insert("map.entries".into(), map_entries);
+    natives.insert("map.has_key".into(), map_has_key);
+    natives.insert("map.merge".into(), map_merge);
+}
+
+// 配列の要素を読む（複製しない）
+fn array_arg<'a>(name: &str, value: &'a Value) -> Result<&'a [Value]> {
+    match value {
+        Value::Array(items) => Ok(items),
+        other => Err(Error::Type(format!("{} expects array, but got {}", name, other.type_name()))),
+    }
+}
+
+// 書き換える配列（ほかの値と共有していれば複製する）
+fn array_mut<'a>(name: &str, value: &'a mut Value) -> Result<&'a mut Vec<Value>> {
+    match value {
+        Value::Array(items) => Ok(Rc::make_mut(items)),
+        other => Err(Error::Type(format!("{} expects array, but got {}", name, other.type_name()))),
+    }
+}
+
+// 配列の要素を取り出す（ほかの値と共有していれば複製する）
+fn take_array(name: &str, value: Value) -> Result<Vec<Value>> {
+    match value {
+        Value::Array(items) => Ok(Rc::unwrap_or_clone(items)),
+        other => Err(Error::Type(format!("{} expects array, but got {}", name, other.type_name()))),
+    }
+}
+
+fn map_arg<'a>(name: &str, value: &'a Value) -> Result<&'a OrderedMap<String, Value>> {
+    match value {
+        Value::Map(map) => Ok(map),
+        other => Err(Error::Type(format!("{} expects map, but got {}", name, other.type_name()))),
+    }
+}
+
+fn map_mut<'a>(name: &str, value: &'a mut Value) -> Result<&'a mut OrderedMap<String, Value>> {
+    match value {
+        Value::Map(map) => Ok(Rc::make_mut(map)),
+        other => Err(Error::Type(format!("{} expects map, but got {}", name, other.type_name()))),
+    }
+}
+
+fn take_map(name: &str, value: Value) -> Result<OrderedMap<String, Value>> {
+    match value {
+        Value::Map(map) => Ok(Rc::unwrap_or_clone(map)),
+        other => Err(Error::Type(format!("{} expects map, but got {}", name, other.type_name()))),
+    }
+}
+
+// 先頭から順に引数を取り出す（引数の数は検査済み）
+fn next_arg(args: &mut std::vec::IntoIter<Value>) -> Value {
+    args.next().unwrap_or(Value::None)
+}
+
+// 要素の位置を求める（負の位置は末尾から数える）。範囲外はエラー
+fn element_index(name: &str, index: &Value, len: usize) -> Result<usize> {
+    let index = num_arg(name, index)?;
+    let resolved = if index < 0 { len as i64 + index } else { index };
+    if resolved < 0 || resolved >= len as i64 {
+        return Err(Error::Runtime(format!(
+            "{}: index {} out of range for array of length {}",
+            name, index, len
+        )));
+    }
+    Ok(resolved as usize)
+}
+
+// 関数（map や filter に渡された Elium の関数）の結果を真偽値として取り出す
+fn predicate(interpreter: &mut Interpreter, name: &str, function: &Value, item: Value) -> Result<bool> {
+    match interpreter.call_value(function, vec![item])? {
+        Value::Boolean(b) => Ok(b),
+        other => Err(Error::Type(format!(
+            "{} expects the function to return bool, but got {}",
+            name,
+            other.type_name()
+        ))),
+    }
+}
+
+/// xs.push(x): 末尾に追加する
+fn array_push(_: &mut Interpreter, receiver: &mut Value, args: Vec<Value>) -> Result<Value> {
+    expect_args("array.push", &args, 1)?;
+    array_mut("array.push", receiver)?.extend(args);
+    Ok(Value::None)
+}
+
+/// xs.pop(): 末尾の要素を取り除いて返す
+fn array_pop(_: &mut Interpreter, receiver: &mut Value, args: Vec<Value>) -> Result<Value> {
+    expect_args("array.pop", &args, 0)?;
+    array_mut("array.pop", receiver)?
+        .pop()
+        .ok_or_else(|| Error::Runtime("array.pop: array is empty".into()))
+}
+
+/// xs.insert(index, x): index の位置に挿入する（index が長さと同じなら末尾に追加）
+fn array_insert(_: &mut Interpreter, receiver: &mut Value, mut args: Vec<Value>) -> Result<Value> {
+    expect_args("array.insert", &args, 2)?;
+    let items = array_mut("array.insert", receiver)?;
+    let value = args.pop().unwrap_or(Value::None);
+    let index = match num_arg("array.insert", &args[0])? {
+        index if index == items.len() as i64 => items.len(),
+        _ => element_index("array.insert", &args[0], items.len())?,
+    };
+    items.insert(index, value);
+    Ok(Value::None)
+}
+
+/// xs.remove(index): index の位置の要素を取り除いて返す
+fn array_remove(_: &mut Interpreter, receiver: &mut Value, args: Vec<Value>) -> Result<Value> {
+    expect_args("array.remove", &args, 1)?;
+    let items = array_mut("array.remove", receiver)?;
+    let index = element_index("array.remove", &args[0], items.len())?;
+    Ok(items.remove(index))
+}
+
+/// m.insert(key, value): キーに値を設定する
+fn map_insert(_: &mut Interpreter, receiver: &mut Value, mut args: Vec<Value>) -> Result<Value> {
+    expect_args("map.insert", &args, 2)?;
+    let value = args.pop().unwrap_or(Value::None);
+    let key = text_arg("map.insert", &args[0])?;
+    map_mut("map.insert", receiver)?.insert(key, value);
+    Ok(Value::None)
+}
+
+/// m.remove(key): キーを取り除いて値を返す（なければ none）
+fn map_remove(_: &mut Interpreter, receiver: &mut Value, args: Vec<Value>) -> Result<Value> {
+    expect_args("map.remove", &args, 1)?;
+    let key = text_arg("map.remove", &args[0])?;
+    Ok(map_mut("map.remove", receiver)?.remove(&key).unwrap_or(Value::None))
+}
+
+/// xs.get(index): index の位置の要素（負の位置は末尾から数える）
+fn array_get(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("array.get", &args, 2)?;
+    let items = array_arg("array.get", &args[0])?;
+    let index = element_index("array.get", &args[1], items.len())?;
+    Ok(items[index].clone())
+}
+
+/// xs.slice(start, end): start から end の手前までの配列（end を省略すると末尾まで）
+fn array_slice(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_arg_range("array.slice", &args, 2, 3)?;
+    let items = array_arg("array.slice", &args[0])?;
+    let len = items.len();
+    let start = clamp_index(num_arg("array.slice", &args[1])?, len);
+    let end = match args.get(2) {
+        Some(end) => clamp_index(num_arg("array.slice", end)?, len),
+        None => len,
+    };
+    Ok(Value::array(items[start..end.max(start)].to_vec()))
+}
+
+/// xs.concat(ys): 2 つの配列をつないだ配列
+fn array_concat(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("array.concat", &args, 2)?;
+    let mut args = args.into_iter();
+    let mut items = take_array("array.concat", next_arg(&mut args))?;
+    items.extend_from_slice(array_arg("array.concat", &next_arg(&mut args))?);
+    Ok(Value::array(items))
+}
+
+/// xs.reverse(): 逆順にした配列
+fn array_reverse(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("array.reverse", &args, 1)?;
+    let mut items = take_array("array.reverse", next_arg(&mut args.into_iter()))?;
+    items.reverse();
+    Ok(Value::array(items))
+}
+
+// 比較関数のない sort で使う順序（数値同士・テキスト同士・真偽値同士のみ比較できる）
+fn compare_values(left: &Value, right: &Value) -> Result<Ordering> {
+    let ordering = match (left, right) {
+        (Value::Number(l), Value::Number(r)) => Some(l.cmp(r)),
+        (Value::Number(l), Value::Float(r)) => (*l as f64).partial_cmp(r),
+        (Value::Float(l), Value::Number(r)) => l.partial_cmp(&(*r as f64)),
+        (Value::Float(l), Value::Float(r)) => l.partial_cmp(r),
+        (Value::Text(l), Value::Text(r)) => Some(l.cmp(r)),
+        (Value::Boolean(l), Value::Boolean(r)) => Some(l.cmp(r)),
+        _ => None,
+    };
+    ordering.ok_or_else(|| Error::Type(format!("array.sort cannot compare {} with {}", left, right)))
+}
+
+// 安定なマージソート。比較関数は Elium の関数でエラーになりうるので、標準の sort_by は使わない
+fn merge_sort(items: Vec<Value>, before: &mut dyn FnMut(&Value, &Value) -> Result<bool>) -> Result<Vec<Value>> {
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+    let mut left = items;
+    let right = left.split_off(left.len() / 2);
+    let mut left = merge_sort(left, before)?.into_iter().peekable();
+    let mut right = merge_sort(right, before)?.into_iter().peekable();
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        // 右が左より厳密に前に来るときだけ右を先に出す（同じ順位なら元の順序を保つ）
+        let next = if before(r, l)? { right.next() } else { left.next() };
+        merged.extend(next);
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
+
+/// xs.sort() / xs.sort(compare): 並べ替えた配列。compare(a, b) は a を b より前に置くなら
+/// true（または負の数）を返す関数
+fn array_sort(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_arg_range("array.sort", &args, 1, 2)?;
+    let mut args = args.into_iter();
+    let items = take_array("array.sort", next_arg(&mut args))?;
+    let compare = args.next();
+    let sorted = match compare {
+        None => merge_sort(items, &mut |a, b| Ok(compare_values(a, b)? == Ordering::Less))?,
+        Some(compare) => merge_sort(items, &mut |a, b| {
+            match interpreter.call_value(&compare, vec![a.clone(), b.clone()])? {
+                Value::Boolean(b) => Ok(b),
+                Value::Number(n) => Ok(n < 0),
+                Value::Float(f) => Ok(f < 0.0),
+                other => Err(Error::Type(format!(
+                    "array.sort expects the compare function to return bool or num, but got {}",
+                    other.type_name()
+                ))),
+            }
+        })?,
+    };
+    Ok(Value::array(sorted))
+}
+
+/// xs.contains(x): x と等しい要素があるか
+fn array_contains(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("array.contains", &args, 2)?;
+    let items = array_arg("array.contains", &args[0])?;
+    Ok(Value::Boolean(items.iter().any(|item| values_equal(item, &args[1]))))
+}
+
+/// xs.index_of(x): x と等しい最初の要素の位置。なければ none
+fn array_index_of(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("array.index_of", &args, 2)?;
+    let items = array_arg("array.index_of", &args[0])?;
+    Ok(items
+        .iter()
+        .position(|item| values_equal(item, &args[1]))
+        .map_or(Value::None, |index| Value::Number(index as i64)))
+}
+
+/// xs.map(f): 各要素に f を適用した配列
+fn array_map(interpreter: &mut Interpreter, mut args: Vec<Value>) -> Result<Value> {
+    expect_args("array.map", &args, 2)?;
+    let function = args.pop().unwrap_or(Value::None);
+    let items = take_array("array.map", args.pop().unwrap_or(Value::None))?;
+    let mut mapped = Vec::with_capacity(items.len());
+    for item in items {
+        mapped.push(interpreter.call_value(&function, vec![item])?);
+    }
+    Ok(Value::array(mapped))
+}
+
+/// xs.filter(f): f が true を返す要素だけの配列
+fn array_filter(interpreter: &mut Interpreter, mut args: Vec<Value>) -> Result<Value> {
+    expect_args("array.filter", &args, 2)?;
+    let function = args.pop().unwrap_or(Value::None);
+    let items = take_array("array.filter", args.pop().unwrap_or(Value::None))?;
+    let mut kept = Vec::new();
+    for item in items {
+        if predicate(interpreter, "array.filter", &function, item.clone())? {
+            kept.push(item);
+        }
+    }
+    Ok(Value::array(kept))
+}
+
+/// xs.reduce(f, initial): 先頭から f(累積値, 要素) を順に適用した結果
+fn array_reduce(interpreter: &mut Interpreter, mut args: Vec<Value>) -> Result<Value> {
+    expect_args("array.reduce", &args, 3)?;
+    let mut accumulator = args.pop().unwrap_or(Value::None);
+    let function = args.pop().unwrap_or(Value::None);
+    let items = take_array("array.reduce", args.pop().unwrap_or(Value::None))?;
+    for item in items {
+        accumulator = interpreter.call_value(&function, vec![accumulator, item])?;
+    }
+    Ok(accumulator)
+}
+
+/// xs.any(f): f が true を返す要素が 1 つでもあるか
+fn array_any(interpreter: &mut Interpreter, mut args: Vec<Value>) -> Result<Value> {
+    expect_args("array.any", &args, 2)?;
+    let function = args.pop().unwrap_or(Value::None);
+    let items = take_array("array.any", args.pop().unwrap_or(Value::None))?;
+    for item in items {
+        if predicate(interpreter, "array.any", &function, item)? {
+            return Ok(Value::Boolean(true));
+        }
+    }
+    Ok(Value::Boolean(false))
+}
+
+/// xs.all(f): すべての要素で f が true を返すか
+fn array_all(interpreter: &mut Interpreter, mut args: Vec<Value>) -> Result<Value> {
+    expect_args("array.all", &args, 2)?;
+    let function = args.pop().unwrap_or(Value::None);
+    let items = take_array("array.all", args.pop().unwrap_or(Value::None))?;
+    for item in items {
+        if !predicate(interpreter, "array.all", &function, item)? {
+            return Ok(Value::Boolean(false));
+        }
+    }
+    Ok(Value::Boolean(true))
+}
+
+/// m.get(key) / m.get(key, default): キーの値。なければ default（省略時は none）
+fn map_get(_: &mut Interpreter, mut args: Vec<Value>) -> Result<Value> {
+    expect_arg_range("map.get", &args, 2, 3)?;
+    let default = if args.len() == 3 { args.pop() } else { None };
+    let key = text_arg("map.get", &args[1])?;
+    let map = map_arg("map.get", &args[0])?;
+    Ok(map.get(&key).cloned().or(default).unwrap_or(Value::None))
+}
+
+// マップの要素（挿入順）
+fn map_entries_of(name: &str, args: Vec<Value>) -> Result<OrderedMap<String, Value>> {
+    expect_args(name, &args, 1)?;
+    take_map(name, next_arg(&mut args.into_iter()))
+}
+
+/// m.keys(): キーの配列（挿入順）
+fn map_keys(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    let entries = map_entries_of("map.keys", args)?;
+    Ok(Value::array(entries.into_iter().map(|(key, _)| Value::text(key)).collect()))
+}
+
+/// m.values(): 値の配列（挿入順）
+fn map_values(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    let entries = map_entries_of("map.values", args)?;
+    Ok(Value::array(entries.into_iter().map(|(_, value)| value).collect()))
+}
+
+/// m.entries(): [キー, 値] の配列（挿入順）
+fn map_entries(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    let entries = map_entries_of("map.entries", args)?;
+    Ok(Value::array(
+        entries
+            .into_iter()
+            .map(|(key, value)| Value::array(vec![Value::text(key), value]))
+            .collect(),
+    ))
+}
+
+/// m.has_key(key): キーがあるか
+fn map_has_key(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("map.has_key", &args, 2)?;
+    let key = text_arg("map.has_key", &args[1])?;
+    Ok(Value::Boolean(map_arg("map.has_key", &args[0])?.contains_key(&key)))
+}
+
+/// m.merge(other): 2 つのマップを合わせたマップ（同じキーは other の値を使い、位置は m のまま）
+fn map_merge(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("map.merge", &args, 2)?;
+    let mut args = args.into_iter();
+    let mut map = take_map("map.merge", next_arg(&mut args))?;
+    map.extend(take_map("map.merge", next_arg(&mut args))?);
+    Ok(Value::map(map))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::testing::run;
+    use crate::interpreter::Engine;
+
+    fn numbers(items: &[i64]) -> Value {
+        Value::array(items.iter().copied().map(Value::Number).collect())
+    }
+
+    #[test]
+    fn mutators_copy_shared_payloads() {
+        let mut interpreter = Interpreter::new();
+        let shared = numbers(&[1, 2, 3]);
+        let mut xs = shared.clone();
+        array_push(&mut interpreter, &mut xs, vec![Value::Number(4)]).unwrap();
+        array_insert(&mut interpreter, &mut xs, vec![Value::Number(4), Value::Number(9)]).unwrap();
+        assert_eq!(array_remove(&mut interpreter, &mut xs, vec![Value::Number(-5)]).unwrap(), Value::Number(1));
+        assert_eq!(xs, numbers(&[2, 3, 4, 9]));
+        // 書き換えたのは複製で、共有していた値は変わらない
+        assert_eq!(shared, numbers(&[1, 2, 3]));
+        match array_remove(&mut interpreter, &mut xs, vec![Value::Number(4)]) {
+            Err(Error::Runtime(message)) => assert_eq!(message, "array.remove: index 4 out of range for array of length 4"),
+            other => panic!("{:?}", other),
+        }
+        assert!(array_pop(&mut interpreter, &mut numbers(&[]), vec![]).is_err());
+
+        let shared = Value::map(OrderedMap::from_iter([("a".to_string(), Value::Number(1))]));
+        let mut m = shared.clone();
+        map_insert(&mut interpreter, &mut m, vec![Value::text("b"), Value::Boolean(true)]).unwrap();
+        assert_eq!(map_remove(&mut interpreter, &mut m, vec![Value::text("a")]).unwrap(), Value::Number(1));
+        assert_eq!(map_remove(&mut interpreter, &mut m, vec![Value::text("a")]).unwrap(), Value::None);
+        assert_eq!(map_keys(&mut interpreter, vec![m]).unwrap(), Value::array(vec![Value::text("b")]));
+        assert_eq!(map_keys(&mut interpreter, vec![shared]).unwrap(), Value::array(vec![Value::text("a")]));
+    }
+
+    #[test]
+    fn higher_order_methods_call_elium_functions() {
+        let source = r#"
+            function (name=by_first, a, b) {
+                return a.get(0) < b.get(0)
+            }
+            function (name=odd, x) {
+                return math.mod(x, 2) == 1
+            }
+            function (name=add, a, b) {
+                return a + b
+            }
+            import math
+            pairs = [[2, "a"], [1, "b"], [2, "c"], [1, "d"]].sort(by_first)
+            odds = [1, 2, 3, 4, 5].filter(odd)
+            total = [1, 2, 3].map(math.abs).reduce(add, 10)
+            some = [2, 4].any(odd)
+            every = [1, 3].all(odd)
+        "#;
+        for engine in [Engine::Tree, Engine::Vm] {
+            let (interpreter, result) = run(engine, source);
+            result.unwrap();
+            // 比較関数で同じ順位の要素は元の順序を保つ
+            let order: Vec<Value> = ["b", "d", "a", "c"].into_iter().map(Value::text).collect();
+            let Some(Value::Array(pairs)) = interpreter.lookup_variable("pairs") else { panic!("{:?}", engine) };
+            assert_eq!(pairs.iter().map(|pair| array_arg("pair", pair).unwrap()[1].clone()).collect::<Vec<_>>(), order, "{:?}", engine);
+            assert_eq!(interpreter.lookup_variable("odds"), Some(numbers(&[1, 3, 5])), "{:?}", engine);
+            assert_eq!(interpreter.lookup_variable("total"), Some(Value::Number(16)), "{:?}", engine);
+            assert_eq!(interpreter.lookup_variable("some"), Some(Value::Boolean(false)), "{:?}", engine);
+            assert_eq!(interpreter.lookup_variable("every"), Some(Value::Boolean(true)), "{:?}", engine);
+
+            let (_, result) = run(engine, "xs = [1, \"a\"].sort()\n");
+            assert!(matches!(result, Err(Error::Type(ref msg)) if msg.starts_with("array.sort cannot compare")), "{:?}", result);
+            let (_, result) = run(engine, "function (name=id, x) {\n    return x\n}\nxs = [1].filter(id)\n");
+            assert!(matches!(result, Err(Error::Type(ref msg)) if msg.contains("to return bool")), "{:?}", result);
+        }
+    }
+}
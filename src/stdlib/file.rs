@@ -0,0 +1,264 @@
+// src/stdlib/file.rs
+
+use super::{expect_args, require_path, text_arg, Access};
+use crate::ast::Value;
+use crate::builtins::NativeFn;
+use crate::interpreter::Interpreter;
+use crate::utils::error::{Error, Result};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// file.open(path) { ... } で開いたファイル。ブロックを抜けると閉じる
+#[derive(Debug, Clone)]
+pub struct OpenFile {
+    pub path: PathBuf,
+    pub file: Rc<File>,
+}
+
+pub fn register(natives: &mut HashMap<String, NativeFn>) {
+    natives.insert("file.open".into(), file_open);
+    natives.insert("file.read".into(), file_read);
+    natives.insert("file.lines".into(), file_lines);
+    natives.insert("file.write".into(), file_write);
+    natives.insert("file.edit".into(), file_write);
+    natives.insert("file.append".into(), file_append);
+    natives.insert("file.exists".into(), file_exists);
+    natives.insert("file.remove".into(), file_remove);
+    natives.insert("file.rename".into(), file_rename);
+    natives.insert("file.list".into(), file_list);
+}
+
+fn io_error(action: &str, path: &Path, e: std::io::Error) -> Error {
+    Error::Runtime(format!("Failed to {} {}: {}", action, path.display(), e))
+}
+
+// 開いているファイル（file.open のブロックの中でだけ使える）
+fn current_file(interpreter: &Interpreter, name: &str) -> Result<OpenFile> {
+    interpreter.open_files.last().cloned().ok_or_else(|| {
+        Error::Runtime(format!("{} without a path can only be used inside a file.open block", name))
+    })
+}
+
+/// file.open(path) / file.open(dir, name): ファイルを開く（なければ作成する）。
+/// ブロックの中では path を省略した file.read() / file.edit(text) などがこのファイルを対象にする
+fn file_open(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    let path = match args.as_slice() {
+        [path] => PathBuf::from(text_arg("file.open", path)?),
+        [dir, name] => Path::new(&text_arg("file.open", dir)?).join(text_arg("file.open", name)?),
+        _ => return Err(Error::Runtime(format!(
+            "Function file.open expected 1 or 2 arguments, but got {}",
+            args.len()
+        ))),
+    };
+    require_path(interpreter, Access::Read, &path)?;
+    require_path(interpreter, Access::Write, &path)?;
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .map_err(|e| io_error("open", &path, e))?;
+    let display = path.display().to_string();
+    interpreter.open_files.push(OpenFile { path, file: Rc::new(file) });
+    Ok(Value::text(display))
+}
+
+// 引数にパスがあればそのファイルを、なければ開いているファイルを全部読む
+fn read_contents(interpreter: &Interpreter, name: &str, args: &[Value]) -> Result<String> {
+    match args {
+        [] => {
+            let open = current_file(interpreter, name)?;
+            let mut file = &*open.file;
+            let mut contents = String::new();
+            file.seek(SeekFrom::Start(0))
+                .and_then(|_| file.read_to_string(&mut contents))
+                .map_err(|e| io_error("read", &open.path, e))?;
+            Ok(contents)
+        }
+        [path] => {
+            let path = PathBuf::from(text_arg(name, path)?);
+            require_path(interpreter, Access::Read, &path)?;
+            std::fs::read_to_string(&path).map_err(|e| io_error("read", &path, e))
+        }
+        _ => Err(Error::Runtime(format!(
+            "Function {} expected 0 or 1 arguments, but got {}",
+            name,
+            args.len()
+        ))),
+    }
+}
+
+/// file.read(path): ファイル全体をテキストとして読む
+fn file_read(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    read_contents(interpreter, "file.read", &args).map(Value::text)
+}
+
+/// file.lines(path): ファイルを行ごとに分けた配列（改行文字は含まない）
+fn file_lines(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    let contents = read_contents(interpreter, "file.lines", &args)?;
+    Ok(Value::array(contents.lines().map(|line| Value::text(line.to_string())).collect()))
+}
+
+/// file.write(path, text) / file.edit(text): ファイルの内容を置き換える
+fn file_write(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    match args.as_slice() {
+        [text] => {
+            let open = current_file(interpreter, "file.write")?;
+            let mut file = &*open.file;
+            open.file.set_len(0)
+                .and_then(|_| file.seek(SeekFrom::Start(0)))
+                .and_then(|_| file.write_all(text.to_string().as_bytes()))
+                .map_err(|e| io_error("write", &open.path, e))?;
+        }
+        [path, text] => {
+            let path = PathBuf::from(text_arg("file.write", path)?);
+            require_path(interpreter, Access::Write, &path)?;
+            std::fs::write(&path, text.to_string()).map_err(|e| io_error("write", &path, e))?;
+        }
+        _ => return Err(Error::Runtime(format!(
+            "Function file.write expected 1 or 2 arguments, but got {}",
+            args.len()
+        ))),
+    }
+    Ok(Value::None)
+}
+
+/// file.append(path, text): ファイルの末尾に追記する（なければ作成する）
+fn file_append(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    match args.as_slice() {
+        [text] => {
+            let open = current_file(interpreter, "file.append")?;
+            let mut file = &*open.file;
+            file.seek(SeekFrom::End(0))
+                .and_then(|_| file.write_all(text.to_string().as_bytes()))
+                .map_err(|e| io_error("append to", &open.path, e))?;
+        }
+        [path, text] => {
+            let path = PathBuf::from(text_arg("file.append", path)?);
+            require_path(interpreter, Access::Write, &path)?;
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .and_then(|mut file| file.write_all(text.to_string().as_bytes()))
+                .map_err(|e| io_error("append to", &path, e))?;
+        }
+        _ => return Err(Error::Runtime(format!(
+            "Function file.append expected 1 or 2 arguments, but got {}",
+            args.len()
+        ))),
+    }
+    Ok(Value::None)
+}
+
+/// file.exists(path): ファイルまたはディレクトリが存在するか
+fn file_exists(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("file.exists", &args, 1)?;
+    let path = PathBuf::from(text_arg("file.exists", &args[0])?);
+    require_path(interpreter, Access::Read, &path)?;
+    Ok(Value::Boolean(path.exists()))
+}
+
+/// file.remove(path): ファイル（または空のディレクトリ）を削除する
+fn file_remove(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("file.remove", &args, 1)?;
+    let path = PathBuf::from(text_arg("file.remove", &args[0])?);
+    require_path(interpreter, Access::Write, &path)?;
+    let result = if path.is_dir() {
+        std::fs::remove_dir(&path)
+    } else {
+        std::fs::remove_file(&path)
+    };
+    result.map_err(|e| io_error("remove", &path, e))?;
+    Ok(Value::None)
+}
+
+/// file.rename(from, to): ファイルの名前を変更（移動）する
+fn file_rename(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("file.rename", &args, 2)?;
+    let from = PathBuf::from(text_arg("file.rename", &args[0])?);
+    let to = PathBuf::from(text_arg("file.rename", &args[1])?);
+    require_path(interpreter, Access::Write, &from)?;
+    require_path(interpreter, Access::Write, &to)?;
+    std::fs::rename(&from, &to).map_err(|e| io_error("rename", &from, e))?;
+    Ok(Value::None)
+}
+
+/// file.list(dir): ディレクトリ内の名前の配列（名前順）
+fn file_list(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("file.list", &args, 1)?;
+    let dir = PathBuf::from(text_arg("file.list", &args[0])?);
+    require_path(interpreter, Access::Read, &dir)?;
+    let entries = std::fs::read_dir(&dir).map_err(|e| io_error("list", &dir, e))?;
+    let mut names = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| io_error("list", &dir, e))?;
+        names.push(entry.file_name().to_string_lossy().into_owned());
+    }
+    names.sort();
+    Ok(Value::array(names.into_iter().map(Value::text).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::Value;
+    use crate::interpreter::testing::run;
+    use crate::interpreter::Engine;
+    use crate::utils::error::Error;
+
+    const SCRIPT: &str = r#"
+        import file
+        path = "DIR/notes.txt"
+        file.write(path, "one\n")
+        file.append(path, "two\n")
+        lines = file.lines(path)
+        file.open("DIR", "notes.txt") {
+            before = file.read()
+            file.edit("three\n")
+            file.append("four\n")
+        }
+        after = file.read(path)
+        file.rename(path, "DIR/moved.txt")
+        names = file.list("DIR")
+        moved = file.exists("DIR/moved.txt")
+        file.remove("DIR/moved.txt")
+        gone = file.exists("DIR/moved.txt")
+    "#;
+
+    #[test]
+    fn files_are_read_written_and_closed_after_the_block() {
+        for engine in [Engine::Tree, Engine::Vm] {
+            let dir = std::env::temp_dir().join(format!("elium-file-{:?}-{}", engine, std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let dir_name = dir.display().to_string();
+            let (interpreter, result) = run(engine, &SCRIPT.replace("DIR", &dir_name));
+            result.unwrap();
+            let variable = |name: &str| interpreter.lookup_variable(name).unwrap_or_else(|| panic!("{} is not set", name));
+            assert_eq!(variable("lines"), Value::array(vec![Value::text("one"), Value::text("two")]), "{:?}", engine);
+            assert_eq!(variable("before"), Value::text("one\ntwo\n"), "{:?}", engine);
+            assert_eq!(variable("after"), Value::text("three\nfour\n"), "{:?}", engine);
+            assert_eq!(variable("names"), Value::array(vec![Value::text("moved.txt")]), "{:?}", engine);
+            assert_eq!((variable("moved"), variable("gone")), (Value::Boolean(true), Value::Boolean(false)), "{:?}", engine);
+            assert!(interpreter.open_files.is_empty(), "{:?}", engine);
+
+            // ブロックをエラーで抜けてもファイルは閉じる。I/O の失敗はパニックせずにエラーを返す
+            let failing = "import file\nfile.open(\"DIR/x.txt\") {\n    file.edit(\"x\")\n    bad = not 1\n}\n";
+            let (interpreter, result) = run(engine, &failing.replace("DIR", &dir_name));
+            assert!(matches!(result, Err(Error::Type(_))), "{:?}: {:?}", engine, result);
+            assert!(interpreter.open_files.is_empty(), "{:?}", engine);
+            for source in [
+                "import file\nx = file.read()\n",
+                "import file\nx = file.read(\"DIR/missing.txt\")\n",
+                "import file\nfile.remove(\"DIR/missing.txt\")\n",
+            ] {
+                let (_, result) = run(engine, &source.replace("DIR", &dir_name));
+                assert!(matches!(result, Err(Error::Runtime(_))), "{:?}: {:?}", engine, result);
+            }
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+    }
+}
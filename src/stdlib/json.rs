@@ -0,0 +1,445 @@
+// src/stdlib/json.rs
+
+use super::{expect_arg_range, expect_args, num_arg, text_arg};
+use crate::ast::Value;
+use crate::builtins::NativeFn;
+use crate::interpreter::Interpreter;
+use crate::ordered_map::OrderedMap;
+use crate::utils::error::{Error, Result};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+pub fn register(natives: &mut HashMap<String, NativeFn>) {
+    natives.insert("json.parse".into(), json_parse);
+    natives.insert("json.stringify".into(), json_stringify);
+}
+
+/// json.parse(text): JSON のテキストを値にする。整数は num、小数や指数表記は float、null は none。
+/// オブジェクトのキーは文書中の順序を保つ（同じキーが複数あれば最後の値を使う）
+fn json_parse(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("json.parse", &args, 1)?;
+    let source = text_arg("json.parse", &args[0])?;
+    parse(&source)
+}
+
+/// json.stringify(value) / json.stringify(value, indent): 値を JSON のテキストにする。
+/// indent（空白の数）を指定すると改行と字下げを入れる。マップのキーは挿入順に並べる
+fn json_stringify(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_arg_range("json.stringify", &args, 1, 2)?;
+    let indent = match args.get(1) {
+        Some(indent) => {
+            let indent = num_arg("json.stringify", indent)?;
+            if indent < 0 {
+                return Err(Error::Runtime(format!("json.stringify: invalid indent {}", indent)));
+            }
+            // JavaScript の JSON.stringify と同じく 10 までにする（字下げだけで巨大なテキストを作らないように）
+            indent.min(MAX_INDENT) as usize
+        }
+        None => 0,
+    };
+    let mut out = String::new();
+    stringify(&args[0], indent, 0, &mut out)?;
+    Ok(Value::text(out))
+}
+
+/// JSON のテキストを値にする
+pub fn parse(source: &str) -> Result<Value> {
+    let mut parser = JsonParser { chars: source.chars().collect(), pos: 0, line: 1, column: 1 };
+    parser.skip_whitespace();
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    if parser.pos < parser.chars.len() {
+        return Err(parser.error("unexpected trailing characters"));
+    }
+    Ok(value)
+}
+
+// 字下げの空白の数の上限
+const MAX_INDENT: i64 = 10;
+
+// ネストの深さの上限（深すぎる入力でスタックが溢れないようにする）
+const MAX_DEPTH: usize = 512;
+
+struct JsonParser {
+    chars: Vec<char>,
+    pos: usize,
+    // エラー表示用の現在位置（1 から数える）
+    line: usize,
+    column: usize,
+}
+
+impl JsonParser {
+    fn error(&self, message: &str) -> Error {
+        Error::Runtime(format!("Invalid JSON at line {}, column {}: {}", self.line, self.column, message))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.next();
+                Ok(())
+            }
+            Some(c) => Err(self.error(&format!("expected '{}', but found '{}'", expected, c))),
+            None => Err(self.error(&format!("expected '{}', but reached the end", expected))),
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value> {
+        self.parse_nested(0)
+    }
+
+    fn parse_nested(&mut self, depth: usize) -> Result<Value> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+        match self.peek() {
+            Some('{') => self.parse_object(depth),
+            Some('[') => self.parse_array(depth),
+            Some('"') => self.parse_string().map(Value::text),
+            Some('-' | '0'..='9') => self.parse_number(),
+            Some('t') => self.parse_keyword("true", Value::Boolean(true)),
+            Some('f') => self.parse_keyword("false", Value::Boolean(false)),
+            Some('n') => self.parse_keyword("null", Value::None),
+            Some(c) => Err(self.error(&format!("unexpected character '{}'", c))),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn parse_keyword(&mut self, keyword: &str, value: Value) -> Result<Value> {
+        for expected in keyword.chars() {
+            if self.peek() != Some(expected) {
+                return Err(self.error(&format!("invalid literal, expected {}", keyword)));
+            }
+            self.next();
+        }
+        Ok(value)
+    }
+
+    fn parse_object(&mut self, depth: usize) -> Result<Value> {
+        self.expect('{')?;
+        let mut map = OrderedMap::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.next();
+            return Ok(Value::map(map));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("expected a string key"));
+            }
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            self.skip_whitespace();
+            let value = self.parse_nested(depth + 1)?;
+            map.insert(key, value);
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(Value::map(map)),
+                _ => return Err(self.error("expected ',' or '}' after object member")),
+            }
+        }
+    }
+
+    fn parse_array(&mut self, depth: usize) -> Result<Value> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.next();
+            return Ok(Value::array(items));
+        }
+        loop {
+            self.skip_whitespace();
+            items.push(self.parse_nested(depth + 1)?);
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(Value::array(items)),
+                _ => return Err(self.error("expected ',' or ']' after array element")),
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String> {
+        self.expect('"')?;
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(text),
+                Some('\\') => match self.next() {
+                    Some('"') => text.push('"'),
+                    Some('\\') => text.push('\\'),
+                    Some('/') => text.push('/'),
+                    Some('b') => text.push('\u{8}'),
+                    Some('f') => text.push('\u{c}'),
+                    Some('n') => text.push('\n'),
+                    Some('r') => text.push('\r'),
+                    Some('t') => text.push('\t'),
+                    Some('u') => text.push(self.parse_unicode_escape()?),
+                    _ => return Err(self.error("invalid escape sequence")),
+                },
+                Some(c) if (c as u32) < 0x20 => return Err(self.error("control character in string")),
+                Some(c) => text.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self.next().and_then(|c| c.to_digit(16)).ok_or_else(|| self.error("invalid \\u escape"))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    // \uXXXX（サロゲートペアは 2 つ続けて 1 文字にする）
+    fn parse_unicode_escape(&mut self) -> Result<char> {
+        let high = self.parse_hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if self.next() != Some('\\') || self.next() != Some('u') {
+                return Err(self.error("unpaired surrogate in \\u escape"));
+            }
+            let low = self.parse_hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(self.error("unpaired surrogate in \\u escape"));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("invalid \\u escape"))
+    }
+
+    fn parse_number(&mut self) -> Result<Value> {
+        let start = self.pos;
+        if self.peek() == Some('-') {
+            self.next();
+        }
+        match self.peek() {
+            Some('0') => {
+                self.next();
+            }
+            Some('1'..='9') => self.skip_digits(),
+            _ => return Err(self.error("invalid number")),
+        }
+        let mut is_float = false;
+        if self.peek() == Some('.') {
+            is_float = true;
+            self.next();
+            if !matches!(self.peek(), Some('0'..='9')) {
+                return Err(self.error("expected digits after '.'"));
+            }
+            self.skip_digits();
+        }
+        if matches!(self.peek(), Some('e' | 'E')) {
+            is_float = true;
+            self.next();
+            if matches!(self.peek(), Some('+' | '-')) {
+                self.next();
+            }
+            if !matches!(self.peek(), Some('0'..='9')) {
+                return Err(self.error("expected digits in exponent"));
+            }
+            self.skip_digits();
+        }
+        let literal: String = self.chars[start..self.pos].iter().collect();
+        // 整数でも i64 に収まらなければ小数として読む
+        if !is_float {
+            if let Ok(n) = literal.parse::<i64>() {
+                return Ok(Value::Number(n));
+            }
+        }
+        literal.parse::<f64>().map(Value::Float).map_err(|_| self.error("invalid number"))
+    }
+
+    fn skip_digits(&mut self) {
+        while matches!(self.peek(), Some('0'..='9')) {
+            self.next();
+        }
+    }
+}
+
+// 値を JSON のテキストとして out に書き出す（indent が 0 なら 1 行にする）
+fn stringify(value: &Value, indent: usize, depth: usize, out: &mut String) -> Result<()> {
+    if depth > MAX_DEPTH {
+        return Err(Error::Runtime("json.stringify: value is nested too deeply".into()));
+    }
+    let newline = |out: &mut String, depth: usize| {
+        if indent > 0 {
+            out.push('\n');
+            out.push_str(&" ".repeat(indent * depth));
+        }
+    };
+    match value {
+        Value::None => out.push_str("null"),
+        Value::Boolean(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) => out.push_str(&n.to_string()),
+        Value::Float(f) => {
+            if !f.is_finite() {
+                return Err(Error::Runtime(format!("json.stringify: {:?} cannot be represented in JSON", f)));
+            }
+            out.push_str(&format!("{:?}", f));
+        }
+        Value::Text(s) => write_string(s, out),
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                newline(out, depth + 1);
+                stringify(item, indent, depth + 1, out)?;
+            }
+            if !items.is_empty() {
+                newline(out, depth);
+            }
+            out.push(']');
+        }
+        Value::Map(map) => {
+            out.push('{');
+            for (i, (key, item)) in map.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                newline(out, depth + 1);
+                write_string(key, out);
+                out.push_str(if indent > 0 { ": " } else { ":" });
+                stringify(item, indent, depth + 1, out)?;
+            }
+            if !map.is_empty() {
+                newline(out, depth);
+            }
+            out.push('}');
+        }
+        Value::Function(name) => {
+            return Err(Error::Type(format!("json.stringify: function {} cannot be represented in JSON", name)));
+        }
+    }
+    Ok(())
+}
+
+fn write_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> String {
+        match parse(source) {
+            Err(Error::Runtime(message)) => message,
+            other => panic!("expected an error for {:?}, got {:?}", source, other),
+        }
+    }
+
+    fn compact(value: &Value) -> String {
+        let mut out = String::new();
+        stringify(value, 0, 0, &mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn parses_scalars() {
+        assert_eq!(parse("42").unwrap(), Value::Number(42));
+        assert_eq!(parse(" -7 ").unwrap(), Value::Number(-7));
+        assert_eq!(parse("2.5").unwrap(), Value::Float(2.5));
+        assert_eq!(parse("1e2").unwrap(), Value::Float(100.0));
+        // i64 に収まらない整数は小数にする
+        assert_eq!(parse("12345678901234567890").unwrap(), Value::Float(12345678901234567890.0));
+        assert_eq!(parse("true").unwrap(), Value::Boolean(true));
+        assert_eq!(parse("null").unwrap(), Value::None);
+        assert_eq!(parse(r#""a\"b\\c\né😀""#).unwrap(), Value::text("a\"b\\c\né😀"));
+    }
+
+    #[test]
+    fn objects_keep_document_order() {
+        let value = parse(r#"{"z": 1, "a": [true, {}], "m": "x", "z": 2}"#).unwrap();
+        let Value::Map(map) = &value else {
+            panic!("expected a map, got {:?}", value);
+        };
+        let keys: Vec<&str> = map.iter().map(|(key, _)| key.as_str()).collect();
+        // 同じキーは最後の値を使い、位置は最初に現れた場所のまま
+        assert_eq!(keys, ["z", "a", "m"]);
+        assert_eq!(map.get("z"), Some(&Value::Number(2)));
+        assert_eq!(compact(&value), r#"{"z":2,"a":[true,{}],"m":"x"}"#);
+    }
+
+    #[test]
+    fn reports_errors_with_positions() {
+        assert_eq!(error("{\n  \"a\": }"), "Invalid JSON at line 2, column 8: unexpected character '}'");
+        assert!(error("[1, 2").contains("expected ',' or ']'"));
+        assert!(error("\"open").ends_with("unterminated string"));
+        assert!(error("01").ends_with("unexpected trailing characters"));
+        assert!(error("1.").ends_with("expected digits after '.'"));
+        assert!(error("tru").ends_with("invalid literal, expected true"));
+        assert!(error(r#""\ud83d""#).ends_with("unpaired surrogate in \\u escape"));
+        assert!(error("\"a\u{1}\"").ends_with("control character in string"));
+        assert!(error(&"[".repeat(MAX_DEPTH + 2)).ends_with("nested too deeply"));
+    }
+
+    #[test]
+    fn stringifies_with_indent() {
+        let value = parse(r#"{"a": [1, 2.0], "b": {}, "c": "t\u0001"}"#).unwrap();
+        assert_eq!(compact(&value), r#"{"a":[1,2.0],"b":{},"c":"t\u0001"}"#);
+        let mut pretty = String::new();
+        stringify(&value, 2, 0, &mut pretty).unwrap();
+        assert_eq!(pretty, "{\n  \"a\": [\n    1,\n    2.0\n  ],\n  \"b\": {},\n  \"c\": \"t\\u0001\"\n}");
+        // 書き出したテキストを読み込むと同じ値になる
+        assert_eq!(parse(&pretty).unwrap(), value);
+    }
+
+    #[test]
+    fn stringify_checks_its_arguments() {
+        let mut interpreter = Interpreter::new();
+        let value = Value::array(vec![Value::Number(1)]);
+        let wide = json_stringify(&mut interpreter, vec![value.clone(), Value::Number(1_000_000)]).unwrap();
+        // 字下げは 10 までにする
+        assert_eq!(wide, Value::text(format!("[\n{}1\n]", " ".repeat(MAX_INDENT as usize))));
+        assert!(json_stringify(&mut interpreter, vec![value, Value::Number(-1)]).is_err());
+        assert!(json_stringify(&mut interpreter, vec![Value::Float(f64::NAN)]).is_err());
+        let function = Value::Function("f".into());
+        assert!(matches!(json_stringify(&mut interpreter, vec![function]), Err(Error::Type(_))));
+    }
+}
@@ -0,0 +1,392 @@
+// src/stdlib/math.rs
+
+use super::{expect_arg_range, expect_args, float_arg, num_arg};
+use crate::ast::Value;
+use crate::builtins::{self, NativeFn};
+use crate::interpreter::Interpreter;
+use crate::utils::error::{Error, Result};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// 整数と小数のどちらも受け付ける。結果の型は、整数で表せる演算（abs, min, pow など）は
+// 引数が整数なら整数、sqrt や三角関数などは常に小数になる
+pub fn register(natives: &mut HashMap<String, NativeFn>) {
+    natives.insert("math.abs".into(), math_abs);
+    natives.insert("math.min".into(), math_min);
+    natives.insert("math.max".into(), math_max);
+    natives.insert("math.pow".into(), math_pow);
+    natives.insert("math.sqrt".into(), math_sqrt);
+    natives.insert("math.floor".into(), math_floor);
+    natives.insert("math.ceil".into(), math_ceil);
+    natives.insert("math.round".into(), math_round);
+    natives.insert("math.sin".into(), math_sin);
+    natives.insert("math.cos".into(), math_cos);
+    natives.insert("math.tan".into(), math_tan);
+    natives.insert("math.asin".into(), math_asin);
+    natives.insert("math.acos".into(), math_acos);
+    natives.insert("math.atan".into(), math_atan);
+    natives.insert("math.atan2".into(), math_atan2);
+    natives.insert("math.exp".into(), math_exp);
+    natives.insert("math.log".into(), math_log);
+    natives.insert("math.log2".into(), math_log2);
+    natives.insert("math.log10".into(), math_log10);
+    natives.insert("math.div".into(), math_div);
+    natives.insert("math.mod".into(), math_mod);
+    natives.insert("math.gcd".into(), math_gcd);
+    natives.insert("math.lcm".into(), math_lcm);
+    natives.insert("math.clamp".into(), math_clamp);
+}
+
+/// math モジュールの定数（math.pi など）
+pub fn constant(name: &str) -> Option<Value> {
+    match name {
+        "pi" => Some(Value::Float(std::f64::consts::PI)),
+        "e" => Some(Value::Float(std::f64::consts::E)),
+        "tau" => Some(Value::Float(std::f64::consts::TAU)),
+        "inf" => Some(Value::Float(f64::INFINITY)),
+        _ => None,
+    }
+}
+
+fn domain_error(name: &str, value: f64) -> Error {
+    Error::Runtime(format!("{}: {:?} is out of the domain", name, value))
+}
+
+fn overflow_error(name: &str) -> Error {
+    Error::Runtime(format!("{}: integer overflow", name))
+}
+
+/// math.abs(x): 絶対値
+fn math_abs(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("math.abs", &args, 1)?;
+    match &args[0] {
+        Value::Number(n) => n.checked_abs().map(Value::Number).ok_or_else(|| overflow_error("math.abs")),
+        other => Ok(Value::Float(float_arg("math.abs", other)?.abs())),
+    }
+}
+
+// min / max は可変個の引数、または配列 1 つを受け取る
+fn extremum(name: &str, args: Vec<Value>, pick_right: fn(f64, f64) -> bool) -> Result<Value> {
+    let values = match <[Value; 1]>::try_from(args) {
+        Ok([Value::Array(items)]) => Rc::unwrap_or_clone(items),
+        Ok([single]) => vec![single],
+        Err(args) => args,
+    };
+    let mut best: Option<Value> = None;
+    for value in values {
+        let current = float_arg(name, &value)?;
+        if current.is_nan() {
+            return Err(domain_error(name, current));
+        }
+        best = match best {
+            Some(best) if !pick_right(float_arg(name, &best)?, current) => Some(best),
+            _ => Some(value),
+        };
+    }
+    best.ok_or_else(|| Error::Runtime(format!("Function {} expected at least 1 value", name)))
+}
+
+/// math.min(a, b, ...) / math.min(array): 最小値
+fn math_min(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    extremum("math.min", args, |best, current| current < best)
+}
+
+/// math.max(a, b, ...) / math.max(array): 最大値
+fn math_max(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    extremum("math.max", args, |best, current| current > best)
+}
+
+/// math.pow(base, exponent): べき乗。どちらも整数で exponent が 0 以上なら整数
+fn math_pow(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("math.pow", &args, 2)?;
+    match (&args[0], &args[1]) {
+        (Value::Number(base), Value::Number(exponent)) if *exponent >= 0 => u32::try_from(*exponent)
+            .ok()
+            .and_then(|exponent| base.checked_pow(exponent))
+            .map(Value::Number)
+            .ok_or_else(|| overflow_error("math.pow")),
+        (base, exponent) => {
+            let result = float_arg("math.pow", base)?.powf(float_arg("math.pow", exponent)?);
+            if result.is_nan() {
+                return Err(Error::Runtime(format!("math.pow: {} to the power {} is undefined", base, exponent)));
+            }
+            Ok(Value::Float(result))
+        }
+    }
+}
+
+// 小数 1 つを受け取って小数を返す関数の共通部分（domain の外はエラー）
+fn float_function(name: &str, args: Vec<Value>, domain: fn(f64) -> bool, f: fn(f64) -> f64) -> Result<Value> {
+    expect_args(name, &args, 1)?;
+    let x = float_arg(name, &args[0])?;
+    if !domain(x) {
+        return Err(domain_error(name, x));
+    }
+    Ok(Value::Float(f(x)))
+}
+
+fn any_real(x: f64) -> bool {
+    !x.is_nan()
+}
+
+fn positive(x: f64) -> bool {
+    x > 0.0
+}
+
+/// math.sqrt(x): 平方根（x は 0 以上）
+fn math_sqrt(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    float_function("math.sqrt", args, |x| x >= 0.0, f64::sqrt)
+}
+
+// 小数を整数に丸める関数の共通部分（整数はそのまま返す）
+fn rounding(name: &str, args: Vec<Value>, f: fn(f64) -> f64) -> Result<Value> {
+    expect_args(name, &args, 1)?;
+    match &args[0] {
+        Value::Number(n) => Ok(Value::Number(*n)),
+        other => {
+            let x = float_arg(name, other)?;
+            builtins::to_num(Value::Float(f(x))).map_err(|_| domain_error(name, x))
+        }
+    }
+}
+
+/// math.floor(x): x 以下の最大の整数
+fn math_floor(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    rounding("math.floor", args, f64::floor)
+}
+
+/// math.ceil(x): x 以上の最小の整数
+fn math_ceil(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    rounding("math.ceil", args, f64::ceil)
+}
+
+/// math.round(x): 最も近い整数（ちょうど中間なら 0 から遠い方）
+fn math_round(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    rounding("math.round", args, f64::round)
+}
+
+/// math.sin(x): 正弦（ラジアン）
+fn math_sin(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    float_function("math.sin", args, f64::is_finite, f64::sin)
+}
+
+/// math.cos(x): 余弦（ラジアン）
+fn math_cos(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    float_function("math.cos", args, f64::is_finite, f64::cos)
+}
+
+/// math.tan(x): 正接（ラジアン）
+fn math_tan(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    float_function("math.tan", args, f64::is_finite, f64::tan)
+}
+
+/// math.asin(x): 逆正弦（x は -1 以上 1 以下）
+fn math_asin(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    float_function("math.asin", args, |x| (-1.0..=1.0).contains(&x), f64::asin)
+}
+
+/// math.acos(x): 逆余弦（x は -1 以上 1 以下）
+fn math_acos(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    float_function("math.acos", args, |x| (-1.0..=1.0).contains(&x), f64::acos)
+}
+
+/// math.atan(x): 逆正接
+fn math_atan(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    float_function("math.atan", args, any_real, f64::atan)
+}
+
+/// math.atan2(y, x): 点 (x, y) の偏角
+fn math_atan2(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("math.atan2", &args, 2)?;
+    let y = float_arg("math.atan2", &args[0])?;
+    let x = float_arg("math.atan2", &args[1])?;
+    Ok(Value::Float(y.atan2(x)))
+}
+
+/// math.exp(x): e の x 乗
+fn math_exp(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    float_function("math.exp", args, any_real, f64::exp)
+}
+
+/// math.log(x) / math.log(x, base): 自然対数（base を指定するとその底の対数）
+fn math_log(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_arg_range("math.log", &args, 1, 2)?;
+    let x = float_arg("math.log", &args[0])?;
+    if !positive(x) {
+        return Err(domain_error("math.log", x));
+    }
+    match args.get(1) {
+        None => Ok(Value::Float(x.ln())),
+        Some(base) => {
+            let base = float_arg("math.log", base)?;
+            if !positive(base) || base == 1.0 {
+                return Err(Error::Runtime(format!("math.log: invalid base {:?}", base)));
+            }
+            Ok(Value::Float(x.log(base)))
+        }
+    }
+}
+
+/// math.log2(x): 2 を底とする対数
+fn math_log2(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    float_function("math.log2", args, positive, f64::log2)
+}
+
+/// math.log10(x): 10 を底とする対数
+fn math_log10(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    float_function("math.log10", args, positive, f64::log10)
+}
+
+// div / mod の引数を取り出す（0 での除算はエラー）
+fn division_args(name: &str, args: &[Value]) -> Result<(Value, Value)> {
+    expect_args(name, args, 2)?;
+    if float_arg(name, &args[1])? == 0.0 {
+        return Err(Error::Runtime("Division by zero".into()));
+    }
+    Ok((args[0].clone(), args[1].clone()))
+}
+
+/// math.div(a, b): 負の無限大方向に丸めた商（-7 div 2 は -4）
+fn math_div(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    match division_args("math.div", &args)? {
+        (Value::Number(a), Value::Number(b)) => {
+            let quotient = a.checked_div(b).ok_or_else(|| overflow_error("math.div"))?;
+            // 割り切れず符号が異なる場合は、0 方向への切り捨てを 1 つ下げる
+            let floored = if a % b != 0 && (a < 0) != (b < 0) { quotient - 1 } else { quotient };
+            Ok(Value::Number(floored))
+        }
+        (a, b) => Ok(Value::Float((float_arg("math.div", &a)? / float_arg("math.div", &b)?).floor())),
+    }
+}
+
+/// math.mod(a, b): math.div と組になる剰余。結果の符号は b と同じ（-7 mod 2 は 1）
+fn math_mod(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    match division_args("math.mod", &args)? {
+        (Value::Number(a), Value::Number(b)) => {
+            let remainder = a.checked_rem(b).ok_or_else(|| overflow_error("math.mod"))?;
+            let floored = if remainder != 0 && (remainder < 0) != (b < 0) { remainder + b } else { remainder };
+            Ok(Value::Number(floored))
+        }
+        (a, b) => {
+            let (a, b) = (float_arg("math.mod", &a)?, float_arg("math.mod", &b)?);
+            Ok(Value::Float(a - b * (a / b).floor()))
+        }
+    }
+}
+
+fn gcd(a: i64, b: i64) -> Option<i64> {
+    let (mut a, mut b) = (a.unsigned_abs(), b.unsigned_abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    i64::try_from(a).ok()
+}
+
+/// math.gcd(a, b): 最大公約数（0 以上）
+fn math_gcd(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("math.gcd", &args, 2)?;
+    let a = num_arg("math.gcd", &args[0])?;
+    let b = num_arg("math.gcd", &args[1])?;
+    gcd(a, b).map(Value::Number).ok_or_else(|| overflow_error("math.gcd"))
+}
+
+/// math.lcm(a, b): 最小公倍数（0 以上）
+fn math_lcm(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("math.lcm", &args, 2)?;
+    let a = num_arg("math.lcm", &args[0])?;
+    let b = num_arg("math.lcm", &args[1])?;
+    if a == 0 || b == 0 {
+        return Ok(Value::Number(0));
+    }
+    gcd(a, b)
+        .and_then(|divisor| (a / divisor).checked_mul(b))
+        .and_then(i64::checked_abs)
+        .map(Value::Number)
+        .ok_or_else(|| overflow_error("math.lcm"))
+}
+
+/// math.clamp(x, low, high): x を low 以上 high 以下に収める
+fn math_clamp(_: &mut Interpreter, mut args: Vec<Value>) -> Result<Value> {
+    expect_args("math.clamp", &args, 3)?;
+    let high = args.pop().unwrap_or(Value::None);
+    let low = args.pop().unwrap_or(Value::None);
+    let value = args.pop().unwrap_or(Value::None);
+    let (x, lo, hi) = (
+        float_arg("math.clamp", &value)?,
+        float_arg("math.clamp", &low)?,
+        float_arg("math.clamp", &high)?,
+    );
+    if lo.is_nan() || hi.is_nan() || lo > hi {
+        return Err(Error::Runtime(format!("math.clamp: low {} is greater than high {}", low, high)));
+    }
+    Ok(if x < lo {
+        low
+    } else if x > hi {
+        high
+    } else {
+        value
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(f: NativeFn, args: Vec<Value>) -> Result<Value> {
+        f(&mut Interpreter::new(), args)
+    }
+
+    fn runtime_error(result: Result<Value>, expected: &str) {
+        match result {
+            Err(Error::Runtime(message)) => assert_eq!(message, expected),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn integers_stay_integers() {
+        let n = Value::Number;
+        assert_eq!(call(math_abs, vec![n(-3)]).unwrap(), n(3));
+        assert_eq!(call(math_abs, vec![Value::Float(-1.5)]).unwrap(), Value::Float(1.5));
+        assert_eq!(call(math_pow, vec![n(2), n(10)]).unwrap(), n(1024));
+        assert_eq!(call(math_pow, vec![n(2), n(-1)]).unwrap(), Value::Float(0.5));
+        assert_eq!(call(math_min, vec![n(3), Value::Float(1.5), n(2)]).unwrap(), Value::Float(1.5));
+        assert_eq!(call(math_max, vec![Value::array(vec![n(3), n(7), n(2)])]).unwrap(), n(7));
+        assert_eq!(call(math_floor, vec![Value::Float(-1.5)]).unwrap(), n(-2));
+        assert_eq!(call(math_ceil, vec![Value::Float(1.2)]).unwrap(), n(2));
+        assert_eq!(call(math_gcd, vec![n(-12), n(18)]).unwrap(), n(6));
+        assert_eq!(call(math_lcm, vec![n(4), n(-6)]).unwrap(), n(12));
+        assert_eq!(call(math_clamp, vec![n(15), n(0), n(10)]).unwrap(), n(10));
+        runtime_error(call(math_abs, vec![n(i64::MIN)]), "math.abs: integer overflow");
+        runtime_error(call(math_pow, vec![n(10), n(19)]), "math.pow: integer overflow");
+    }
+
+    #[test]
+    fn div_and_mod_round_toward_negative_infinity() {
+        let n = Value::Number;
+        let cases = [(7, 2, 3, 1), (-7, 2, -4, 1), (7, -2, -4, -1), (-7, -2, 3, -1), (-6, 3, -2, 0)];
+        for (a, b, quotient, remainder) in cases {
+            assert_eq!(call(math_div, vec![n(a), n(b)]).unwrap(), n(quotient), "{} div {}", a, b);
+            assert_eq!(call(math_mod, vec![n(a), n(b)]).unwrap(), n(remainder), "{} mod {}", a, b);
+        }
+        assert_eq!(call(math_mod, vec![Value::Float(-7.5), n(2)]).unwrap(), Value::Float(0.5));
+        runtime_error(call(math_div, vec![n(1), n(0)]), "Division by zero");
+        runtime_error(call(math_mod, vec![n(1), Value::Float(0.0)]), "Division by zero");
+        runtime_error(call(math_div, vec![n(i64::MIN), n(-1)]), "math.div: integer overflow");
+    }
+
+    #[test]
+    fn domain_errors_are_runtime_errors() {
+        runtime_error(call(math_sqrt, vec![Value::Number(-1)]), "math.sqrt: -1.0 is out of the domain");
+        runtime_error(call(math_log, vec![Value::Number(0)]), "math.log: 0.0 is out of the domain");
+        runtime_error(call(math_asin, vec![Value::Float(2.0)]), "math.asin: 2.0 is out of the domain");
+        runtime_error(
+            call(math_clamp, vec![Value::Number(1), Value::Number(5), Value::Number(0)]),
+            "math.clamp: low 5 is greater than high 0",
+        );
+        assert!(matches!(call(math_sqrt, vec![Value::text("4")]), Err(Error::Type(_))));
+        assert_eq!(call(math_sqrt, vec![Value::Number(4)]).unwrap(), Value::Float(2.0));
+        assert_eq!(constant("pi"), Some(Value::Float(std::f64::consts::PI)));
+        assert_eq!(constant("nope"), None);
+    }
+}
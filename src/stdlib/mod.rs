@@ -0,0 +1,113 @@
+// src/stdlib/mod.rs
+
+pub mod capabilities;
+pub mod collections;
+pub mod file;
+pub mod json;
+pub mod math;
+pub mod os;
+pub mod random;
+pub mod text;
+
+use crate::ast::Value;
+use crate::builtins::{MutatorFn, NativeFn};
+use crate::utils::error::{Error, Result};
+use std::collections::HashMap;
+
+pub use capabilities::{require, require_path, Access, Capabilities, Capability};
+
+/// 標準ライブラリのパッケージ名（Import from elium to os）
+pub const STD_PACKAGE: &str = "elium";
+
+/// 組み込みモジュールの一覧
+pub const MODULES: [&str; 6] = ["os", "file", "text", "math", "random", "json"];
+
+/// 組み込みモジュールのネイティブ関数を "os.args" のような名前で登録する
+/// （配列・マップのメソッドは "array.push" のような型名つきの名前で登録する）
+pub fn register(natives: &mut HashMap<String, NativeFn>, mutators: &mut HashMap<String, MutatorFn>) {
+    collections::register(natives, mutators);
+    os::register(natives);
+    file::register(natives);
+    text::register(natives);
+    math::register(natives);
+    random::register(natives);
+    json::register(natives);
+}
+
+/// 組み込みモジュールの定数（math.pi など）
+pub fn constant(module: &str, name: &str) -> Option<Value> {
+    match module {
+        "math" => math::constant(name),
+        _ => None,
+    }
+}
+
+/// モジュール名が組み込みモジュール（elium.os または os）なら、その名前を返す
+pub fn builtin_module(module_name: &str) -> Option<&'static str> {
+    let name = module_name
+        .strip_prefix(STD_PACKAGE)
+        .and_then(|rest| rest.strip_prefix('.'))
+        .unwrap_or(module_name);
+    MODULES.iter().copied().find(|module| *module == name)
+}
+
+/// 引数の個数を検査する
+pub fn expect_args(name: &str, args: &[Value], count: usize) -> Result<()> {
+    if args.len() != count {
+        return Err(Error::Runtime(format!(
+            "Function {} expected {} arguments, but got {}",
+            name,
+            count,
+            args.len()
+        )));
+    }
+    Ok(())
+}
+
+/// 引数の個数が min 以上 max 以下かを検査する（省略可能な引数がある関数用）
+pub fn expect_arg_range(name: &str, args: &[Value], min: usize, max: usize) -> Result<()> {
+    if args.len() < min || args.len() > max {
+        return Err(Error::Runtime(format!(
+            "Function {} expected {} to {} arguments, but got {}",
+            name,
+            min,
+            max,
+            args.len()
+        )));
+    }
+    Ok(())
+}
+
+/// 整数の引数を取り出す
+pub fn num_arg(name: &str, value: &Value) -> Result<i64> {
+    match value {
+        Value::Number(n) => Ok(*n),
+        other => Err(Error::Type(format!("{} expects num, but got {}", name, other.type_name()))),
+    }
+}
+
+/// 数値の引数を小数として取り出す（整数も受け付ける）
+pub fn float_arg(name: &str, value: &Value) -> Result<f64> {
+    match value {
+        Value::Number(n) => Ok(*n as f64),
+        Value::Float(f) => Ok(*f),
+        other => Err(Error::Type(format!("{} expects num or float, but got {}", name, other.type_name()))),
+    }
+}
+
+/// slice の位置を 0..=len に丸める（負の位置は末尾から数える）
+pub fn clamp_index(index: i64, len: usize) -> usize {
+    if index < 0 {
+        len.saturating_sub(index.unsigned_abs() as usize)
+    } else {
+        (index as usize).min(len)
+    }
+}
+
+/// テキストの引数を取り出す
+pub fn text_arg(name: &str, value: &Value) -> Result<String> {
+    match value {
+        Value::Text(s) => Ok(s.to_string()),
+        other => Err(Error::Type(format!("{} expects text, but got {}", name, other.type_name()))),
+    }
+}
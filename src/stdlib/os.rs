@@ -0,0 +1,92 @@
+// src/stdlib/os.rs
+
+use super::{expect_args, require, text_arg, Capability};
+use crate::ast::Value;
+use crate::builtins::NativeFn;
+use crate::interpreter::Interpreter;
+use crate::utils::error::{Error, Result};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub fn register(natives: &mut HashMap<String, NativeFn>) {
+    natives.insert("os.args".into(), os_args);
+    natives.insert("os.env".into(), os_env);
+    natives.insert("os.set_env".into(), os_set_env);
+    natives.insert("os.cwd".into(), os_cwd);
+    natives.insert("os.platform".into(), os_platform);
+    natives.insert("os.time".into(), os_time);
+    natives.insert("os.exit".into(), os_exit);
+}
+
+/// os.args(): スクリプトに渡された引数の配列
+fn os_args(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    require(interpreter, Capability::Process)?;
+    expect_args("os.args", &args, 0)?;
+    Ok(Value::array(interpreter.args.iter().map(|arg| Value::text(arg.as_str())).collect()))
+}
+
+/// os.env(name): 環境変数の値（未設定なら None）
+fn os_env(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    require(interpreter, Capability::Env)?;
+    expect_args("os.env", &args, 1)?;
+    let name = text_arg("os.env", &args[0])?;
+    Ok(std::env::var(name).map(Value::text).unwrap_or(Value::None))
+}
+
+/// os.set_env(name, value): 環境変数を設定する
+fn os_set_env(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    require(interpreter, Capability::Env)?;
+    expect_args("os.set_env", &args, 2)?;
+    let name = text_arg("os.set_env", &args[0])?;
+    if name.is_empty() || name.contains(['=', '\0']) {
+        return Err(Error::Runtime(format!("Invalid environment variable name: {:?}", name)));
+    }
+    let value = args[1].to_string();
+    if value.contains('\0') {
+        return Err(Error::Runtime("Environment variable value must not contain NUL".into()));
+    }
+    std::env::set_var(name, value);
+    Ok(Value::None)
+}
+
+/// os.cwd(): カレントディレクトリ
+fn os_cwd(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    require(interpreter, Capability::Env)?;
+    expect_args("os.cwd", &args, 0)?;
+    let dir = std::env::current_dir()
+        .map_err(|e| Error::Runtime(format!("Failed to get current directory: {}", e)))?;
+    Ok(Value::text(dir.display().to_string()))
+}
+
+/// os.platform(): "linux", "macos", "windows" など
+fn os_platform(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    require(interpreter, Capability::Env)?;
+    expect_args("os.platform", &args, 0)?;
+    Ok(Value::text(std::env::consts::OS.to_string()))
+}
+
+/// os.time(): UNIX エポックからの経過秒数（小数）
+fn os_time(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    require(interpreter, Capability::Clock)?;
+    expect_args("os.time", &args, 0)?;
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| Error::Runtime(format!("System clock is before the UNIX epoch: {}", e)))?;
+    Ok(Value::Float(elapsed.as_secs_f64()))
+}
+
+/// os.exit(code): 終了コードを指定してプロセスを終了する（省略時は 0）
+fn os_exit(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    require(interpreter, Capability::Process)?;
+    let code = match args.as_slice() {
+        [] => 0,
+        [Value::Number(code)] => i32::try_from(*code)
+            .map_err(|_| Error::Runtime(format!("Exit code out of range: {}", code)))?,
+        [other] => return Err(Error::Type(format!("os.exit expects num, but got {}", other.type_name()))),
+        _ => return Err(Error::Runtime(format!(
+            "Function os.exit expected 1 arguments, but got {}",
+            args.len()
+        ))),
+    };
+    std::process::exit(code);
+}
@@ -0,0 +1,246 @@
+// src/stdlib/random.rs
+
+use super::{expect_arg_range, expect_args, float_arg, num_arg};
+use crate::ast::Value;
+use crate::builtins::NativeFn;
+use crate::interpreter::Interpreter;
+use crate::utils::error::{Error, Result};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// 疑似乱数生成器（xoshiro256**）。同じシードからは常に同じ列を生成する
+#[derive(Debug, Clone)]
+pub struct Random {
+    state: [u64; 4],
+}
+
+impl Random {
+    /// シードから生成器を作る（状態は splitmix64 で展開する）
+    pub fn new(seed: u64) -> Self {
+        let mut x = seed;
+        let mut next = || {
+            x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        };
+        Self { state: [next(), next(), next(), next()] }
+    }
+
+    /// 現在時刻とプロセス ID から生成器を作る（--seed を指定しない場合）
+    pub fn from_entropy() -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or(0);
+        Self::new(nanos ^ (u64::from(std::process::id()) << 32))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let [s0, s1, s2, s3] = &mut self.state;
+        let result = s1.wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = *s1 << 17;
+        *s2 ^= *s0;
+        *s3 ^= *s1;
+        *s1 ^= *s2;
+        *s0 ^= *s3;
+        *s2 ^= t;
+        *s3 = s3.rotate_left(45);
+        result
+    }
+
+    /// 0 以上 1 未満の小数
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// 0 以上 bound 未満の整数（偏りが出ないよう、端数の範囲に入ったら引き直す）
+    pub fn below(&mut self, bound: u64) -> u64 {
+        let zone = u64::MAX - u64::MAX % bound;
+        loop {
+            let x = self.next_u64();
+            if x < zone {
+                return x % bound;
+            }
+        }
+    }
+}
+
+pub fn register(natives: &mut HashMap<String, NativeFn>) {
+    natives.insert("random.seed".into(), random_seed);
+    natives.insert("random.int".into(), random_int);
+    natives.insert("random.float".into(), random_float);
+    natives.insert("random.choice".into(), random_choice);
+    natives.insert("random.shuffle".into(), random_shuffle);
+    natives.insert("random.sample".into(), random_sample);
+}
+
+fn array_arg(name: &str, value: Value) -> Result<Vec<Value>> {
+    match value {
+        Value::Array(items) => Ok(Rc::unwrap_or_clone(items)),
+        other => Err(Error::Type(format!("{} expects array, but got {}", name, other.type_name()))),
+    }
+}
+
+// Fisher-Yates で先頭の count 個を無作為に選ぶ（count が長さと同じなら全体のシャッフル）
+fn shuffle_prefix(interpreter: &Interpreter, items: &mut [Value], count: usize) {
+    let mut random = interpreter.random.borrow_mut();
+    for i in 0..count.min(items.len().saturating_sub(1)) {
+        let j = i + random.below((items.len() - i) as u64) as usize;
+        items.swap(i, j);
+    }
+}
+
+/// random.seed(n): 乱数列を n から始め直す（読み込んだモジュールとも共有する）
+fn random_seed(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("random.seed", &args, 1)?;
+    let seed = num_arg("random.seed", &args[0])?;
+    interpreter.seed(seed as u64);
+    Ok(Value::None)
+}
+
+/// random.int(low, high): low 以上 high 以下の整数
+fn random_int(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("random.int", &args, 2)?;
+    let low = num_arg("random.int", &args[0])?;
+    let high = num_arg("random.int", &args[1])?;
+    if low > high {
+        return Err(Error::Runtime(format!("random.int: low {} is greater than high {}", low, high)));
+    }
+    // 範囲の大きさは u64 に収まる（low..=high 全体が i64 の範囲なら 0 は 2^64 を表す）
+    let span = high.wrapping_sub(low) as u64;
+    let offset = match span.checked_add(1) {
+        Some(size) => interpreter.random.borrow_mut().below(size),
+        None => interpreter.random.borrow_mut().next_u64(),
+    };
+    Ok(Value::Number(low.wrapping_add(offset as i64)))
+}
+
+/// random.float() / random.float(low, high): 0 以上 1 未満（または low 以上 high 未満）の小数
+fn random_float(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_arg_range("random.float", &args, 0, 2)?;
+    let unit = interpreter.random.borrow_mut().next_f64();
+    match args.as_slice() {
+        [] => Ok(Value::Float(unit)),
+        [low, high] => {
+            let low = float_arg("random.float", low)?;
+            let high = float_arg("random.float", high)?;
+            if low > high || !(high - low).is_finite() {
+                return Err(Error::Runtime(format!("random.float: invalid range {:?} to {:?}", low, high)));
+            }
+            Ok(Value::Float(low + (high - low) * unit))
+        }
+        _ => Err(Error::Runtime("Function random.float expected 0 or 2 arguments, but got 1".into())),
+    }
+}
+
+/// random.choice(array): 配列から無作為に選んだ要素
+fn random_choice(interpreter: &mut Interpreter, mut args: Vec<Value>) -> Result<Value> {
+    expect_args("random.choice", &args, 1)?;
+    let mut items = array_arg("random.choice", args.remove(0))?;
+    if items.is_empty() {
+        return Err(Error::Runtime("random.choice: array is empty".into()));
+    }
+    let index = interpreter.random.borrow_mut().below(items.len() as u64) as usize;
+    Ok(items.swap_remove(index))
+}
+
+/// random.shuffle(array): 要素を無作為に並べ替えた配列
+fn random_shuffle(interpreter: &mut Interpreter, mut args: Vec<Value>) -> Result<Value> {
+    expect_args("random.shuffle", &args, 1)?;
+    let mut items = array_arg("random.shuffle", args.remove(0))?;
+    let count = items.len();
+    shuffle_prefix(interpreter, &mut items, count);
+    Ok(Value::array(items))
+}
+
+/// random.sample(array, count): 重複なしで無作為に選んだ count 個の要素の配列
+fn random_sample(interpreter: &mut Interpreter, mut args: Vec<Value>) -> Result<Value> {
+    expect_args("random.sample", &args, 2)?;
+    let count = num_arg("random.sample", &args[1])?;
+    let mut items = array_arg("random.sample", args.remove(0))?;
+    if count < 0 || count as usize > items.len() {
+        return Err(Error::Runtime(format!(
+            "random.sample: cannot take {} items from an array of length {}",
+            count,
+            items.len()
+        )));
+    }
+    shuffle_prefix(interpreter, &mut items, count as usize);
+    items.truncate(count as usize);
+    Ok(Value::array(items))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // シードを指定したインタプリタで関数を呼ぶ
+    fn seeded(seed: u64) -> Interpreter {
+        let mut interpreter = Interpreter::new();
+        interpreter.seed(seed);
+        interpreter
+    }
+
+    fn numbers(range: std::ops::Range<i64>) -> Value {
+        Value::array(range.map(Value::Number).collect())
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_values() {
+        let draw = |interpreter: &mut Interpreter| -> Vec<Value> {
+            vec![
+                random_int(interpreter, vec![Value::Number(1), Value::Number(100)]).unwrap(),
+                random_float(interpreter, vec![]).unwrap(),
+                random_choice(interpreter, vec![numbers(0..10)]).unwrap(),
+                random_shuffle(interpreter, vec![numbers(0..10)]).unwrap(),
+                random_sample(interpreter, vec![numbers(0..10), Value::Number(3)]).unwrap(),
+            ]
+        };
+        let first = draw(&mut seeded(42));
+        assert_eq!(first, draw(&mut seeded(42)));
+        assert_ne!(first, draw(&mut seeded(43)));
+        // random.seed で途中から列をやり直せる
+        let mut interpreter = seeded(1);
+        random_seed(&mut interpreter, vec![Value::Number(42)]).unwrap();
+        assert_eq!(first, draw(&mut interpreter));
+    }
+
+    #[test]
+    fn values_stay_in_range() {
+        let mut interpreter = seeded(7);
+        for _ in 0..1000 {
+            let Value::Number(n) = random_int(&mut interpreter, vec![Value::Number(-3), Value::Number(3)]).unwrap() else {
+                panic!("random.int returned a non-number")
+            };
+            assert!((-3..=3).contains(&n), "{}", n);
+            let Value::Float(f) = random_float(&mut interpreter, vec![Value::Float(2.0), Value::Float(2.5)]).unwrap() else {
+                panic!("random.float returned a non-float")
+            };
+            assert!((2.0..2.5).contains(&f), "{}", f);
+        }
+        // 全範囲でもあふれない
+        random_int(&mut interpreter, vec![Value::Number(i64::MIN), Value::Number(i64::MAX)]).unwrap();
+        // 並べ替えても要素は変わらない
+        for shuffled in [
+            random_shuffle(&mut interpreter, vec![numbers(0..20)]).unwrap(),
+            random_sample(&mut interpreter, vec![numbers(0..20), Value::Number(20)]).unwrap(),
+        ] {
+            let mut items = array_arg("test", shuffled).unwrap();
+            items.sort_by_key(|item| if let Value::Number(n) = item { *n } else { i64::MAX });
+            assert_eq!(Value::array(items), numbers(0..20));
+        }
+    }
+
+    #[test]
+    fn invalid_arguments_are_errors() {
+        let mut interpreter = seeded(0);
+        let n = Value::Number;
+        assert!(matches!(random_int(&mut interpreter, vec![n(3), n(1)]), Err(Error::Runtime(_))));
+        assert!(matches!(random_choice(&mut interpreter, vec![numbers(0..0)]), Err(Error::Runtime(_))));
+        assert!(matches!(random_sample(&mut interpreter, vec![numbers(0..2), n(3)]), Err(Error::Runtime(_))));
+        assert!(matches!(random_sample(&mut interpreter, vec![numbers(0..2), n(-1)]), Err(Error::Runtime(_))));
+        assert!(matches!(random_shuffle(&mut interpreter, vec![n(1)]), Err(Error::Type(_))));
+    }
+}
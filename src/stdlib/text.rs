@@ -0,0 +1,352 @@
+// src/stdlib/text.rs
+
+use super::{clamp_index, expect_arg_range, expect_args, num_arg, text_arg};
+use crate::ast::Value;
+use crate::builtins::NativeFn;
+use crate::interpreter::Interpreter;
+use crate::utils::error::{Error, Result};
+use std::collections::HashMap;
+
+// text の関数はすべてテキストを第 1 引数に取るので、
+// "text.upper" の名前で登録すれば s.upper() と text.upper(s) のどちらでも呼べる。
+// 位置と長さはバイトではなく文字（Unicode のスカラー値）単位で数える
+pub fn register(natives: &mut HashMap<String, NativeFn>) {
+    natives.insert("text.slice".into(), text_slice);
+    natives.insert("text.split".into(), text_split);
+    natives.insert("text.join".into(), text_join);
+    natives.insert("array.join".into(), text_join);
+    natives.insert("text.trim".into(), text_trim);
+    natives.insert("text.trim_start".into(), text_trim_start);
+    natives.insert("text.trim_end".into(), text_trim_end);
+    natives.insert("text.upper".into(), text_upper);
+    natives.insert("text.lower".into(), text_lower);
+    natives.insert("text.contains".into(), text_contains);
+    natives.insert("text.starts_with".into(), text_starts_with);
+    natives.insert("text.ends_with".into(), text_ends_with);
+    natives.insert("text.find".into(), text_find);
+    natives.insert("text.replace".into(), text_replace);
+    natives.insert("text.repeat".into(), text_repeat);
+    natives.insert("text.pad_start".into(), text_pad_start);
+    natives.insert("text.pad_end".into(), text_pad_end);
+    natives.insert("text.chars".into(), text_chars);
+    natives.insert("text.code".into(), text_code);
+    natives.insert("text.from_code".into(), text_from_code);
+    natives.insert("text.format".into(), text_format);
+    natives.insert("num.format".into(), text_format);
+    natives.insert("float.format".into(), text_format);
+}
+
+/// text.slice(s, start, end): start 文字目から end 文字目の手前まで（end を省略すると末尾まで）
+fn text_slice(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_arg_range("text.slice", &args, 2, 3)?;
+    let s = text_arg("text.slice", &args[0])?;
+    let len = s.chars().count();
+    let start = clamp_index(num_arg("text.slice", &args[1])?, len);
+    let end = match args.get(2) {
+        Some(end) => clamp_index(num_arg("text.slice", end)?, len),
+        None => len,
+    };
+    let sliced: String = s.chars().skip(start).take(end.saturating_sub(start)).collect();
+    Ok(Value::text(sliced))
+}
+
+/// text.split(s, sep): sep で分けた配列。sep が空なら 1 文字ずつに分ける
+fn text_split(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("text.split", &args, 2)?;
+    let s = text_arg("text.split", &args[0])?;
+    let sep = text_arg("text.split", &args[1])?;
+    let parts = if sep.is_empty() {
+        s.chars().map(|c| Value::text(c.to_string())).collect()
+    } else {
+        s.split(sep.as_str()).map(|part| Value::text(part.to_string())).collect()
+    };
+    Ok(Value::array(parts))
+}
+
+/// text.join(array, sep) / array.join(sep): 配列の要素をテキストにして sep でつなぐ
+fn text_join(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("text.join", &args, 2)?;
+    let items = match &args[0] {
+        Value::Array(items) => items,
+        other => return Err(Error::Type(format!("text.join expects array, but got {}", other.type_name()))),
+    };
+    let sep = text_arg("text.join", &args[1])?;
+    let parts: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+    Ok(Value::text(parts.join(&sep)))
+}
+
+// テキスト 1 つを受け取ってテキストを返す関数の共通部分
+fn map_text(name: &str, args: Vec<Value>, f: impl Fn(&str) -> String) -> Result<Value> {
+    expect_args(name, &args, 1)?;
+    Ok(Value::text(f(&text_arg(name, &args[0])?)))
+}
+
+/// text.trim(s): 前後の空白を取り除く
+fn text_trim(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    map_text("text.trim", args, |s| s.trim().to_string())
+}
+
+/// text.trim_start(s): 先頭の空白を取り除く
+fn text_trim_start(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    map_text("text.trim_start", args, |s| s.trim_start().to_string())
+}
+
+/// text.trim_end(s): 末尾の空白を取り除く
+fn text_trim_end(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    map_text("text.trim_end", args, |s| s.trim_end().to_string())
+}
+
+/// text.upper(s): 大文字にする
+fn text_upper(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    map_text("text.upper", args, str::to_uppercase)
+}
+
+/// text.lower(s): 小文字にする
+fn text_lower(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    map_text("text.lower", args, str::to_lowercase)
+}
+
+// テキスト 2 つを受け取って真偽値を返す関数の共通部分
+fn test_text(name: &str, args: Vec<Value>, f: impl Fn(&str, &str) -> bool) -> Result<Value> {
+    expect_args(name, &args, 2)?;
+    let s = text_arg(name, &args[0])?;
+    let pattern = text_arg(name, &args[1])?;
+    Ok(Value::Boolean(f(&s, &pattern)))
+}
+
+/// text.contains(s, pattern): pattern を含むか
+fn text_contains(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    test_text("text.contains", args, |s, pattern| s.contains(pattern))
+}
+
+/// text.starts_with(s, pattern): pattern で始まるか
+fn text_starts_with(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    test_text("text.starts_with", args, |s, pattern| s.starts_with(pattern))
+}
+
+/// text.ends_with(s, pattern): pattern で終わるか
+fn text_ends_with(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    test_text("text.ends_with", args, |s, pattern| s.ends_with(pattern))
+}
+
+/// text.find(s, pattern): 最初に現れる位置（文字単位）。見つからなければ none
+fn text_find(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("text.find", &args, 2)?;
+    let s = text_arg("text.find", &args[0])?;
+    let pattern = text_arg("text.find", &args[1])?;
+    Ok(match s.find(&pattern) {
+        Some(byte) => Value::Number(s[..byte].chars().count() as i64),
+        None => Value::None,
+    })
+}
+
+/// text.replace(s, from, to): from をすべて to に置き換える
+fn text_replace(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("text.replace", &args, 3)?;
+    let s = text_arg("text.replace", &args[0])?;
+    let from = text_arg("text.replace", &args[1])?;
+    let to = text_arg("text.replace", &args[2])?;
+    if from.is_empty() {
+        return Err(Error::Runtime("text.replace cannot replace an empty text".into()));
+    }
+    Ok(Value::text(s.replace(&from, &to)))
+}
+
+/// text.repeat(s, count): count 回繰り返す
+fn text_repeat(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("text.repeat", &args, 2)?;
+    let s = text_arg("text.repeat", &args[0])?;
+    let count = num_arg("text.repeat", &args[1])?;
+    if count < 0 {
+        return Err(Error::Runtime(format!("text.repeat expects a non-negative count, but got {}", count)));
+    }
+    interpreter.reserve(usize::try_from(count).ok().and_then(|count| s.len().checked_mul(count)))?;
+    Ok(Value::text(s.repeat(count as usize)))
+}
+
+// 文字数が width になるまで fill を詰めるのに必要なテキスト
+fn padding(interpreter: &Interpreter, name: &str, args: &[Value]) -> Result<(String, String)> {
+    expect_arg_range(name, args, 2, 3)?;
+    let s = text_arg(name, &args[0])?;
+    let width = num_arg(name, &args[1])?;
+    let fill = match args.get(2) {
+        Some(fill) => text_arg(name, fill)?,
+        None => " ".to_string(),
+    };
+    let mut fill_chars = fill.chars();
+    let fill = match (fill_chars.next(), fill_chars.next()) {
+        (Some(c), None) => c,
+        _ => return Err(Error::Runtime(format!("{} expects a single fill character, but got {:?}", name, fill))),
+    };
+    let missing = usize::try_from(width.max(0))
+        .map(|width| width.saturating_sub(s.chars().count()))
+        .ok();
+    interpreter.reserve(missing.and_then(|missing| missing.checked_mul(fill.len_utf8())?.checked_add(s.len())))?;
+    let missing = missing.unwrap_or_default();
+    Ok((s, std::iter::repeat_n(fill, missing).collect()))
+}
+
+/// text.pad_start(s, width, fill): 先頭に fill（省略時は空白）を詰めて width 文字にする
+fn text_pad_start(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    let (s, pad) = padding(interpreter, "text.pad_start", &args)?;
+    Ok(Value::text(pad + &s))
+}
+
+/// text.pad_end(s, width, fill): 末尾に fill（省略時は空白）を詰めて width 文字にする
+fn text_pad_end(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    let (s, pad) = padding(interpreter, "text.pad_end", &args)?;
+    Ok(Value::text(s + &pad))
+}
+
+/// text.chars(s): 1 文字ずつの配列
+fn text_chars(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("text.chars", &args, 1)?;
+    let s = text_arg("text.chars", &args[0])?;
+    Ok(Value::array(s.chars().map(|c| Value::text(c.to_string())).collect()))
+}
+
+/// text.code(s): 1 文字のテキストの文字コード（Unicode のコードポイント）
+fn text_code(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("text.code", &args, 1)?;
+    let s = text_arg("text.code", &args[0])?;
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(Value::Number(c as i64)),
+        _ => Err(Error::Runtime(format!("text.code expects a single character, but got {:?}", s))),
+    }
+}
+
+/// text.from_code(code): 文字コードから 1 文字のテキストを作る
+fn text_from_code(_: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_args("text.from_code", &args, 1)?;
+    let code = num_arg("text.from_code", &args[0])?;
+    u32::try_from(code)
+        .ok()
+        .and_then(char::from_u32)
+        .map(|c| Value::text(c.to_string()))
+        .ok_or_else(|| Error::Runtime(format!("Invalid character code: {}", code)))
+}
+
+/// text.format(value, width, precision): 数値を小数点以下 precision 桁にして、
+/// 右寄せで width 文字にする（x.format(8, 2) の形でも呼べる）。テキストは precision 文字までに切り詰める
+fn text_format(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value> {
+    expect_arg_range("text.format", &args, 1, 3)?;
+    let width = match args.get(1) {
+        Some(width) => usize::try_from(num_arg("text.format", width)?.max(0)).ok(),
+        None => Some(0),
+    };
+    let precision = match args.get(2) {
+        Some(precision) => {
+            let precision = num_arg("text.format", precision)?;
+            if precision < 0 {
+                return Err(Error::Runtime(format!(
+                    "text.format expects a non-negative precision, but got {}",
+                    precision
+                )));
+            }
+            Some(usize::try_from(precision).ok())
+        }
+        None => None,
+    };
+    // 書式にした文字列は、小数点以下の桁と整数部（f64 は 309 桁まで）と、字下げを合わせた長さより長くならない
+    let longest = match (&args[0], precision) {
+        (Value::Number(_) | Value::Float(_), Some(precision)) => precision.and_then(|precision| precision.checked_add(320)),
+        _ => Some(args[0].to_string().len()),
+    };
+    interpreter.reserve(longest.zip(width).map(|(longest, width)| longest.max(width)))?;
+    let (width, precision) = (width.unwrap_or_default(), precision.map(Option::unwrap_or_default));
+    let formatted = match (&args[0], precision) {
+        (Value::Number(n), Some(precision)) => format!("{:.*}", precision, *n as f64),
+        (Value::Float(f), Some(precision)) => format!("{:.*}", precision, f),
+        (Value::Text(s), Some(precision)) => s.chars().take(precision).collect(),
+        (Value::Number(_) | Value::Float(_) | Value::Text(_), None) => args[0].to_string(),
+        (other, _) => {
+            return Err(Error::Type(format!(
+                "text.format expects num, float or text, but got {}",
+                other.type_name()
+            )))
+        }
+    };
+    let missing = width.saturating_sub(formatted.chars().count());
+    Ok(Value::text(" ".repeat(missing) + &formatted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::Limits;
+
+    fn call(f: NativeFn, args: Vec<Value>) -> Result<Value> {
+        f(&mut Interpreter::new(), args)
+    }
+
+    fn text(s: &str) -> Value {
+        Value::text(s)
+    }
+
+    #[test]
+    fn format_pads_and_rounds() {
+        let n = Value::Number;
+        assert_eq!(call(text_format, vec![Value::Float(1.23456), n(8), n(2)]).unwrap(), text("    1.23"));
+        assert_eq!(call(text_format, vec![n(7), n(0), n(1)]).unwrap(), text("7.0"));
+        assert_eq!(call(text_format, vec![n(42), n(5)]).unwrap(), text("   42"));
+        assert_eq!(call(text_format, vec![text("ab"), n(4)]).unwrap(), text("  ab"));
+        // テキストの precision は文字数で切り詰める
+        assert_eq!(call(text_format, vec![text("héllo"), n(4), n(2)]).unwrap(), text("  hé"));
+        assert_eq!(call(text_format, vec![text("ab"), n(0), n(10)]).unwrap(), text("ab"));
+        assert!(call(text_format, vec![Value::Float(1.0), n(0), n(-1)]).is_err());
+        match call(text_format, vec![Value::Boolean(true), n(0), n(1)]) {
+            Err(Error::Type(message)) => assert_eq!(message, "text.format expects num, float or text, but got bool"),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn slice_counts_characters() {
+        let n = Value::Number;
+        assert_eq!(call(text_slice, vec![text("héllo"), n(1), n(3)]).unwrap(), text("él"));
+        assert_eq!(call(text_slice, vec![text("hello"), n(2)]).unwrap(), text("llo"));
+        // 負の位置は末尾から数え、範囲外は端に寄せる
+        assert_eq!(call(text_slice, vec![text("hello"), n(-3), n(-1)]).unwrap(), text("ll"));
+        assert_eq!(call(text_slice, vec![text("hello"), n(3), n(100)]).unwrap(), text("lo"));
+        assert_eq!(call(text_slice, vec![text("hello"), n(4), n(2)]).unwrap(), text(""));
+    }
+
+    #[test]
+    fn pad_fills_to_width() {
+        let n = Value::Number;
+        assert_eq!(call(text_pad_start, vec![text("7"), n(3), text("0")]).unwrap(), text("007"));
+        assert_eq!(call(text_pad_end, vec![text("x"), n(3)]).unwrap(), text("x  "));
+        assert_eq!(call(text_pad_end, vec![text("é"), n(3), text("ü")]).unwrap(), text("éüü"));
+        // 既に width 以上なら何もしない
+        assert_eq!(call(text_pad_start, vec![text("long"), n(2)]).unwrap(), text("long"));
+        assert!(call(text_pad_start, vec![text("x"), n(3), text("ab")]).is_err());
+    }
+
+    #[test]
+    fn repeat_checks_the_count() {
+        let n = Value::Number;
+        assert_eq!(call(text_repeat, vec![text("ab"), n(3)]).unwrap(), text("ababab"));
+        assert_eq!(call(text_repeat, vec![text("ab"), n(0)]).unwrap(), text(""));
+        assert!(call(text_repeat, vec![text("ab"), n(-1)]).is_err());
+        // メモリの上限を超える大きさは作る前にエラーにする
+        let mut interpreter = Interpreter::new();
+        interpreter.set_limits(Limits { memory: Some(1000), ..Limits::default() });
+        let result = text_repeat(&mut interpreter, vec![text("ab"), n(1000)]);
+        assert!(matches!(result, Err(Error::MemoryLimit(_))), "{:?}", result);
+    }
+
+    #[test]
+    fn huge_values_are_errors_without_a_memory_limit() {
+        let n = Value::Number;
+        let too_large = |result: Result<Value>| {
+            assert!(matches!(result, Err(Error::Runtime(ref msg)) if msg.starts_with("Value is too large")), "{:?}", result)
+        };
+        too_large(call(text_repeat, vec![text("ab"), n(4_000_000_000_000)]));
+        too_large(call(text_repeat, vec![text("ab"), n(i64::MAX)]));
+        too_large(call(text_pad_start, vec![text("ab"), n(4_000_000_000_000)]));
+        too_large(call(text_pad_end, vec![text("ab"), n(i64::MAX), text("あ")]));
+        too_large(call(text_format, vec![n(1), n(i64::MAX)]));
+        too_large(call(text_format, vec![n(1), n(0), n(i64::MAX)]));
+    }
+}
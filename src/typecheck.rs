@@ -0,0 +1,625 @@
+// src/typecheck.rs
+
+use crate::ast::{ASTNode, Expr, FunctionDef, Value};
+use crate::utils::error::{Error, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// 静的検査で扱う型（Any は型注釈のない値で、どの型とも互換とする）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Type {
+    Num,
+    Float,
+    Text,
+    Bool,
+    Array,
+    Map,
+    Function,
+    None,
+    Any,
+}
+
+impl Type {
+    /// 型注釈の型名から型を求める
+    pub fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "num" => Some(Type::Num),
+            "float" => Some(Type::Float),
+            "text" => Some(Type::Text),
+            "bool" => Some(Type::Bool),
+            "array" => Some(Type::Array),
+            "map" => Some(Type::Map),
+            "function" => Some(Type::Function),
+            "none" => Some(Type::None),
+            "any" => Some(Type::Any),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Type::Num => "num",
+            Type::Float => "float",
+            Type::Text => "text",
+            Type::Bool => "bool",
+            Type::Array => "array",
+            Type::Map => "map",
+            Type::Function => "function",
+            Type::None => "none",
+            Type::Any => "any",
+        }
+    }
+
+    pub fn of_value(value: &Value) -> Type {
+        Type::from_name(value.type_name()).unwrap_or(Type::Any)
+    }
+
+    /// この型の場所に other 型の値を置けるか（num は float へ暗黙に広げられる）
+    pub fn accepts(self, other: Type) -> bool {
+        self == Type::Any
+            || other == Type::Any
+            || self == other
+            || (self == Type::Float && other == Type::Num)
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// 実行時の値が型注釈に合うかを判定する（インタプリタの実行時検査で使う）
+pub fn value_matches(value: &Value, type_name: &str) -> bool {
+    Type::from_name(type_name).is_some_and(|t| t.accepts(Type::of_value(value)))
+}
+
+/// 二項演算の結果の型（インタプリタの evaluate_expression と同じ規則）
+fn binary_result(left: Type, op: &str, right: Type) -> Option<Type> {
+    let numeric = |t: Type| t == Type::Num || t == Type::Float;
+    match op {
+        // 等価比較はどの型の組み合わせでも使える
+        "==" | "!=" | "is" => return Some(Type::Bool),
+        "and" | "or" => {
+            return (Type::Bool.accepts(left) && Type::Bool.accepts(right)).then_some(Type::Bool);
+        }
+        _ => {}
+    }
+    match (left, right) {
+        (Type::Any, _) | (_, Type::Any) => match op {
+            ">" | "<" => Some(Type::Bool),
+            _ => Some(Type::Any),
+        },
+        (Type::Num, Type::Num) if matches!(op, "+" | "-" | "*" | "/") => Some(Type::Num),
+        (l, r) if numeric(l) && numeric(r) => match op {
+            "+" | "-" | "*" | "/" => Some(Type::Float),
+            ">" | "<" => Some(Type::Bool),
+            _ => None,
+        },
+        (Type::Text, Type::Text) if op == "+" => Some(Type::Text),
+        _ => None,
+    }
+}
+
+/// 2 つの型を合流させる（if の分岐や複数の return の型をまとめる）
+fn join(a: Type, b: Type) -> Type {
+    match (a, b) {
+        _ if a == b => a,
+        (Type::Num, Type::Float) | (Type::Float, Type::Num) => Type::Float,
+        _ => Type::Any,
+    }
+}
+
+// 変数の型：宣言された型（型注釈）は固定、推論された型は代入ごとに変わる
+#[derive(Debug, Clone, Copy)]
+struct Binding {
+    ty: Type,
+    declared: bool,
+}
+
+// 関数の型：引数の型 と 戻り値の型
+struct Signature {
+    params: Vec<Type>,
+    return_type: Type,
+}
+
+// 検査中の関数の戻り値：型注釈 と 観測した return の型
+struct ReturnSlot {
+    expected: Option<Type>,
+    observed: Vec<Type>,
+}
+
+/// 関数の推論結果
+#[derive(Debug, Clone)]
+pub struct FunctionInfo {
+    pub params: Vec<(String, Type)>,
+    pub return_type: Type,
+    // 関数内の変数の（最後に推論された）型
+    pub locals: BTreeMap<String, Type>,
+}
+
+/// 推論結果。エディタのホバー表示などのツールから参照する
+#[derive(Debug, Clone, Default)]
+pub struct TypeInfo {
+    // トップレベル変数の（最後に推論された）型
+    pub variables: BTreeMap<String, Type>,
+    pub functions: BTreeMap<String, FunctionInfo>,
+}
+
+impl TypeInfo {
+    /// 名前に対するホバー表示用の文字列を返す（関数の中の変数は function を指定する）
+    pub fn hover(&self, name: &str, function: Option<&str>) -> Option<String> {
+        if let Some(function) = function.and_then(|f| self.functions.get(f)) {
+            if let Some((_, ty)) = function.params.iter().find(|(param, _)| param == name) {
+                return Some(format!("{}: {}", name, ty));
+            }
+            if let Some(ty) = function.locals.get(name) {
+                return Some(format!("{}: {}", name, ty));
+            }
+        }
+        if let Some(ty) = self.variables.get(name) {
+            return Some(format!("{}: {}", name, ty));
+        }
+        self.functions.get(name).map(|info| {
+            let params: Vec<String> = info.params.iter().map(|(param, ty)| format!("{}: {}", param, ty)).collect();
+            format!("function {}({}) -> {}", name, params.join(", "), info.return_type)
+        })
+    }
+}
+
+impl fmt::Display for TypeInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for name in self.functions.keys() {
+            if let Some(hover) = self.hover(name, None) {
+                writeln!(f, "{}", hover)?;
+            }
+            for (local, ty) in &self.functions[name].locals {
+                writeln!(f, "    {}: {}", local, ty)?;
+            }
+        }
+        for (name, ty) in &self.variables {
+            writeln!(f, "{}: {}", name, ty)?;
+        }
+        Ok(())
+    }
+}
+
+/// 型注釈と推論に基づく静的検査。分からない部分は Any として扱う（漸進的型付け）
+struct TypeChecker {
+    // 変数の型（スコープごと）。if は実行時と同じく新しいスコープを作らない
+    scopes: Vec<HashMap<String, Binding>>,
+    functions: HashMap<String, Signature>,
+    // 検査中の関数の戻り値
+    returns: Vec<ReturnSlot>,
+    info: TypeInfo,
+    errors: Vec<String>,
+}
+
+/// プログラム全体の型を推論する。明らかな誤りがあればまとめて Error::Type として返す
+pub fn infer(stmts: &[ASTNode]) -> Result<TypeInfo> {
+    let mut checker = TypeChecker::new();
+    checker.check_block(stmts);
+    if !checker.errors.is_empty() {
+        return Err(Error::Type(checker.errors.join("\n")));
+    }
+    if let Some(scope) = checker.scopes.first() {
+        checker.info.variables = scope.iter().map(|(name, binding)| (name.clone(), binding.ty)).collect();
+    }
+    Ok(checker.info)
+}
+
+impl TypeChecker {
+    fn new() -> Self {
+        Self {
+            scopes: vec![HashMap::new()],
+            functions: HashMap::new(),
+            returns: Vec::new(),
+            info: TypeInfo::default(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn error(&mut self, message: String) {
+        self.errors.push(message);
+    }
+
+    // 変数の型。関数の中で分かるのはその関数の引数と代入した変数だけで、ほかの変数は
+    // 呼び出し元の変数でありうる（動的スコープ）ので、定義した場所のトップレベルの型は使わない
+    fn lookup(&self, name: &str) -> Option<Type> {
+        self.scopes.last().and_then(|scope| scope.get(name)).map(|binding| binding.ty)
+    }
+
+    fn current_scope(&mut self) -> &mut HashMap<String, Binding> {
+        self.scopes.last_mut().expect("type checker always has a global scope")
+    }
+
+    fn check_block(&mut self, stmts: &[ASTNode]) {
+        for stmt in stmts {
+            self.check_statement(stmt);
+        }
+    }
+
+    fn check_statement(&mut self, stmt: &ASTNode) {
+        match stmt {
+            ASTNode::Variable(name, type_name, expr, _) => {
+                let actual = self.type_of(expr);
+                // 注釈がなければ、同じスコープで宣言済みの型と照合する
+                let declared = match type_name {
+                    Some(type_name) => Type::from_name(type_name),
+                    None => self
+                        .current_scope()
+                        .get(name)
+                        .filter(|binding| binding.declared)
+                        .map(|binding| binding.ty),
+                };
+                let binding = match declared {
+                    Some(declared) => {
+                        if !declared.accepts(actual) {
+                            self.error(format!(
+                                "Cannot assign {} to variable {} of type {}",
+                                actual, name, declared
+                            ));
+                        }
+                        Binding { ty: declared, declared: true }
+                    }
+                    None => Binding { ty: actual, declared: false },
+                };
+                self.current_scope().insert(name.clone(), binding);
+            }
+            ASTNode::If(condition, then_body, else_body) => {
+                // 実行時と同じく、true 以外の条件はどの型でも else 側になるので、条件の型は検査しない
+                self.type_of(condition);
+                // 各分岐を同じ状態から検査し、分岐後の変数の型を合流させる
+                let before = self.current_scope().clone();
+                self.check_block(then_body);
+                let after_then = std::mem::replace(self.current_scope(), before.clone());
+                self.check_block(else_body);
+                let after_else = std::mem::take(self.current_scope());
+                *self.current_scope() = merge_branches(&before, after_then, after_else);
+            }
+            ASTNode::Function(name, def) => self.check_function(name, def),
+            ASTNode::FunctionCall(name, args) => {
+                self.type_of_call(name, args);
+            }
+            ASTNode::MethodCall(object, _, args) => {
+                self.type_of_method_call(object, args);
+            }
+            ASTNode::With(open, body) => {
+                self.type_of(open);
+                self.check_block(body);
+            }
+            ASTNode::Return(expr) => {
+                let actual = self.type_of(expr);
+                let mismatch = match self.returns.last_mut() {
+                    Some(slot) => {
+                        slot.observed.push(actual);
+                        slot.expected.filter(|expected| !expected.accepts(actual))
+                    }
+                    None => None,
+                };
+                if let Some(expected) = mismatch {
+                    self.error(format!("Expected return value of type {}, but got {}", expected, actual));
+                }
+            }
+            ASTNode::Package(_) | ASTNode::Import(_, _) | ASTNode::Msg(_) | ASTNode::Exit => {}
+        }
+    }
+
+    fn check_function(&mut self, name: &str, def: &FunctionDef) {
+        let params: Vec<Type> = def
+            .params
+            .iter()
+            .map(|(_, type_name)| type_name.as_deref().and_then(Type::from_name).unwrap_or(Type::Any))
+            .collect();
+        let expected = def.return_type.as_deref().and_then(Type::from_name);
+        // 再帰呼び出しも検査できるよう、本体より先に登録する（戻り値は推論が終わるまで Any）
+        self.functions.insert(
+            name.to_string(),
+            Signature { params: params.clone(), return_type: expected.unwrap_or(Type::Any) },
+        );
+
+        let scope = def
+            .params
+            .iter()
+            .zip(&params)
+            .map(|((param, _), &ty)| (param.clone(), Binding { ty, declared: true }))
+            .collect();
+        self.scopes.push(scope);
+        self.returns.push(ReturnSlot { expected, observed: Vec::new() });
+        self.check_block(&def.body);
+        let slot = self.returns.pop().expect("return slot pushed above");
+        let scope = self.scopes.pop().unwrap_or_default();
+
+        let return_type = match expected {
+            Some(expected) => {
+                if !matches!(expected, Type::Any | Type::None) && !contains_return(&def.body) {
+                    self.error(format!("Function {} must return a value of type {}", name, expected));
+                }
+                expected
+            }
+            None => {
+                // 最後まで到達しうる場合は None が返る
+                let mut observed = slot.observed;
+                if !always_returns(&def.body) {
+                    observed.push(Type::None);
+                }
+                observed.into_iter().reduce(join).unwrap_or(Type::None)
+            }
+        };
+        if let Some(signature) = self.functions.get_mut(name) {
+            signature.return_type = return_type;
+        }
+
+        let param_names: Vec<&String> = def.params.iter().map(|(param, _)| param).collect();
+        let locals = scope
+            .into_iter()
+            .filter(|(local, _)| !param_names.contains(&local))
+            .map(|(local, binding)| (local, binding.ty))
+            .collect();
+        let params = def.params.iter().map(|(param, _)| param.clone()).zip(params).collect();
+        self.info.functions.insert(name.to_string(), FunctionInfo { params, return_type, locals });
+    }
+
+    /// 式の型を求める。分からない場合は Any
+    pub fn type_of(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Literal(value) => Type::of_value(value),
+            // 変数でなく関数の名前なら、関数への参照になる
+            Expr::Variable(name, _) => self.lookup(name).unwrap_or_else(|| {
+                if self.functions.contains_key(name) {
+                    Type::Function
+                } else {
+                    Type::Any
+                }
+            }),
+            Expr::BinaryOp(left, op, right) => {
+                if op == "==" || op == "is" || op == "!=" {
+                    match (&**left, &**right) {
+                        (value, Expr::TypePattern(_)) | (Expr::TypePattern(_), value) => {
+                            self.type_of(value);
+                            return Type::Bool;
+                        }
+                        _ => {}
+                    }
+                }
+                let left_type = self.type_of(left);
+                let right_type = self.type_of(right);
+                binary_result(left_type, op, right_type).unwrap_or_else(|| {
+                    self.error(format!("Invalid operation: {} {} {}", left_type, op, right_type));
+                    Type::Any
+                })
+            }
+            Expr::UnaryOp(op, operand) => {
+                let operand_type = self.type_of(operand);
+                if op == "not" && !Type::Bool.accepts(operand_type) {
+                    self.error(format!("Invalid operation: {} {}", op, operand_type));
+                }
+                Type::Bool
+            }
+            Expr::Input(prompt, type_name) => {
+                self.type_of(prompt);
+                type_name.as_deref().and_then(Type::from_name).unwrap_or(Type::Text)
+            }
+            Expr::FunctionCall(name, args) => self.type_of_call(name, args),
+            Expr::Member(object, _) => {
+                self.type_of(object);
+                Type::Any
+            }
+            Expr::MethodCall(object, _, args) => self.type_of_method_call(object, args),
+            Expr::TypePattern(type_name) => {
+                self.error(format!("Type pattern :{}: can only be used with ==, != or is", type_name));
+                Type::Any
+            }
+            Expr::Array(items) => {
+                for item in items {
+                    self.type_of(item);
+                }
+                Type::Array
+            }
+            Expr::Map(entries) => {
+                for (_, value) in entries {
+                    self.type_of(value);
+                }
+                Type::Map
+            }
+        }
+    }
+
+    // メソッドやモジュールの関数の型は実行時まで分からない
+    fn type_of_method_call(&mut self, object: &Expr, args: &[Expr]) -> Type {
+        self.type_of(object);
+        for arg in args {
+            self.type_of(arg);
+        }
+        Type::Any
+    }
+
+    fn type_of_call(&mut self, name: &str, args: &[Expr]) -> Type {
+        let arg_types: Vec<Type> = args.iter().map(|arg| self.type_of(arg)).collect();
+        let signature = match self.functions.get(name) {
+            Some(signature) => signature,
+            // 組み込み関数の戻り値の型
+            None => {
+                return match name {
+                    "num" => Type::Num,
+                    "float" => Type::Float,
+                    "bool" => Type::Bool,
+                    "text" | "str" | "repr" | "type" => Type::Text,
+                    "len" => Type::Num,
+                    "gc" => Type::Map,
+                    _ => Type::Any,
+                };
+            }
+        };
+        if signature.params.len() != arg_types.len() {
+            let message = format!(
+                "Function {} expected {} arguments, but got {}",
+                name,
+                signature.params.len(),
+                arg_types.len()
+            );
+            let return_type = signature.return_type;
+            self.error(message);
+            return return_type;
+        }
+        let mismatches: Vec<String> = signature
+            .params
+            .iter()
+            .zip(&arg_types)
+            .enumerate()
+            .filter(|(_, (expected, actual))| !expected.accepts(**actual))
+            .map(|(i, (expected, actual))| {
+                format!("Function {} expected {} for argument {}, but got {}", name, expected, i + 1, actual)
+            })
+            .collect();
+        let return_type = signature.return_type;
+        self.errors.extend(mismatches);
+        return_type
+    }
+}
+
+/// if の両分岐の後の変数の型を合流させる。片方でしか代入されない変数は元の型と合流する
+fn merge_branches(
+    before: &HashMap<String, Binding>,
+    after_then: HashMap<String, Binding>,
+    mut after_else: HashMap<String, Binding>,
+) -> HashMap<String, Binding> {
+    let mut merged = HashMap::new();
+    for (name, then_binding) in after_then {
+        let other = after_else.remove(&name).or_else(|| before.get(&name).copied());
+        let ty = match other {
+            Some(other) => join(then_binding.ty, other.ty),
+            // 片方の分岐でしか定義されない変数は、未定義の可能性がある
+            None => Type::Any,
+        };
+        merged.insert(name, Binding { ty, declared: then_binding.declared });
+    }
+    for (name, else_binding) in after_else {
+        let ty = match before.get(&name) {
+            Some(previous) => join(previous.ty, else_binding.ty),
+            None => Type::Any,
+        };
+        merged.insert(name, Binding { ty, declared: else_binding.declared });
+    }
+    merged
+}
+
+/// 本体（if の中も含む）に return 文があるか
+fn contains_return(stmts: &[ASTNode]) -> bool {
+    stmts.iter().any(|stmt| match stmt {
+        ASTNode::Return(_) => true,
+        ASTNode::If(_, then_body, else_body) => contains_return(then_body) || contains_return(else_body),
+        ASTNode::With(_, body) => contains_return(body),
+        _ => false,
+    })
+}
+
+/// どの経路でも最後に return するか
+fn always_returns(stmts: &[ASTNode]) -> bool {
+    stmts.iter().any(|stmt| match stmt {
+        ASTNode::Return(_) | ASTNode::Exit => true,
+        ASTNode::If(_, then_body, else_body) => always_returns(then_body) && always_returns(else_body),
+        ASTNode::With(_, body) => always_returns(body),
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    // 検査して、エラーがあればそのメッセージを返す
+    fn check(source: &str) -> std::result::Result<TypeInfo, String> {
+        infer(&parser::parse_source(source).unwrap()).map_err(|err| match err {
+            Error::Type(message) => message,
+            other => panic!("expected a type error, got {:?}", other),
+        })
+    }
+
+    fn error(source: &str) -> String {
+        check(source).expect_err("expected a type error")
+    }
+
+    #[test]
+    fn annotated_variables_reject_other_types() {
+        assert_eq!(error("x: num = \"a\"\n"), "Cannot assign text to variable x of type num");
+        // 注釈した型は同じスコープの後の代入にも適用する
+        assert_eq!(error("x: num = 1\nx = true\n"), "Cannot assign bool to variable x of type num");
+        assert_eq!(error("x: float = 1.5\nx = [1]\n"), "Cannot assign array to variable x of type float");
+        // 注釈がなければ別の型を代入できる（漸進的型付け）
+        check("x = 1\nx = \"a\"\n").unwrap();
+    }
+
+    #[test]
+    fn return_values_must_match_the_annotation() {
+        assert_eq!(
+            error("function (name=f, n: num) -> num {\n    return \"x\"\n}\n"),
+            "Expected return value of type num, but got text"
+        );
+        assert_eq!(
+            error("function (name=f, n: num) -> text {\n    msg \"no return\"\n}\n"),
+            "Function f must return a value of type text"
+        );
+        check("function (name=f, n) -> num {\n    return n\n}\n").unwrap();
+    }
+
+    #[test]
+    fn arguments_must_match_the_parameters() {
+        assert_eq!(
+            error("function (name=half, n: num) -> num {\n    return n * 2\n}\nx = half(\"a\")\n"),
+            "Function half expected num for argument 1, but got text"
+        );
+        // 複数の誤りはまとめて報告する
+        let message = error("x: num = \"a\"\ny: text = 1\n");
+        assert_eq!(message.lines().count(), 2, "{}", message);
+    }
+
+    #[test]
+    fn invalid_operations_are_reported() {
+        assert_eq!(error("x = \"a\" - 1\n"), "Invalid operation: text - num");
+        assert_eq!(error("x = [1] * \"b\"\n"), "Invalid operation: array * text");
+        assert_eq!(error("x = not 5\n"), "Invalid operation: not num");
+        // 推論した変数の型も使う
+        assert_eq!(error("n = 1\nt = \"a\"\nx = t + n\n"), "Invalid operation: text + num");
+        // 型が分からない値（注釈のない引数）との演算は実行時に任せる
+        check("function (name=f, a) {\n    return a - 1\n}\n").unwrap();
+    }
+
+    #[test]
+    fn hover_shows_inferred_types() {
+        let source = "function (name=area, w: num, h) {\n    s = w * 2\n    return s\n}\ncount = 3\nratio = count * 1.5\nname = \"box\"\n";
+        let info = check(source).unwrap();
+        assert_eq!(info.hover("count", None).as_deref(), Some("count: num"));
+        assert_eq!(info.hover("ratio", None).as_deref(), Some("ratio: float"));
+        assert_eq!(info.hover("name", None).as_deref(), Some("name: text"));
+        assert_eq!(info.hover("area", None).as_deref(), Some("function area(w: num, h: any) -> num"));
+        // 関数の中では引数とローカル変数を優先し、なければトップレベルを探す
+        assert_eq!(info.hover("w", Some("area")).as_deref(), Some("w: num"));
+        assert_eq!(info.hover("s", Some("area")).as_deref(), Some("s: num"));
+        assert_eq!(info.hover("count", Some("area")).as_deref(), Some("count: num"));
+        assert_eq!(info.hover("s", None), None);
+        assert_eq!(info.hover("missing", Some("area")), None);
+    }
+
+    #[test]
+    fn free_variables_in_functions_are_any() {
+        // 関数の中の n は呼び出し時の n（動的スコープ）なので、定義した時点の型では検査しない
+        check("n = \"a\"\nfunction (name=f) {\n    return n * 2\n}\nn = 3\nx = f()\n").unwrap();
+        let info = check("function (name=f) {\n    y = n\n    return y\n}\n").unwrap();
+        assert_eq!(info.hover("y", Some("f")).as_deref(), Some("y: any"));
+        // 引数と関数の中で代入した変数の型は使う
+        assert_eq!(
+            error("function (name=f, n: num) {\n    t = \"a\"\n    return t - n\n}\n"),
+            "Invalid operation: text - num"
+        );
+    }
+
+    #[test]
+    fn conditions_of_any_type_are_accepted() {
+        // true 以外は else 側（実行時と最適化と同じ）
+        check("if (\"yes\") {\n    x = 1\n}\nif (0) {\n    y = 1\n}\n").unwrap();
+        // 条件の中の式は検査する
+        assert_eq!(error("if (\"a\" - 1) {\n    x = 1\n}\n"), "Invalid operation: text - num");
+    }
+}
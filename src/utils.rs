@@ -2,21 +2,62 @@
 
 pub mod error {
     use std::fmt;
+    use std::time::Duration;
 
     #[derive(Debug)]
     pub enum Error {
         Runtime(String),
         Syntax(String),
         Type(String),
+        Name(String),
+        // 実行の上限を超えた（上限の値を持つ。埋め込み先はこれらを受け取ってスクリプトを打ち切る）
+        StepLimit(u64),
+        DepthLimit(usize),
+        MemoryLimit(usize),
+        Timeout(Duration),
+        // 埋め込み先が許可していない資源（ファイル、環境変数など）を使おうとした
+        Denied(String),
         UnexpectedEOF,
     }
 
+    impl Error {
+        /// 実行の上限を超えたエラーか
+        pub fn is_limit(&self) -> bool {
+            matches!(
+                self,
+                Error::StepLimit(_) | Error::DepthLimit(_) | Error::MemoryLimit(_) | Error::Timeout(_)
+            )
+        }
+    }
+
+    impl Error {
+        /// メッセージの前に文脈（"In module x" など）を付ける。種類は変えない
+        /// （メッセージを持たない UnexpectedEOF は構文エラーとして付ける）
+        pub fn context(self, context: &str) -> Error {
+            match self {
+                Error::Runtime(msg) => Error::Runtime(format!("{}: {}", context, msg)),
+                Error::Syntax(msg) => Error::Syntax(format!("{}: {}", context, msg)),
+                Error::Type(msg) => Error::Type(format!("{}: {}", context, msg)),
+                Error::Name(msg) => Error::Name(format!("{}: {}", context, msg)),
+                Error::Denied(msg) => Error::Denied(format!("{}: {}", context, msg)),
+                Error::UnexpectedEOF => Error::Syntax(format!("{}: Unexpected end of input", context)),
+                limit => limit,
+            }
+        }
+    }
+
     impl fmt::Display for Error {
         fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
             match self {
                 Error::Runtime(msg) => write!(f, "Runtime Error: {}", msg),
                 Error::Syntax(msg) => write!(f, "Syntax Error: {}", msg),
                 Error::Type(msg) => write!(f, "Type Error: {}", msg),
+                Error::Name(msg) => write!(f, "Name Error: {}", msg),
+                Error::StepLimit(limit) => write!(f, "Limit Error: exceeded {} steps", limit),
+                Error::DepthLimit(limit) => write!(f, "Limit Error: exceeded call depth {}", limit),
+                Error::MemoryLimit(limit) => write!(f, "Limit Error: allocated more than {} bytes", limit),
+                Error::Timeout(limit) => write!(f, "Limit Error: ran longer than {} ms", limit.as_millis()),
+                Error::Denied(msg) => write!(f, "Permission Error: {}", msg),
                 Error::UnexpectedEOF => write!(f, "Unexpected end of input"),
             }
         }
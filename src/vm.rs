@@ -0,0 +1,443 @@
+// src/vm.rs
+
+use crate::ast::Value;
+use crate::compiler::{Chunk, FunctionProto, Op, Store};
+use crate::interpreter::{
+    binary_operation, check_type, exit_program, expect_bool, member_of, read_input, take_if_shared, Interpreter,
+    Namespace,
+};
+use crate::ordered_map::OrderedMap;
+use crate::resolver::Layout;
+use crate::stdlib::{self, Capability};
+use crate::utils::error::{Error, Result};
+use std::rc::Rc;
+
+/// 実行中の関数の呼び出し（木構造の評価器と VM で共通）
+#[derive(Debug, Clone)]
+pub struct Frame {
+    // ローカル変数の名前とスロットの索引（関数ごとに共有する）
+    pub layout: Rc<Layout>,
+    // ローカル変数（スロットの順。まだ代入されていない変数は None）
+    pub locals: Vec<Option<Value>>,
+}
+
+impl Interpreter {
+    /// コンパイルしたプログラムを実行する。プログラムのトップレベルの変数をスコープのスロットに
+    /// 割り当て、番号がずれていれば（スコープに先に変数があれば）命令のスロットを付け替える
+    pub fn run_program(&mut self, program: &FunctionProto) -> Result<Value> {
+        let slots: Vec<u32> = program.globals.iter().map(|name| self.scope.slot(name) as u32).collect();
+        if slots.iter().enumerate().all(|(index, &slot)| index as u32 == slot) {
+            return self.execute(&program.chunk);
+        }
+        let chunk = relocate_globals(&program.chunk, &slots);
+        self.execute(&chunk)
+    }
+
+    /// コンパイル済みの関数を呼び出す（AST の関数と同じく引数と戻り値の型注釈を実行時にも検査する）
+    pub fn call_compiled(&mut self, function: Rc<FunctionProto>, args: Vec<Value>) -> Result<Value> {
+        self.push_frame(&function.name, &function.params, &function.locals, args)?;
+        let result = self.execute(&function.chunk);
+        self.pop_frame();
+        check_return(&function, result?)
+    }
+
+    /// 引数を検査して関数のフレームを積む（引数はローカル変数の先頭のスロットに置く）
+    pub fn push_frame(
+        &mut self,
+        name: &str,
+        params: &[(String, Option<String>)],
+        layout: &Rc<Layout>,
+        args: Vec<Value>,
+    ) -> Result<()> {
+        if params.len() != args.len() {
+            return Err(Error::Runtime(format!(
+                "Function {} expected {} arguments, but got {}",
+                name,
+                params.len(),
+                args.len()
+            )));
+        }
+        let mut locals = vec![None; layout.len()];
+        for (slot, ((param, type_name), value)) in params.iter().zip(args).enumerate() {
+            if let Some(type_name) = type_name {
+                check_type(&value, type_name, &format!("argument {} of function {}", param, name))?;
+            }
+            locals[slot] = Some(value);
+        }
+        self.meter.enter()?;
+        self.frames.push(Frame { layout: layout.clone(), locals });
+        Ok(())
+    }
+
+    /// 関数のフレームを取り除く
+    pub fn pop_frame(&mut self) {
+        self.frames.pop();
+        self.meter.leave();
+    }
+
+    /// 実行中の関数（内側の呼び出しから順に）のローカル変数を名前で探す
+    pub fn frame_variable(&self, name: &str) -> Option<Value> {
+        self.frames.iter().rev().find_map(|frame| {
+            let slot = frame.layout.slot(name)?;
+            frame.locals[slot].clone()
+        })
+    }
+
+    /// 実行中の関数のローカル変数。まだ代入されていなければ呼び出し元の変数を名前で探す（動的スコープ）
+    pub fn load_local(&self, slot: u32) -> Result<Value> {
+        let frame = self.frames.last().expect("local variable outside of a function");
+        match &frame.locals[slot as usize] {
+            Some(value) => Ok(value.clone()),
+            None => self.load_variable(&frame.layout[slot as usize]),
+        }
+    }
+
+    /// トップレベルの変数。まだ代入されていなければ関数への参照
+    pub fn load_global(&self, slot: u32) -> Result<Value> {
+        match self.scope.get_slot(slot as usize) {
+            Some(value) => Ok(value.clone()),
+            None => {
+                let name = self.scope.slot_name(slot as usize);
+                self.function_reference(name).ok_or_else(|| Error::Runtime(format!("Undefined variable: {}", name)))
+            }
+        }
+    }
+
+    /// a.b.c の形の名前の並びの値（名前空間のメンバー、またはマップのメンバー）
+    pub fn load_path(&self, path: &[&str]) -> Result<Value> {
+        let Some((member, object)) = path.split_last() else {
+            return Err(Error::Runtime("Empty member path".into()));
+        };
+        if object.is_empty() {
+            return self.load_variable(member);
+        }
+        match self.resolve_namespace_path(object) {
+            Some(namespace) => {
+                let alias = (object.len() == 1).then(|| object[0]);
+                self.namespace_value(namespace, alias, member)
+            }
+            None => member_of(self.load_path(object)?, member),
+        }
+    }
+
+    // 命令列を実行して戻り値を返す。エラーや return で抜けるときも、ブロック内で開いたファイルを閉じ、
+    // 同じループで呼び出していた関数のフレームを取り除く
+    fn execute(&mut self, chunk: &Chunk) -> Result<Value> {
+        let mut withs = Vec::new();
+        let frames = self.frames.len();
+        let result = self.run(chunk, &mut withs);
+        while self.frames.len() > frames {
+            self.pop_frame();
+        }
+        if let Some(&depth) = withs.first() {
+            self.open_files.truncate(depth);
+        }
+        result
+    }
+
+    // コンパイル済みの関数どうしの呼び出しは、呼び出し元の状態を calls に積んで同じループで続けて実行する
+    // （Rust のスタックを使わないので、呼び出しの深さは実行の上限だけで決まる）
+    fn run(&mut self, main: &Chunk, withs: &mut Vec<usize>) -> Result<Value> {
+        let mut calls: Vec<Suspended> = Vec::new();
+        // 実行中の関数（None なら main を実行中）
+        let mut function: Option<Rc<FunctionProto>> = None;
+        let mut stack: Vec<Value> = Vec::new();
+        // BeginInvoke で解決した名前空間（名前空間でなければ None で、値は stack に積む）
+        let mut invocations: Vec<Option<Namespace>> = Vec::new();
+        let mut pc = 0;
+        loop {
+            self.meter.step()?;
+            let chunk = match &function {
+                Some(function) => &function.chunk,
+                None => main,
+            };
+            let op = chunk.code[pc];
+            pc += 1;
+            match op {
+                Op::Constant(index) => stack.push(chunk.constants[index as usize].clone()),
+                Op::LoadLocal(slot) => stack.push(self.load_local(slot)?),
+                Op::StoreLocal(slot) => {
+                    let value = pop(&mut stack);
+                    let frame = self.frames.last_mut().expect("local variable outside of a function");
+                    frame.locals[slot as usize] = Some(value);
+                }
+                Op::LoadGlobal(slot) => stack.push(self.load_global(slot)?),
+                Op::StoreGlobal(slot) => {
+                    let value = pop(&mut stack);
+                    self.scope.set_slot(slot as usize, value);
+                }
+                Op::LoadName(name) => stack.push(self.load_variable(text(chunk, name))?),
+                Op::CheckType { type_name, context } => {
+                    let value = stack.last().expect("value stack underflow");
+                    check_type(value, text(chunk, type_name), text(chunk, context))?;
+                }
+                Op::Binary(op) => {
+                    let right = pop(&mut stack);
+                    let left = pop(&mut stack);
+                    let value = binary_operation(left, op.as_str(), right)?;
+                    self.charge(&value)?;
+                    stack.push(value);
+                }
+                Op::IsType { type_name, negate } => {
+                    let value = pop(&mut stack);
+                    stack.push(Value::Boolean((value.type_name() == text(chunk, type_name)) != negate));
+                }
+                Op::Not => {
+                    let value = pop(&mut stack);
+                    stack.push(Value::Boolean(!expect_bool(value, "not")?));
+                }
+                Op::ShortCircuit { or, target } => {
+                    let left = expect_bool(pop(&mut stack), logical_op(or))?;
+                    if left == or {
+                        stack.push(Value::Boolean(left));
+                        pc = target as usize;
+                    }
+                }
+                Op::TestBool { or } => {
+                    let right = expect_bool(pop(&mut stack), logical_op(or))?;
+                    stack.push(Value::Boolean(right));
+                }
+                Op::Jump(target) => pc = target as usize,
+                Op::JumpUnlessTrue(target) => {
+                    if pop(&mut stack) != Value::Boolean(true) {
+                        pc = target as usize;
+                    }
+                }
+                Op::Pop => {
+                    pop(&mut stack);
+                }
+                Op::Input { type_name } => {
+                    let prompt = pop(&mut stack);
+                    stdlib::require(self, Capability::Stdin)?;
+                    stack.push(read_input(&prompt, type_name.map(|type_name| text(chunk, type_name)))?);
+                }
+                Op::Call { name, argc } => {
+                    let args = pop_args(&mut stack, argc);
+                    let name = text(chunk, name);
+                    match self.compiled_callee(name) {
+                        Some(callee) => {
+                            self.push_frame(&callee.name, &callee.params, &callee.locals, args)?;
+                            calls.push(Suspended {
+                                function: function.replace(callee),
+                                pc,
+                                stack: std::mem::take(&mut stack),
+                                invocations: std::mem::take(&mut invocations),
+                                withs: withs.len(),
+                            });
+                            pc = 0;
+                        }
+                        None => stack.push(self.call_function(name, args)?),
+                    }
+                }
+                Op::BeginInvoke { path } => {
+                    let path = names(chunk, path);
+                    // 名前空間なら受け取る値の位置には none を積んでおく（スタックの増減を一定にする）
+                    match self.resolve_namespace_path(&path) {
+                        Some(namespace) => {
+                            stack.push(Value::None);
+                            invocations.push(Some(namespace));
+                        }
+                        None => {
+                            stack.push(self.load_path(&path)?);
+                            invocations.push(None);
+                        }
+                    }
+                }
+                Op::InvokePath { method, argc, target } => {
+                    let args = pop_args(&mut stack, argc);
+                    let receiver = pop(&mut stack);
+                    let method = text(chunk, method);
+                    match invocations.pop().expect("InvokePath without BeginInvoke") {
+                        Some(namespace) => stack.push(self.call_namespace(namespace, method, args)?),
+                        // 変数に対する xs.push(x) などは、書き換えた値を変数に戻す
+                        None => match target.zip(self.mutator(&receiver, method)) {
+                            Some((target, mutator)) => {
+                                let (mut value, taken) = take_if_shared(self.stored(target), receiver);
+                                let result = self.call_mutator(mutator, &mut value, args);
+                                if result.is_ok() || taken {
+                                    self.store(target, value);
+                                }
+                                stack.push(result?);
+                            }
+                            None => stack.push(self.invoke_method(receiver, method, args)?),
+                        },
+                    }
+                }
+                Op::Invoke { method, argc } => {
+                    let args = pop_args(&mut stack, argc);
+                    let receiver = pop(&mut stack);
+                    stack.push(self.invoke_method(receiver, text(chunk, method), args)?);
+                }
+                Op::LoadPath(path) => stack.push(self.load_path(&names(chunk, path))?),
+                Op::GetMember(member) => {
+                    let value = pop(&mut stack);
+                    stack.push(member_of(value, text(chunk, member))?);
+                }
+                Op::MakeArray(count) => {
+                    let value = Value::array(pop_args(&mut stack, count));
+                    self.charge(&value)?;
+                    stack.push(value);
+                }
+                Op::MakeMap(keys) => {
+                    let keys = names(chunk, keys);
+                    let values = pop_args(&mut stack, keys.len() as u32);
+                    let mut map = OrderedMap::with_capacity(keys.len());
+                    for (key, value) in keys.into_iter().zip(values) {
+                        map.insert(key.to_string(), value);
+                    }
+                    let value = Value::map(map);
+                    self.charge(&value)?;
+                    stack.push(value);
+                }
+                Op::Msg(message) => println!("{}", text(chunk, message)),
+                Op::Package(name) => self.declare_package(text(chunk, name))?,
+                Op::Import { package, target } => {
+                    self.import(text(chunk, package), target.map(|target| text(chunk, target)))?
+                }
+                Op::DefineFunction(index) => {
+                    let function = chunk.functions[index as usize].clone();
+                    self.compiled.insert(function.name.clone(), function);
+                }
+                Op::OpenWith => withs.push(self.open_files.len()),
+                Op::CloseWith => {
+                    let depth = withs.pop().expect("CloseWith without OpenWith");
+                    self.open_files.truncate(depth);
+                }
+                Op::Return => {
+                    let value = pop(&mut stack);
+                    let Some(caller) = calls.pop() else {
+                        return Ok(value);
+                    };
+                    // 呼び出した関数の中のブロックで開いたファイルを閉じる
+                    if let Some(&depth) = withs.get(caller.withs) {
+                        self.open_files.truncate(depth);
+                    }
+                    withs.truncate(caller.withs);
+                    self.pop_frame();
+                    let callee = std::mem::replace(&mut function, caller.function);
+                    let value = check_return(callee.as_ref().expect("return from a call without a function"), value)?;
+                    pc = caller.pc;
+                    stack = caller.stack;
+                    invocations = caller.invocations;
+                    stack.push(value);
+                }
+                Op::Exit => {
+                    stdlib::require(self, Capability::Process)?;
+                    exit_program()
+                }
+            }
+        }
+    }
+
+    // call_function がコンパイル済みの関数を呼び出す名前なら、その関数（組み込み関数を優先する）
+    fn compiled_callee(&self, name: &str) -> Option<Rc<FunctionProto>> {
+        if self.natives.contains_key(name) {
+            return None;
+        }
+        self.compiled.get(name).cloned()
+    }
+
+    // InvokePath の書き戻し先の値の置き場所
+    fn stored(&mut self, target: Store) -> &mut Option<Value> {
+        match target {
+            Store::Local(slot) => {
+                let frame = self.frames.last_mut().expect("local variable outside of a function");
+                &mut frame.locals[slot as usize]
+            }
+            Store::Global(slot) => self.scope.slot_mut(slot as usize),
+        }
+    }
+
+    // InvokePath の書き戻し先に値を書き込む
+    fn store(&mut self, target: Store, value: Value) {
+        match target {
+            Store::Local(slot) => {
+                let frame = self.frames.last_mut().expect("local variable outside of a function");
+                frame.locals[slot as usize] = Some(value);
+            }
+            Store::Global(slot) => self.scope.set_slot(slot as usize, value),
+        }
+    }
+}
+
+// 呼び出し元の実行状態（呼び出した関数から戻ったら続きを実行する）
+struct Suspended {
+    function: Option<Rc<FunctionProto>>,
+    pc: usize,
+    stack: Vec<Value>,
+    invocations: Vec<Option<Namespace>>,
+    // 呼び出したときに開いていたブロックの数
+    withs: usize,
+}
+
+// 戻り値の型注釈を検査する
+fn check_return(function: &FunctionProto, value: Value) -> Result<Value> {
+    if let Some(type_name) = &function.return_type {
+        check_type(&value, type_name, &format!("return value of function {}", function.name))?;
+    }
+    Ok(value)
+}
+
+// トップレベルの変数のスロットを付け替えた命令列（入れ子の関数も付け替える）
+fn relocate_globals(chunk: &Chunk, slots: &[u32]) -> Chunk {
+    let code = chunk
+        .code
+        .iter()
+        .map(|&op| match op {
+            Op::LoadGlobal(slot) => Op::LoadGlobal(slots[slot as usize]),
+            Op::StoreGlobal(slot) => Op::StoreGlobal(slots[slot as usize]),
+            Op::InvokePath { method, argc, target: Some(Store::Global(slot)) } => {
+                Op::InvokePath { method, argc, target: Some(Store::Global(slots[slot as usize])) }
+            }
+            other => other,
+        })
+        .collect();
+    let functions = chunk
+        .functions
+        .iter()
+        .map(|function| {
+            Rc::new(FunctionProto { chunk: relocate_globals(&function.chunk, slots), ..(**function).clone() })
+        })
+        .collect();
+    Chunk { code, constants: chunk.constants.clone(), functions }
+}
+
+fn logical_op(or: bool) -> &'static str {
+    if or {
+        "or"
+    } else {
+        "and"
+    }
+}
+
+fn pop(stack: &mut Vec<Value>) -> Value {
+    stack.pop().expect("value stack underflow")
+}
+
+// 先頭の count 個の値を積んだ順に取り出す
+fn pop_args(stack: &mut Vec<Value>, count: u32) -> Vec<Value> {
+    let start = stack.len() - count as usize;
+    stack.split_off(start)
+}
+
+// テキストの定数（名前）
+fn text(chunk: &Chunk, index: u32) -> &str {
+    match &chunk.constants[index as usize] {
+        Value::Text(text) => text,
+        other => panic!("expected a name constant, but got {:?}", other),
+    }
+}
+
+// テキストの配列の定数（a.b.c の名前の並びやマップのキー）
+fn names(chunk: &Chunk, index: u32) -> Vec<&str> {
+    match &chunk.constants[index as usize] {
+        Value::Array(items) => items
+            .iter()
+            .map(|item| match item {
+                Value::Text(text) => &**text,
+                other => panic!("expected a name constant, but got {:?}", other),
+            })
+            .collect(),
+        other => panic!("expected a path constant, but got {:?}", other),
+    }
+}
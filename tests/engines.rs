@@ -0,0 +1,93 @@
+// tests/engines.rs
+//
+// 木構造の評価器（--engine tree）とバイトコード VM（--engine vm）の差分テスト。
+// tests/scripts の各スクリプトを両方の方式で実行し、標準出力、エラー、終了コードと
+// トップレベルの変数（各スクリプトが最後に gc() の結果を stats に入れる）が一致することを確かめる。
+// 実行方式が一致するだけでなく、次の期待する結果とも比べる
+// - <名前>.err：エラー出力。このファイルがないスクリプトはエラーを出してはいけない
+// - <名前>.out：スクリプトが os.args() の最初の引数のファイルに書き出す結果（標準モジュールの動作の確認）
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn scripts() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scripts");
+    let mut scripts: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .expect("tests/scripts is missing")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "el"))
+        .collect();
+    scripts.sort();
+    assert!(!scripts.is_empty(), "no scripts in {}", dir.display());
+    scripts
+}
+
+// 乱数のシードを固定し、バイトコードのキャッシュは使わない（キャッシュの有無で結果が変わらないように）。
+// スクリプトには結果を書き出すファイルを引数で渡す
+fn eliumw(args: &[&str], script: &Path, output: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_eliumw"))
+        .args(["--no-cache", "--seed", "0"])
+        .args(args)
+        .arg(script)
+        .arg(output)
+        .current_dir(script.parent().unwrap())
+        .output()
+        .expect("failed to run eliumw")
+}
+
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+// スクリプトが結果を書き出すファイル（実行方式ごとに別にする）
+fn output_path(script: &Path, engine: &str) -> PathBuf {
+    let name = script.file_stem().unwrap().to_string_lossy();
+    std::env::temp_dir().join(format!("elium-engines-{}-{}-{}.out", std::process::id(), name, engine))
+}
+
+// 期待する結果のファイル（なければ None）
+fn expected(script: &Path, extension: &str) -> Option<String> {
+    std::fs::read_to_string(script.with_extension(extension)).ok()
+}
+
+#[test]
+fn engines_print_the_same_output_and_errors() {
+    for script in scripts() {
+        let name = script.file_name().unwrap().to_string_lossy().into_owned();
+        let run = |engine: &str| {
+            let output = output_path(&script, engine);
+            let _ = std::fs::remove_file(&output);
+            let result = eliumw(&["--engine", engine], &script, &output);
+            let written = std::fs::read_to_string(&output).ok();
+            let _ = std::fs::remove_file(&output);
+            (result, written)
+        };
+        let (tree, tree_written) = run("tree");
+        let (vm, vm_written) = run("vm");
+        assert_eq!(text(&tree.stdout), text(&vm.stdout), "stdout of {} differs", name);
+        assert_eq!(text(&tree.stderr), text(&vm.stderr), "stderr of {} differs", name);
+        assert_eq!(tree.status.code(), vm.status.code(), "exit status of {} differs", name);
+        assert_eq!(tree_written, vm_written, "output of {} differs", name);
+
+        let errors = expected(&script, "err").unwrap_or_default();
+        assert_eq!(text(&tree.stderr), errors, "unexpected errors from {}", name);
+        assert_eq!(tree_written, expected(&script, "out"), "output of {} does not match {}.out", name, name);
+    }
+}
+
+#[test]
+fn engines_leave_the_same_variables_and_heap() {
+    for script in scripts() {
+        let output = output_path(&script, "compare");
+        let result = eliumw(&["--compare"], &script, &output);
+        let _ = std::fs::remove_file(&output);
+        let stdout = text(&result.stdout);
+        assert!(
+            stdout.trim_end().ends_with("== engines agree =="),
+            "engines differ on {}:\n{}{}",
+            script.display(),
+            stdout,
+            text(&result.stderr)
+        );
+    }
+}
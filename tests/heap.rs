@@ -0,0 +1,192 @@
+// tests/heap.rs
+//
+// 参照の循環で解放されない値がないことの確認。確保中のバイト数を数えるアロケータを使うので、
+// ほかのテストとは別のテストクレートにする（アロケータはこのクレートのテストだけに効く）。
+// 自分自身を含む配列やマップ、配列やマップに入れた関数、読み込んだモジュールを作っては捨てて、
+// インタプリタを捨てたあとに残るバイト数が繰り返しの回数で増えないことを確かめる
+
+use elium::ast::Value;
+use elium::interpreter::{Engine, Interpreter};
+use elium::utils::error::Result;
+use elium::{limits, optimizer, parser, resolver};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
+
+// スレッドごとに確保中のバイト数を数えるアロケータ（テストは並行して別のスレッドで動く）
+struct Counting;
+
+thread_local! {
+    static LIVE: Cell<isize> = const { Cell::new(0) };
+}
+
+fn count(delta: isize) {
+    let _ = LIVE.try_with(|live| live.set(live.get() + delta));
+}
+
+fn live() -> isize {
+    LIVE.with(Cell::get)
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let pointer = System.alloc(layout);
+        if !pointer.is_null() {
+            count(layout.size() as isize);
+        }
+        pointer
+    }
+
+    unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+        System.dealloc(pointer, layout);
+        count(-(layout.size() as isize));
+    }
+
+    unsafe fn realloc(&self, pointer: *mut u8, layout: Layout, size: usize) -> *mut u8 {
+        let moved = System.realloc(pointer, layout, size);
+        if !moved.is_null() {
+            count(size as isize - layout.size() as isize);
+        }
+        moved
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+const ENGINES: [Engine; 2] = [Engine::Tree, Engine::Vm];
+
+// eliumw と同じく名前を検査して最適化してから実行する。modules はモジュールを探すディレクトリ
+fn run(engine: Engine, source: &str, modules: Option<&Path>) -> (Interpreter, Result<()>) {
+    let mut interpreter = Interpreter::new();
+    interpreter.engine = engine;
+    interpreter.seed(0);
+    {
+        let mut loader = interpreter.loader.borrow_mut();
+        loader.cache_bytecode = false;
+        if let Some(dir) = modules {
+            loader.search_paths = vec![dir.to_path_buf()];
+        }
+    }
+    let result = parser::parse_source(source)
+        .and_then(|stmts| resolver::check(&stmts).map(|_| optimizer::optimize(stmts, false)))
+        .and_then(|stmts| interpreter.interpret(stmts));
+    (interpreter, result)
+}
+
+// runs 回実行してはインタプリタを捨てたあとに、増えたまま残るバイト数（大きなスタックのスレッドで実行する）。
+// 共有名の表などはスレッドごとに最初の実行で育つので、最初の 1 回は数えない
+fn leaked(engine: Engine, source: String, modules: Option<PathBuf>, runs: usize) -> isize {
+    limits::run_with_stack(move || {
+        let run_once = || {
+            let (interpreter, result) = run(engine, &source, modules.as_deref());
+            result.unwrap();
+            drop(interpreter);
+        };
+        run_once();
+        let start = live();
+        for _ in 0..runs {
+            run_once();
+        }
+        live() - start
+    })
+}
+
+// 自分自身を加えた配列とマップを作っては捨てる（bench/cycles.el と同じ）
+fn churn(rounds: usize) -> String {
+    format!(
+        r#"function (name=churn, n) {{
+    if (n < 1) {{
+        return 0
+    }}
+    xs = [n, "node"]
+    xs.push(xs)
+    m = {{id: n, items: xs}}
+    m.insert("self", m)
+    return len(xs) + len(m) + churn(n - 1)
+}}
+function (name=rounds, n) {{
+    if (n < 1) {{
+        return gc()
+    }}
+    total = churn(100)
+    return rounds(n - 1)
+}}
+stats = [rounds(5), rounds({})]
+"#,
+        rounds
+    )
+}
+
+#[test]
+fn heap_stats_stay_bounded_in_a_loop() {
+    for engine in ENGINES {
+        let source = churn(50);
+        let (reachable, before, after) = limits::run_with_stack(move || {
+            let (interpreter, result) = run(engine, &source, None);
+            result.unwrap();
+            let Some(Value::Array(stats)) = interpreter.scope.get("stats") else {
+                panic!("stats is missing");
+            };
+            (interpreter.heap_stats().objects(), stats[0].repr(), stats[1].repr())
+        });
+        assert_eq!(before, after, "{:?}: the heap grew between 5 and 50 rounds", engine);
+        // 残るのは stats の配列と 2 つの統計のマップだけ
+        assert_eq!(reachable, 3, "{:?}: unexpected objects are still reachable", engine);
+    }
+}
+
+#[test]
+fn self_references_are_freed() {
+    for engine in ENGINES {
+        let leaked = leaked(engine, churn(20), None, 5);
+        assert!(leaked.abs() < 1024, "{:?}: {} bytes leaked by 5 runs", engine, leaked);
+    }
+}
+
+// 関数への参照を入れた配列とマップを、モジュールとの間でやりとりする
+const FUNCTIONS: &str = r#"import registry
+import user
+function (name=double, x) {
+    return x * 2
+}
+own = [double, len, registry.twice]
+table = {local: double, remote: registry.twice, list: own}
+registry.keep(table)
+remote = table.remote
+total = remote(5) + user.run(3)
+"#;
+
+const REGISTRY: &str = r#"function (name=twice, x) {
+    return x + x
+}
+kept = [twice]
+function (name=keep, value) {
+    kept.push(value)
+    return len(kept)
+}
+function (name=apply, x) {
+    f = kept.get(0)
+    return f(x)
+}
+"#;
+
+// registry を別のモジュールからも読み込む（ローダーのキャッシュを共有する）
+const USER: &str = r#"import registry
+function (name=run, x) {
+    return registry.apply(x) + registry.twice(x)
+}
+"#;
+
+#[test]
+fn functions_and_modules_do_not_form_cycles() {
+    let dir = std::env::temp_dir().join(format!("elium-heap-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("registry.el"), REGISTRY).unwrap();
+    std::fs::write(dir.join("user.el"), USER).unwrap();
+    for engine in ENGINES {
+        let leaked = leaked(engine, FUNCTIONS.to_string(), Some(dir.clone()), 10);
+        assert!(leaked.abs() < 1024, "{:?}: {} bytes leaked by 10 runs", engine, leaked);
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
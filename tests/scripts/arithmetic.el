@@ -0,0 +1,28 @@
+import math
+function (name=double, x) {
+    return x * 2
+}
+function (name=sq, x) {
+    return x * x
+}
+function (name=early, x) {
+    return x + 1
+    msg "never"
+}
+a = double(3) + 1
+b = sq(a)
+c = 2 * 3 + 4
+d = math.div(7, 2)
+e = 0 - math.div(7, 2)
+f = math.sqrt(2.25) * 2
+g = math.mod(0 - 7, 3)
+h = math.div(0 - 7, 3)
+i = math.pow(2, 10)
+j = 1.5 + 2
+k = 10 > 3 and 2 > 1 or false
+l = not 1 == 1
+big = 9223372036854775807
+z = early(b)
+t = 5 is :num:
+msg "arithmetic done"
+stats = gc()
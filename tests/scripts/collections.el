@@ -0,0 +1,73 @@
+import file
+import os
+import text
+function (name=double, x) {
+    return x * 2
+}
+function (name=even, x) {
+    return x > 2
+}
+function (name=add, a, b) {
+    return a + b
+}
+function (name=desc, a, b) {
+    return a > b
+}
+log = ""
+xs = [3, 1, 2]
+xs.push(5)
+last = xs.pop()
+xs.insert(0, 9)
+removed = xs.remove(1)
+ys = xs.map(double)
+evens = [1, 2, 3, 4].filter(even)
+total = xs.reduce(add, 0)
+sorted = xs.sort()
+rsorted = xs.sort(desc)
+has = xs.contains(2)
+idx = xs.index_of(2)
+sl = [1, 2, 3, 4].slice(1, 3)
+cat = xs.concat([7, 8])
+rev = xs.reverse()
+anyb = xs.any(even)
+allb = xs.all(even)
+f = double
+viaf = f(21)
+up = ["a", "b"].map(text.upper)
+m = {name: "elium", "ver sion": 1}
+m.insert("z", true)
+gone = m.remove("name")
+keys = m.keys()
+vals = m.values()
+ents = m.entries()
+hk = m.has_key("z")
+mg = m.merge({z: false, q: 1}).keys()
+g = m.get("nope", 0)
+e = xs.get(0 - 1)
+log = log + "xs=[" + text(xs) + "]\n"
+log = log + "last=[" + text(last) + "]\n"
+log = log + "removed=[" + text(removed) + "]\n"
+log = log + "ys=[" + text(ys) + "]\n"
+log = log + "evens=[" + text(evens) + "]\n"
+log = log + "total=[" + text(total) + "]\n"
+log = log + "sorted=[" + text(sorted) + "]\n"
+log = log + "rsorted=[" + text(rsorted) + "]\n"
+log = log + "has=[" + text(has) + "]\n"
+log = log + "idx=[" + text(idx) + "]\n"
+log = log + "sl=[" + text(sl) + "]\n"
+log = log + "cat=[" + text(cat) + "]\n"
+log = log + "rev=[" + text(rev) + "]\n"
+log = log + "anyb=[" + text(anyb) + "]\n"
+log = log + "allb=[" + text(allb) + "]\n"
+log = log + "viaf=[" + text(viaf) + "]\n"
+log = log + "up=[" + text(up) + "]\n"
+log = log + "keys=[" + text(keys) + "]\n"
+log = log + "vals=[" + text(vals) + "]\n"
+log = log + "ents=[" + text(ents) + "]\n"
+log = log + "hk=[" + text(hk) + "]\n"
+log = log + "mg=[" + text(mg) + "]\n"
+log = log + "g=[" + text(g) + "]\n"
+log = log + "e=[" + text(e) + "]\n"
+log = log + "gone=[" + text(gone) + "]\n"
+stats = gc()
+file.write(os.args().get(0), log)
@@ -0,0 +1,11 @@
+function (name=div, a, b) {
+    return math.div(a, b)
+}
+import math
+xs = [1, 2, 3]
+ok = div(7, 2)
+big = 9223372036854775807
+stats = gc()
+msg "before the error"
+bad = big + ok
+msg "never"
@@ -0,0 +1,8 @@
+import shapes
+a = shapes.area(2, 3)
+h = shapes.hyp(3, 4)
+u = shapes.unit
+c = shapes.corners
+c.push([1, 1])
+again = shapes.corners
+stats = gc()
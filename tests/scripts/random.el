@@ -0,0 +1,20 @@
+import math
+import random
+random.seed(7)
+function (name=collect, n, acc) {
+    if (n == 0) {
+        return acc
+    }
+    if (math.mod(n, 3) == 0) {
+        acc.push(n * n)
+    } else {
+        acc.push(random.int(1, 100))
+    }
+    return collect(n - 1, acc)
+}
+xs = collect(50, [])
+f = random.float()
+pick = random.choice(xs)
+shuffled = random.shuffle([1, 2, 3, 4, 5])
+sample = random.sample(xs, 3)
+stats = gc()
@@ -0,0 +1,35 @@
+function (name=fib, n: num) -> num {
+    if (n < 2) {
+        return n
+    }
+    return fib(n - 1) + fib(n - 2)
+}
+function (name=tak, x, y, z) {
+    if (y < x) {
+        return tak(tak(x - 1, y, z), tak(y - 1, z, x), tak(z - 1, x, y))
+    }
+    return z
+}
+function (name=count, n) {
+    if (n == 0) {
+        return 0
+    }
+    return 1 + count(n - 1)
+}
+function (name=even, n) {
+    if (n == 0) {
+        return true
+    }
+    return odd(n - 1)
+}
+function (name=odd, n) {
+    if (n == 0) {
+        return false
+    }
+    return even(n - 1)
+}
+f = fib(18)
+t = tak(12, 8, 4)
+deep = count(2000)
+e = even(101)
+stats = gc()
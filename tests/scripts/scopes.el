@@ -0,0 +1,18 @@
+function (name=g) {
+    return n * 2
+}
+function (name=h, n) {
+    xs = [n]
+    return g() + len(xs)
+}
+n = 5
+xs = [1]
+xs.push(2)
+if (n > 3) {
+    m = 1
+}
+all = str([h(10), g(), xs, m, g])
+function (name=f) {
+    return 0
+}
+stats = gc()
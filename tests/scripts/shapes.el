@@ -0,0 +1,9 @@
+import math
+unit = 10
+function (name=area, w, h) {
+    return w * h * unit
+}
+function (name=hyp, a, b) {
+    return math.sqrt(a * a + b * b)
+}
+corners = [[0, 0], [1, 0]]
@@ -0,0 +1,19 @@
+xs = [1, 2]
+ys = xs
+ys.push(3)
+m = {a: xs}
+xs.push(9)
+function (name=f, arr) {
+    arr.push(100)
+    xs.push(7)
+    return [arr, xs]
+}
+r = f(xs)
+zs = [1, 2, 3]
+zs.push(zs.pop())
+ws = [5, 6]
+ws.push(ws.pop() + len(ws))
+mm = {k: 1}
+mm.insert("j", mm)
+all = str([xs, ys, m, r, zs, ws, mm])
+stats = gc()
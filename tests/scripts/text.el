@@ -0,0 +1,26 @@
+import file
+import json
+import os
+import text
+s = "  Hello, Elium  "
+trimmed = text.trim(s)
+words = text.split(trimmed, ", ")
+joined = text.join(words, "-")
+upper = text.upper(joined)
+found = text.find(joined, "Elium")
+replaced = text.replace(joined, "Hello", "Bye")
+padded = text.pad_start("7", 3, "0") + text.pad_end("x", 3, ".")
+chars = text.chars("abc")
+code = text.code("A")
+back = text.from_code(66)
+fmt = text.format(3.14159, 8, 2)
+fmt_text = text.format("ab", 4)
+rep = text.repeat("ab", 3)
+v = json.parse("{\"b\": [1, 2.5, true, null, \"q\\\"x\"], \"a key\": {\"if\": 1, \"z_1\": \"t\\n\"}}")
+compact = json.stringify(v)
+pretty = json.stringify(v, 2)
+round = json.parse(compact) == v
+shown = str(v) + repr("hi\t") + str("hi") + text([["a"]])
+stats = gc()
+results = [trimmed, words, joined, upper, found, replaced, padded, chars, code, back, fmt, fmt_text, rep, compact, pretty, round, shown]
+file.write(os.args().get(0), text.join(results.map(repr), "\n") + "\n")
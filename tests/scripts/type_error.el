@@ -0,0 +1,7 @@
+import json
+function (name=half, n: num) -> num {
+    return n * 2
+}
+a = half(2)
+v = json.parse("[\"x\"]")
+b = half(v.get(0))